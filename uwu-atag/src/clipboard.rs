@@ -0,0 +1,111 @@
+use colored::Colorize;
+use log::{debug, info};
+
+/// hidden subcommand the background clipboard holder runs as
+#[cfg(target_os = "linux")]
+const HOLD_COMMAND: &str = "hold-clipboard";
+
+/// what the holder prints once the value is on the clipboard
+#[cfg(target_os = "linux")]
+const HOLDER_READY: &str = "ready";
+
+pub fn copy_text_to_clipboard(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // x11 and wayland clipboards are served by whoever set them, so exiting
+    // right away would take the value with us. a copy of ourselves holds it
+    // in the background instead, until something else is copied
+    #[cfg(target_os = "linux")]
+    spawn_holder(text)?;
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        debug!("initializing arboard clipboard");
+        arboard::Clipboard::new()?.set_text(text)?;
+    }
+
+    info!("copied {} bytes to clipboard", text.len());
+    println!(
+        "{} {}",
+        "「copied」".green().bold(),
+        "value is on your clipboard".cyan()
+    );
+
+    Ok(())
+}
+
+/// start the holder and hand it the value over stdin
+///
+/// returns once the holder says it owns the clipboard, the holder
+/// keeps running after we exit
+#[cfg(target_os = "linux")]
+fn spawn_holder(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command, Stdio};
+
+    let mut holder = Command::new(std::env::current_exe()?)
+        .arg(HOLD_COMMAND)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // stdin is closed at the end of this statement, which is how the
+    // holder knows it has the whole value
+    holder
+        .stdin
+        .take()
+        .ok_or("clipboard holder has no stdin")?
+        .write_all(text.as_bytes())?;
+
+    let stdout = holder
+        .stdout
+        .take()
+        .ok_or("clipboard holder has no stdout")?;
+    let mut reply = String::new();
+    BufReader::new(stdout).read_line(&mut reply)?;
+
+    match reply.trim_end() {
+        HOLDER_READY => {
+            debug!("clipboard holder running as pid {}", holder.id());
+            Ok(())
+        }
+        "" => Err("clipboard holder exited without taking the clipboard".into()),
+        error => Err(format!("clipboard holder failed: {}", error).into()),
+    }
+}
+
+/// body of the hold-clipboard subcommand
+///
+/// reads the value from stdin, puts it on the clipboard, reports back
+/// on stdout and then serves it until something else is copied
+#[cfg(target_os = "linux")]
+pub fn hold_clipboard() -> Result<(), Box<dyn std::error::Error>> {
+    use arboard::{Clipboard, SetExtLinux};
+    use std::io::{Read, Write};
+
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+
+    let mut stdout = std::io::stdout();
+    let claimed = Clipboard::new().and_then(|mut clipboard| {
+        clipboard.set_text(text.as_str())?;
+        Ok(clipboard)
+    });
+    let mut clipboard = match claimed {
+        Ok(clipboard) => clipboard,
+        Err(e) => {
+            writeln!(stdout, "{}", e)?;
+            return Err(e.into());
+        }
+    };
+    writeln!(stdout, "{}", HOLDER_READY)?;
+    stdout.flush()?;
+
+    clipboard.set().wait().text(text.as_str())?;
+    info!("clipboard was replaced, served {} bytes", text.len());
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn hold_clipboard() -> Result<(), Box<dyn std::error::Error>> {
+    Err("the clipboard holder is only used on linux".into())
+}
@@ -1,15 +1,49 @@
+mod clipboard;
 mod tag_reader;
+mod tag_writer;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use log::info;
 use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "uwu-atag")]
 #[command(about = "🌸 「simple and cute audio tag dumper」 🌸")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    #[arg(short = 'i', long, value_name = "FILE")]
-    input: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short = 'i', long, value_name = "FILE", required = true)]
+    input: Option<PathBuf>,
+
+    // only used when the input is a directory
+    #[arg(long, value_enum, value_name = "FIELD")]
+    group_by: Option<tag_reader::GroupBy>,
+
+    // copy one field's value (e.g. TrackTitle) to the clipboard
+    #[arg(long, value_name = "KEY")]
+    copy: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// write tags into the file's primary tag
+    Set {
+        #[arg(short = 'i', long, value_name = "FILE")]
+        input: PathBuf,
+
+        // title, artist, album, albumartist, track, tracktotal, disc, year, genre or comment
+        #[arg(long = "tag", value_name = "KEY=VALUE", required = true)]
+        tags: Vec<String>,
+
+        // show what would change without writing the file
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// serve a --copy value in the background, started by --copy itself
+    #[command(hide = true)]
+    HoldClipboard,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -20,8 +54,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let cli = Cli::parse();
 
-    info!("reading tags from file: {}", cli.input.display());
-    tag_reader::read_and_display_tags(&cli.input)?;
+    match cli.command {
+        Some(Command::Set {
+            input,
+            tags,
+            dry_run,
+        }) => {
+            info!("writing tags to file: {}", input.display());
+            return tag_writer::write_tags(&input, &tags, dry_run);
+        }
+        Some(Command::HoldClipboard) => return clipboard::hold_clipboard(),
+        None => {}
+    }
+
+    let input = cli.input.ok_or("--input is required")?;
+
+    if input.is_dir() {
+        if cli.copy.is_some() {
+            return Err("--copy needs a single file, not a directory".into());
+        }
+        info!("reading tags from directory: {}", input.display());
+        tag_reader::read_and_display_directory(&input, cli.group_by)?;
+    } else {
+        info!("reading tags from file: {}", input.display());
+        tag_reader::read_and_display_tags(&input)?;
+
+        if let Some(key_name) = cli.copy {
+            let value = tag_reader::find_tag_value(&input, &key_name)?;
+            clipboard::copy_text_to_clipboard(&value)?;
+        }
+    }
 
     Ok(())
 }
@@ -1,7 +1,20 @@
+use clap::ValueEnum;
 use colored::Colorize;
+use lofty::file::TaggedFile;
 use lofty::prelude::*;
+use lofty::tag::Tag;
 use log::{debug, error, warn};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// tag field used to section directory output
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum GroupBy {
+    Artist,
+    Album,
+    Year,
+}
 
 pub fn read_and_display_tags(path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
     debug!("loading tags from file: {}", path.display());
@@ -11,22 +24,159 @@ pub fn read_and_display_tags(path: &PathBuf) -> Result<(), Box<dyn std::error::E
         e
     })?;
 
-    let tag = match tagged_file.primary_tag() {
+    display_tags(&tagged_file);
+
+    Ok(())
+}
+
+/// look up a single tag value by its key name
+///
+/// the name is matched case-insensitively against the key names
+/// shown in the normal dump (e.g. TrackTitle, TrackArtist)
+pub fn find_tag_value(
+    path: &PathBuf,
+    key_name: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let tagged_file = lofty::read_from_path(path)?;
+    let tag = primary_or_first_tag(&tagged_file).ok_or("no tags found in file")?;
+
+    let item = tag
+        .items()
+        .find(|item| format!("{:?}", item.key()).eq_ignore_ascii_case(key_name))
+        .ok_or_else(|| format!("tag field {} not found in file", key_name))?;
+
+    let value = item
+        .value()
+        .text()
+        .ok_or_else(|| format!("tag field {} is not text", key_name))?;
+
+    Ok(value.to_string())
+}
+
+/// dump tags for every audio file in a directory
+///
+/// files lofty can't read are skipped, when grouping is on anything
+/// missing the field ends up under "Unknown"
+pub fn read_and_display_directory(
+    dir: &PathBuf,
+    group_by: Option<GroupBy>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    debug!("loading tags from directory: {}", dir.display());
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut files = Vec::new();
+    for path in paths {
+        match lofty::read_from_path(&path) {
+            Ok(tagged_file) => files.push((path, tagged_file)),
+            Err(e) => debug!("skipping {}: {}", path.display(), e),
+        }
+    }
+
+    if files.is_empty() {
+        warn!("no audio files found in {}", dir.display());
+        return Ok(());
+    }
+
+    let Some(group_by) = group_by else {
+        for (path, tagged_file) in &files {
+            print_file_header(path);
+            display_tags(tagged_file);
+            println!();
+        }
+        return Ok(());
+    };
+
+    let mut groups: BTreeMap<String, Vec<(PathBuf, TaggedFile)>> = BTreeMap::new();
+    for (path, tagged_file) in files {
+        let key = group_key(&tagged_file, group_by).unwrap_or_else(|| "Unknown".to_string());
+        groups.entry(key).or_default().push((path, tagged_file));
+    }
+
+    for (key, members) in &groups {
+        println!(
+            "{} {} {}",
+            "「group」".magenta().bold(),
+            key.yellow(),
+            format!("({} file(s))", members.len()).cyan()
+        );
+        println!();
+
+        for (path, tagged_file) in members {
+            print_file_header(path);
+            display_tags(tagged_file);
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+fn primary_or_first_tag(tagged_file: &TaggedFile) -> Option<&Tag> {
+    tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+}
+
+fn group_key(tagged_file: &TaggedFile, group_by: GroupBy) -> Option<String> {
+    let tag = primary_or_first_tag(tagged_file)?;
+    let value = match group_by {
+        GroupBy::Artist => tag.artist().map(|a| a.to_string()),
+        GroupBy::Album => tag.album().map(|a| a.to_string()),
+        GroupBy::Year => tag.year().map(|y| y.to_string()),
+    }?;
+
+    let value = value.trim().to_string();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+fn print_file_header(path: &Path) {
+    println!(
+        "{} {}",
+        "「file」".cyan().bold(),
+        path.display().to_string().green()
+    );
+}
+
+/// list which tag formats are present, with how many items each has
+fn display_tag_formats(tagged_file: &TaggedFile) {
+    let tags = tagged_file.tags();
+    if tags.is_empty() {
+        return;
+    }
+
+    let formats: Vec<String> = tags
+        .iter()
+        .map(|tag| format!("{:?} ({} items)", tag.tag_type(), tag.item_count()))
+        .collect();
+
+    println!(
+        "{} {}",
+        "「tag formats」".magenta().bold(),
+        formats.join(", ").cyan()
+    );
+}
+
+fn display_tags(tagged_file: &TaggedFile) {
+    display_tag_formats(tagged_file);
+
+    let tag = match primary_or_first_tag(tagged_file) {
         Some(t) => t,
-        None => match tagged_file.first_tag() {
-            Some(t) => t,
-            None => {
-                warn!("no tags found in file");
-                return Ok(());
-            }
-        },
+        None => {
+            warn!("no tags found in file");
+            return;
+        }
     };
 
     let items: Vec<_> = tag.items().collect();
 
     if items.is_empty() {
         warn!("no tag items found in file");
-        return Ok(());
+        return;
     }
 
     println!(
@@ -47,6 +197,4 @@ pub fn read_and_display_tags(path: &PathBuf) -> Result<(), Box<dyn std::error::E
         );
         println!("  {}: {:?}", "Content".green(), item.value());
     }
-
-    Ok(())
 }
@@ -0,0 +1,119 @@
+use colored::Colorize;
+use lofty::config::WriteOptions;
+use lofty::prelude::*;
+use lofty::tag::Tag;
+use log::{debug, error, info};
+use std::path::PathBuf;
+
+/// friendly names accepted by --tag and the fields they write
+const FRIENDLY_KEYS: [(&str, ItemKey); 10] = [
+    ("title", ItemKey::TrackTitle),
+    ("artist", ItemKey::TrackArtist),
+    ("album", ItemKey::AlbumTitle),
+    ("albumartist", ItemKey::AlbumArtist),
+    ("track", ItemKey::TrackNumber),
+    ("tracktotal", ItemKey::TrackTotal),
+    ("disc", ItemKey::DiscNumber),
+    ("year", ItemKey::Year),
+    ("genre", ItemKey::Genre),
+    ("comment", ItemKey::Comment),
+];
+
+/// write KEY=VALUE pairs into the file's primary tag
+///
+/// a primary tag is created if the file has none. with dry_run the
+/// changes are only printed, the file isn't touched
+pub fn write_tags(
+    path: &PathBuf,
+    assignments: &[String],
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let changes = assignments
+        .iter()
+        .map(|assignment| parse_assignment(assignment))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    debug!("loading tags from file: {}", path.display());
+    let mut tagged_file = lofty::read_from_path(path).map_err(|e| {
+        error!("failed to read audio file: {}", e);
+        e
+    })?;
+
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        info!("no {:?} tag in file, creating one", tag_type);
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file
+        .primary_tag_mut()
+        .ok_or("could not create a tag for this file")?;
+
+    for (name, key, value) in &changes {
+        let old = tag.get_string(key).map(|old| old.to_string());
+        println!(
+            "{} {}: {} -> {}",
+            "「set」".cyan().bold(),
+            name.yellow(),
+            old.as_deref().unwrap_or("(none)").dimmed(),
+            value.green()
+        );
+
+        if !dry_run && !tag.insert_text(key.clone(), value.clone()) {
+            return Err(format!("{:?} tags can't hold {}", tag.tag_type(), name).into());
+        }
+    }
+
+    if dry_run {
+        println!(
+            "{} {}",
+            "「dry run」".yellow().bold(),
+            "nothing was written".cyan()
+        );
+        return Ok(());
+    }
+
+    tagged_file.save_to_path(path, WriteOptions::default())?;
+    println!(
+        "{} {}",
+        "「saved」".green().bold(),
+        path.display().to_string().cyan()
+    );
+
+    Ok(())
+}
+
+/// split `KEY=VALUE` and map the key, numeric fields must be numbers
+fn parse_assignment(
+    assignment: &str,
+) -> Result<(String, ItemKey, String), Box<dyn std::error::Error>> {
+    let (name, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {}", assignment))?;
+    let name = name.trim().to_ascii_lowercase();
+
+    let key = FRIENDLY_KEYS
+        .iter()
+        .find(|(friendly, _)| *friendly == name)
+        .map(|(_, key)| key.clone())
+        .ok_or_else(|| {
+            let known: Vec<&str> = FRIENDLY_KEYS
+                .iter()
+                .map(|(friendly, _)| *friendly)
+                .collect();
+            format!(
+                "unknown tag key {}, expected one of {}",
+                name,
+                known.join(", ")
+            )
+        })?;
+
+    let numeric = matches!(
+        key,
+        ItemKey::TrackNumber | ItemKey::TrackTotal | ItemKey::DiscNumber | ItemKey::Year
+    );
+    if numeric && value.trim().parse::<u32>().is_err() {
+        return Err(format!("{} must be a number, got {}", name, value).into());
+    }
+
+    Ok((name, key, value.trim().to_string()))
+}
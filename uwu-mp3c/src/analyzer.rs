@@ -1,20 +1,28 @@
-use crate::frame::{calculate_entropy, group_into_runs, FrameInfo};
-use anyhow::{anyhow, Result};
+use crate::art;
+use crate::fingerprint::{AudioFingerprinter, format_fingerprint};
+use crate::frame::{
+    EntropyAnomaly, FrameCounts, FrameInfo, Id3v1Fields, ScanOptions, SyncLossRegion, audio_end,
+    calculate_entropy, check_frame_crc, find_entropy_anomalies, find_sync_loss, frame_header_at,
+    group_into_runs, id3v1_tag, id3v2_size, parse_id3v1, write_frames_csv, write_runs_json,
+    xing_frame_count,
+};
+use crate::graph::generate_contiguity_graph;
+use anyhow::{Result, anyhow};
 use colored::Colorize;
 use log::debug;
-use plotters::prelude::*;
-use std::fs::File;
-use std::io::Read;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
-use symphonia::core::audio::{AudioBufferRef, Signal};
-use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer};
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
 use symphonia::core::errors::Error as SymphoniaError;
 use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 
-pub fn analyze(input_path: &Path) -> Result<()> {
+pub fn analyze(input_path: &Path, options: &ScanOptions) -> Result<FrameCounts> {
     println!(
         "{} {}",
         "「analyzing」".cyan().bold(),
@@ -22,8 +30,17 @@ pub fn analyze(input_path: &Path) -> Result<()> {
     );
     println!();
 
-    let reported_duration = mp3_duration::from_path(input_path)?;
-    let naive_duration = calculate_naive_duration(input_path)?;
+    if let Some(art_dir) = &options.extract_art {
+        art::extract_art(input_path, art_dir)?;
+        println!();
+    }
+
+    // every pass below works from this one read of the file
+    let buffer = fs::read(input_path)?;
+
+    let reported_duration = mp3_duration::from_read(&mut Cursor::new(&buffer))?;
+    let naive_scan = calculate_naive_duration(&buffer);
+    let naive_duration = naive_scan.duration;
 
     println!(
         "{} {:.3}s",
@@ -48,96 +65,267 @@ pub fn analyze(input_path: &Path) -> Result<()> {
     }
     println!();
 
-    analyze_structure(input_path)?;
+    print_bitrates(&naive_scan);
+    if let Some(tag) = &naive_scan.id3v1 {
+        print_id3v1(tag);
+    }
 
-    Ok(())
+    // the strict check and the structure pass report the same regions
+    let (frames_walked, sync_loss) = find_sync_loss(&buffer);
+    if options.strict {
+        strict_frame_check(frames_walked, &sync_loss);
+    }
+
+    analyze_structure(input_path, buffer, &sync_loss, options)
 }
 
-fn calculate_naive_duration(input_path: &Path) -> Result<f64> {
-    let mut file = File::open(input_path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+/// running amplitude stats over every decoded sample
+#[derive(Default)]
+struct Levels {
+    peak: f32,
+    sum: f64,
+    sum_squares: f64,
+    samples: u64,
+    clipped: u64,
+}
 
-    let mut total_duration = 0.0;
-    let mut pos = 0;
+impl Levels {
+    /// fold in a decoded buffer of any sample format, returns its sample count
+    fn add(&mut self, decoded: &AudioBufferRef) -> u64 {
+        let mut sample_buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        sample_buf.copy_interleaved_ref(decoded.clone());
+
+        for &sample in sample_buf.samples() {
+            let magnitude = sample.abs();
+            self.peak = self.peak.max(magnitude);
+            self.sum += sample as f64;
+            self.sum_squares += sample as f64 * sample as f64;
+            if magnitude >= 1.0 {
+                self.clipped += 1;
+            }
+        }
 
-    if buffer.len() > 10 && &buffer[0..3] == b"ID3" {
-        let size = ((buffer[6] as usize) << 21)
-            | ((buffer[7] as usize) << 14)
-            | ((buffer[8] as usize) << 7)
-            | (buffer[9] as usize);
-        pos = 10 + size;
-        debug!("skipped ID3v2 tag: {} bytes", 10 + size);
+        let count = sample_buf.samples().len() as u64;
+        self.samples += count;
+        count
     }
 
-    while pos + 4 <= buffer.len() {
-        if buffer[pos] != 0xFF || (buffer[pos + 1] & 0xE0) != 0xE0 {
-            pos += 1;
-            continue;
-        }
-
-        let header = u32::from_be_bytes([
-            buffer[pos],
-            buffer[pos + 1],
-            buffer[pos + 2],
-            buffer[pos + 3],
-        ]);
-
-        let version = (header >> 19) & 0x3;
-        let layer = (header >> 17) & 0x3;
-        let bitrate_index = (header >> 12) & 0xF;
-        let sample_rate_index = (header >> 10) & 0x3;
-        let padding = (header >> 9) & 0x1;
-
-        if version == 1
-            || layer == 0
-            || bitrate_index == 0
-            || bitrate_index == 15
-            || sample_rate_index == 3
-        {
-            pos += 1;
-            continue;
+    fn print(&self) {
+        if self.samples == 0 {
+            return;
         }
 
-        let sample_rates = match version {
-            0 => [11025, 12000, 8000],  
-            2 => [22050, 24000, 16000], 
-            3 => [44100, 48000, 32000], 
-            _ => {
-                pos += 1;
-                continue;
+        let rms = (self.sum_squares / self.samples as f64).sqrt();
+        let dc_offset = self.sum / self.samples as f64;
+        let to_db = |level: f64| {
+            if level > 0.0 {
+                format!("{:.1} dBFS", 20.0 * level.log10())
+            } else {
+                "-inf dBFS".to_string()
             }
         };
-        let sample_rate = sample_rates[sample_rate_index as usize];
 
-        let bitrates = if version == 3 {
-            [
-                0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 0,
-            ]
-        } else {
-            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0]
-        };
-        let bitrate = bitrates[bitrate_index as usize] * 1000;
+        println!(
+            "{} peak {:.4} ({}), rms {:.4} ({}), dc offset {:.5}",
+            "「levels」".cyan().bold(),
+            self.peak,
+            to_db(self.peak as f64).yellow(),
+            rms,
+            to_db(rms).yellow(),
+            dc_offset
+        );
+        if self.clipped > 0 {
+            println!(
+                "{} {} samples at or above full scale ({:.3}%)",
+                "「clipping」".red().bold(),
+                self.clipped.to_string().yellow(),
+                self.clipped as f64 / self.samples as f64 * 100.0
+            );
+        }
+    }
+}
+
+/// what the header-only frame walk found
+struct NaiveScan {
+    duration: f64,
+    /// kbps -> frames at that bitrate, not counting a Xing frame
+    bitrates: BTreeMap<usize, usize>,
+    has_xing: bool,
+    id3v1: Option<Id3v1Fields>,
+}
+
+fn calculate_naive_duration(buffer: &[u8]) -> NaiveScan {
+    let mut total_duration = 0.0;
+    let mut bitrates = BTreeMap::new();
+    let mut pos = id3v2_size(buffer);
 
-        if bitrate == 0 {
+    // a trailing ID3v1 tag would otherwise be scanned as frame data
+    let id3v1 = id3v1_tag(buffer).map(parse_id3v1);
+    let buffer = &buffer[..audio_end(buffer)];
+
+    if pos > 0 {
+        debug!("skipped ID3v2 tag: {} bytes", pos);
+    }
+
+    let mut free_size = None;
+
+    // a Xing/Info tag knows the exact frame count, walking only estimates it
+    while pos + 4 <= buffer.len() && frame_header_at(buffer, pos, &mut free_size).is_none() {
+        pos += 1;
+    }
+    let mut xing_duration = None;
+    if let Some(header) = frame_header_at(buffer, pos, &mut free_size)
+        && let Some(frames) = xing_frame_count(&buffer[pos..])
+    {
+        debug!("found Xing frame count: {} frames", frames);
+        xing_duration = (frames > 0)
+            .then(|| frames as f64 * header.samples_per_frame as f64 / header.sample_rate as f64);
+        // the Xing frame carries no audio, keep it out of the bitrates
+        pos += header.frame_size;
+    }
+    let has_xing = xing_duration.is_some();
+
+    while pos + 4 <= buffer.len() {
+        let Some(header) = frame_header_at(buffer, pos, &mut free_size) else {
             pos += 1;
             continue;
+        };
+
+        total_duration += header.samples_per_frame as f64 / header.sample_rate as f64;
+        *bitrates.entry(header.bitrate).or_insert(0) += 1;
+
+        pos += header.frame_size;
+    }
+
+    NaiveScan {
+        duration: xing_duration.unwrap_or(total_duration),
+        bitrates,
+        has_xing,
+        id3v1,
+    }
+}
+
+fn print_id3v1(tag: &Id3v1Fields) {
+    let show = |value: &str| {
+        if value.is_empty() {
+            "-".dimmed()
+        } else {
+            value.yellow()
         }
+    };
 
-        let samples_per_frame = if version == 3 { 1152 } else { 576 };
-        let frame_size = (samples_per_frame / 8 * bitrate) / sample_rate + padding as usize;
+    println!(
+        "{} title {}, artist {}, album {}, year {}",
+        "「ID3v1」".cyan().bold(),
+        show(&tag.title),
+        show(&tag.artist),
+        show(&tag.album),
+        show(&tag.year)
+    );
+    println!();
+}
 
-        total_duration += samples_per_frame as f64 / sample_rate as f64;
+/// min/max/average bitrate and whether the file is cbr or vbr
+fn print_bitrates(scan: &NaiveScan) {
+    let (Some(min), Some(max)) = (
+        scan.bitrates.keys().next(),
+        scan.bitrates.keys().next_back(),
+    ) else {
+        return;
+    };
+    let frames: usize = scan.bitrates.values().sum();
+    let average = scan
+        .bitrates
+        .iter()
+        .map(|(bitrate, count)| bitrate * count)
+        .sum::<usize>() as f64
+        / frames as f64;
 
-        pos += frame_size;
+    println!(
+        "{} min {}kbps, max {}kbps, average {:.1}kbps",
+        "「bitrate」".cyan().bold(),
+        min.to_string().yellow(),
+        max.to_string().yellow(),
+        average
+    );
+    for (bitrate, count) in &scan.bitrates {
+        println!(
+            "  {}kbps: {} frames",
+            bitrate.to_string().cyan(),
+            count.to_string().yellow()
+        );
     }
 
-    Ok(total_duration)
+    if scan.bitrates.len() == 1 {
+        println!("{} single bitrate", "「CBR」".green().bold());
+    } else if scan.has_xing {
+        println!(
+            "{} {} bitrates, backed by a Xing/Info header",
+            "「VBR」".green().bold(),
+            scan.bitrates.len().to_string().yellow()
+        );
+    } else {
+        println!(
+            "{} {} bitrates but no Xing/Info header, players may guess the duration wrong",
+            "「VBR」".yellow().bold(),
+            scan.bitrates.len().to_string().yellow()
+        );
+    }
+    println!();
 }
 
-fn analyze_structure(input_path: &Path) -> Result<()> {
-    let file = File::open(input_path)?;
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+/// walk frames using only header math and check every boundary
+///
+/// symphonia will happily resync past garbage, this doesn't, every
+/// frame has to start exactly where the previous one said it would
+fn strict_frame_check(frames_checked: usize, regions: &[SyncLossRegion]) {
+    let boundary_errors: Vec<(usize, Option<usize>)> = regions
+        .iter()
+        .map(|region| (region.start, region.resynced.then_some(region.end)))
+        .collect();
+
+    println!("{}", "「strict frame check」".magenta().bold());
+    println!(
+        "{} {}",
+        "「frames walked」".cyan().bold(),
+        frames_checked.to_string().yellow()
+    );
+
+    if boundary_errors.is_empty() {
+        println!("{}", "「frame boundaries ok」".green().bold());
+    } else {
+        println!(
+            "{} {}",
+            "「boundary errors」".red().bold(),
+            boundary_errors.len().to_string().yellow()
+        );
+        for (expected, resync) in &boundary_errors {
+            match resync {
+                Some(found) => println!(
+                    "  expected sync at byte {}, next sync at byte {} ({} bytes skipped)",
+                    expected.to_string().cyan(),
+                    found.to_string().cyan(),
+                    (found - expected).to_string().yellow()
+                ),
+                None => println!(
+                    "  expected sync at byte {}, no further frames found",
+                    expected.to_string().cyan()
+                ),
+            }
+        }
+    }
+    println!();
+}
+
+fn analyze_structure(
+    input_path: &Path,
+    buffer: Vec<u8>,
+    sync_loss: &[SyncLossRegion],
+    options: &ScanOptions,
+) -> Result<FrameCounts> {
+    let audio_start = id3v2_size(&buffer);
+    let audio_len = audio_end(&buffer).saturating_sub(audio_start);
+    let mss = MediaSourceStream::new(Box::new(Cursor::new(buffer)), Default::default());
 
     let mut hint = Hint::new();
     hint.with_extension("mp3");
@@ -164,7 +352,11 @@ fn analyze_structure(input_path: &Path) -> Result<()> {
         codec_params.codec.to_string().yellow()
     );
     if let Some(sr) = codec_params.sample_rate {
-        println!("{} {}Hz", "「sample rate」".cyan().bold(), sr.to_string().yellow());
+        println!(
+            "{} {}Hz",
+            "「sample rate」".cyan().bold(),
+            sr.to_string().yellow()
+        );
     }
     if let Some(ch) = codec_params.channels {
         println!(
@@ -181,11 +373,25 @@ fn analyze_structure(input_path: &Path) -> Result<()> {
     let mut frame_count = 0;
     let mut valid_frames = 0;
     let mut total_samples = 0u64;
+    let mut levels = Levels::default();
     let mut corrupted_frames = 0;
+    let mut crc_failures = 0;
     let mut frame_infos: Vec<FrameInfo> = Vec::new();
     let mut byte_offset = 0;
 
+    let mut hit_frame_limit = false;
+    let mut fingerprinter = options.fingerprint.then(AudioFingerprinter::new);
+
     loop {
+        if options
+            .frame_limit
+            .is_some_and(|limit| frame_count >= limit)
+        {
+            debug!("frame limit reached after {} frames", frame_count);
+            hit_frame_limit = true;
+            break;
+        }
+
         let packet = match format.next_packet() {
             Ok(packet) => packet,
             Err(SymphoniaError::ResetRequired) | Err(SymphoniaError::IoError(_)) => {
@@ -203,30 +409,44 @@ fn analyze_structure(input_path: &Path) -> Result<()> {
 
         let entropy = calculate_entropy(&packet.data);
         let size = packet.data.len();
+        // crc is always checked, --validate-crc decides if a bad one drops the frame
+        let crc_ok = check_frame_crc(&packet.data);
+        if crc_ok == Some(false) {
+            debug!("frame {} failed crc check", frame_count);
+            crc_failures += 1;
+        }
+        let crc_failed = options.validate_crc && crc_ok == Some(false);
 
         match decoder.decode(&packet) {
-            Ok(decoded) => {
+            Ok(decoded) if !crc_failed => {
                 valid_frames += 1;
                 frame_infos.push(FrameInfo {
                     is_valid: true,
                     entropy,
                     size,
                     byte_offset,
+                    crc_ok,
                 });
 
-                if let AudioBufferRef::F32(buf) = decoded {
-                    total_samples += buf.frames() as u64 * buf.spec().channels.count() as u64;
+                total_samples += levels.add(&decoded);
+
+                if let Some(fingerprinter) = fingerprinter.as_mut() {
+                    fingerprinter.consume(decoded)?;
                 }
             }
-            Err(err) => {
-                debug!("failed to decode frame {}: {:?}", frame_count, err);
-                corrupted_frames += 1;
+            result => {
+                // frames dropped only for their crc are counted under crc failures
+                if let Err(err) = result {
+                    debug!("failed to decode frame {}: {:?}", frame_count, err);
+                    corrupted_frames += 1;
+                }
 
                 frame_infos.push(FrameInfo {
                     is_valid: false,
                     entropy,
                     size,
                     byte_offset,
+                    crc_ok,
                 });
             }
         }
@@ -235,6 +455,14 @@ fn analyze_structure(input_path: &Path) -> Result<()> {
         frame_count += 1;
     }
 
+    if hit_frame_limit {
+        println!(
+            "{} stopped after {} frames, results are partial",
+            "「frame limit」".yellow().bold(),
+            frame_count.to_string().yellow()
+        );
+    }
+
     println!(
         "{} {}",
         "「total frames」".cyan().bold(),
@@ -254,13 +482,33 @@ fn analyze_structure(input_path: &Path) -> Result<()> {
         );
     }
 
+    if crc_failures > 0 {
+        println!(
+            "{} {}{}",
+            "「crc failures」".red().bold(),
+            crc_failures.to_string().yellow(),
+            if options.validate_crc {
+                ""
+            } else {
+                " (still counted valid, use --validate-crc to drop them)"
+            }
+        );
+    }
+
+    // --strict has already listed every region as a boundary error
+    if !options.strict {
+        print_sync_loss(sync_loss);
+    }
+
+    let anomalies = find_entropy_anomalies(&frame_infos, options.entropy_sigma);
+    print_entropy_anomalies(&anomalies, options.entropy_sigma);
+
     if let Some(sample_rate) = codec_params.sample_rate {
         let channel_count = codec_params
             .channels
             .map(|c| c.count() as f64)
             .unwrap_or(2.0);
-        let actual_duration_secs =
-            total_samples as f64 / (sample_rate as f64 * channel_count);
+        let actual_duration_secs = total_samples as f64 / (sample_rate as f64 * channel_count);
         println!(
             "{} {:.3}s",
             "「decoded duration」".cyan().bold(),
@@ -268,78 +516,134 @@ fn analyze_structure(input_path: &Path) -> Result<()> {
         );
     }
 
-    println!();
-    println!("{}", "「generating analysis graph」".magenta().bold());
-    let graph_dir = PathBuf::from(".");
-    generate_contiguity_graph(&frame_infos, &graph_dir)?;
+    levels.print();
 
-    Ok(())
-}
+    if let Some(fingerprinter) = fingerprinter {
+        let fingerprint = fingerprinter.finish();
+        if fingerprint.is_empty() {
+            println!(
+                "{} not enough decoded audio to fingerprint",
+                "「fingerprint」".yellow().bold()
+            );
+        } else {
+            println!(
+                "{} {}",
+                "「fingerprint」".magenta().bold(),
+                format_fingerprint(&fingerprint).cyan()
+            );
+        }
+    }
 
-fn generate_contiguity_graph(frames: &[FrameInfo], output_dir: &Path) -> Result<()> {
-    let runs = group_into_runs(frames);
-    let output_path = output_dir.join("contiguity_entropy.png");
+    if let Some(runs_path) = &options.runs_json {
+        write_runs_json(&group_into_runs(&frame_infos), runs_path)?;
+        println!(
+            "{} {}",
+            "「runs saved」".green().bold(),
+            runs_path.display().to_string().cyan()
+        );
+    }
 
-    let root = BitMapBackend::new(&output_path, (7200, 3600)).into_drawing_area();
-    root.fill(&WHITE)?;
+    if let Some(csv_path) = &options.csv {
+        write_frames_csv(&frame_infos, csv_path)?;
+        println!(
+            "{} {}",
+            "「frames saved」".green().bold(),
+            csv_path.display().to_string().cyan()
+        );
+    }
 
-    let total_bytes = if let Some(last) = frames.last() {
-        last.byte_offset + last.size
-    } else {
-        return Ok(());
-    };
+    if !options.no_graph {
+        println!();
+        println!("{}", "「generating analysis graph」".magenta().bold());
+        generate_contiguity_graph(
+            &frame_infos,
+            &default_graph_path(input_path, options),
+            (options.graph_width, options.graph_height),
+            (sync_loss, audio_start, audio_len),
+            &anomalies,
+        )?;
+    }
 
-    let max_entropy = frames.iter().map(|f| f.entropy).fold(0.0_f64, f64::max);
+    Ok(FrameCounts {
+        total: frame_count,
+        valid: valid_frames,
+        corrupted: frame_count - valid_frames,
+    })
+}
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption("Frame Contiguity and Entropy by Bytes", ("sans-serif", 120))
-        .margin(40)
-        .x_label_area_size(160)
-        .y_label_area_size(200)
-        .build_cartesian_2d(0..total_bytes, 0.0..max_entropy.max(8.0))?;
+/// entropy anomalies shown before the rest are just counted
+const ANOMALY_EXAMPLES: usize = 20;
 
-    chart
-        .configure_mesh()
-        .x_desc("Byte Position")
-        .y_desc("Entropy (bits)")
-        .draw()?;
+pub fn print_entropy_anomalies(anomalies: &[EntropyAnomaly], sigma: f64) {
+    if anomalies.is_empty() {
+        return;
+    }
 
-    for run in &runs {
-        let color = if run.is_valid {
-            GREEN.mix(0.3)
-        } else {
-            RED.mix(0.3)
-        };
+    println!(
+        "{} {} frames more than {} sigma from the running mean",
+        "「entropy anomalies」".red().bold(),
+        anomalies.len().to_string().yellow(),
+        sigma
+    );
+    for anomaly in anomalies.iter().take(ANOMALY_EXAMPLES) {
+        println!(
+            "  frame {} at byte {}: entropy {:.2}, running mean {:.2}",
+            anomaly.index.to_string().cyan(),
+            anomaly.byte_offset.to_string().cyan(),
+            anomaly.entropy,
+            anomaly.running_mean
+        );
+    }
+    if anomalies.len() > ANOMALY_EXAMPLES {
+        println!("  and {} more", anomalies.len() - ANOMALY_EXAMPLES);
+    }
+}
+
+/// where in the file the stream lost sync, by raw byte offset
+fn print_sync_loss(regions: &[SyncLossRegion]) {
+    if regions.is_empty() {
+        return;
+    }
 
-        chart.draw_series(std::iter::once(Rectangle::new(
-            [(run.start_byte, 0.0), (run.end_byte, run.avg_entropy)],
-            color.filled(),
-        )))?;
-    }
-
-    chart
-        .draw_series(LineSeries::new(
-            frames
-                .iter()
-                .filter(|f| f.is_valid)
-                .map(|f| (f.byte_offset, f.entropy)),
-            &BLUE,
-        ))?
-        .label("Valid Frame Entropy")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 80, y)], &BLUE));
-
-    chart
-        .configure_series_labels()
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()?;
-
-    root.present()?;
+    let lost_bytes: usize = regions.iter().map(|r| r.end - r.start).sum();
     println!(
-        "{} {}",
-        "「graph saved」".green().bold(),
-        output_path.display().to_string().cyan()
+        "{} {} regions, {} bytes",
+        "「sync loss」".red().bold(),
+        regions.len().to_string().yellow(),
+        lost_bytes.to_string().yellow()
     );
+    for region in regions {
+        println!(
+            "  bytes {}-{} ({} bytes){}",
+            region.start.to_string().cyan(),
+            region.end.to_string().cyan(),
+            (region.end - region.start).to_string().yellow(),
+            if region.resynced {
+                ""
+            } else {
+                ", never resynced"
+            }
+        );
+    }
+}
+
+/// --graph if given, otherwise `<input>-contiguity.png` next to the
+/// input, or in the batch output directory
+fn default_graph_path(input_path: &Path, options: &ScanOptions) -> PathBuf {
+    if let Some(path) = &options.graph {
+        return path.clone();
+    }
 
-    Ok(())
+    let stem = input_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    let dir = match &options.output_dir {
+        Some(dir) => dir.clone(),
+        None => input_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf(),
+    };
+    dir.join(format!("{}-contiguity.png", stem))
 }
@@ -0,0 +1,315 @@
+use crate::frame::id3v2_size;
+use anyhow::Result;
+use colored::Colorize;
+use log::debug;
+use std::fs;
+use std::path::Path;
+
+/// ID3v2 header flag, the tag body has been unsynchronised
+const TAG_UNSYNC: u8 = 0x80;
+/// ID3v2.3/2.4 header flag, an extended header follows the tag header
+const TAG_EXTENDED_HEADER: u8 = 0x40;
+
+/// v2.3 frame flags we can't read past
+const V23_COMPRESSED: u16 = 0x0080;
+const V23_ENCRYPTED: u16 = 0x0040;
+const V23_GROUPED: u16 = 0x0020;
+
+/// v2.4 frame flags
+const V24_GROUPED: u16 = 0x0040;
+const V24_COMPRESSED: u16 = 0x0008;
+const V24_ENCRYPTED: u16 = 0x0004;
+const V24_UNSYNC: u16 = 0x0002;
+const V24_DATA_LENGTH: u16 = 0x0001;
+
+/// one picture out of a PIC or APIC frame
+struct Picture {
+    mime: String,
+    picture_type: u8,
+    data: Vec<u8>,
+}
+
+/// write every picture in the file's ID3v2 tag to `output_dir`
+///
+/// handles v2.2 PIC and v2.3/2.4 APIC frames, undoing unsynchronisation
+/// first. returns how many pictures were written
+pub fn extract_art(input_path: &Path, output_dir: &Path) -> Result<usize> {
+    let buffer = fs::read(input_path)?;
+    let pictures = find_pictures(&buffer);
+
+    if pictures.is_empty() {
+        println!("{} no embedded pictures", "「album art」".yellow().bold());
+        return Ok(0);
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let stem = input_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+
+    for (index, picture) in pictures.iter().enumerate() {
+        let type_name = picture_type_name(picture.picture_type);
+        let slug = type_name.to_lowercase().replace([' ', '/'], "-");
+        let extension = picture_extension(&picture.mime, &picture.data);
+        let path = output_dir.join(format!("{}-{}-{}.{}", stem, index + 1, slug, extension));
+        fs::write(&path, &picture.data)?;
+
+        println!(
+            "{} {} ({}, {} bytes) -> {}",
+            "「album art」".magenta().bold(),
+            type_name.yellow(),
+            picture.mime,
+            picture.data.len(),
+            path.display().to_string().cyan()
+        );
+    }
+
+    Ok(pictures.len())
+}
+
+fn find_pictures(buffer: &[u8]) -> Vec<Picture> {
+    let tag_end = id3v2_size(buffer).min(buffer.len());
+    if tag_end == 0 {
+        return Vec::new();
+    }
+
+    let version = buffer[3];
+    let flags = buffer[5];
+    debug!(
+        "ID3v2.{} tag, {} bytes, flags {:#04x}",
+        version, tag_end, flags
+    );
+
+    // v2.4 unsynchronises frame by frame, earlier versions the whole body
+    let body = if flags & TAG_UNSYNC != 0 && version < 4 {
+        remove_unsync(&buffer[10..tag_end])
+    } else {
+        buffer[10..tag_end].to_vec()
+    };
+
+    let mut pos = 0;
+    if flags & TAG_EXTENDED_HEADER != 0 && version >= 3 && body.len() >= 4 {
+        pos = match version {
+            3 => 4 + read_u32(&body[0..4]) as usize,
+            _ => read_syncsafe(&body[0..4]) as usize,
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut pictures = Vec::new();
+
+    while pos + header_len <= body.len() {
+        let header = &body[pos..pos + header_len];
+        // padding runs to the end of the tag
+        if header[0] == 0 {
+            break;
+        }
+
+        let id = &header[..id_len];
+        let (size, frame_flags) = match version {
+            2 => (read_u24(&header[3..6]) as usize, 0),
+            3 => (read_u32(&header[4..8]) as usize, read_u16(&header[8..10])),
+            _ => (
+                read_syncsafe(&header[4..8]) as usize,
+                read_u16(&header[8..10]),
+            ),
+        };
+
+        let data_start = pos + header_len;
+        let data_end = data_start + size;
+        if data_end > body.len() {
+            debug!(
+                "frame {} runs past the end of the tag",
+                String::from_utf8_lossy(id)
+            );
+            break;
+        }
+        pos = data_end;
+
+        if id != b"PIC" && id != b"APIC" {
+            continue;
+        }
+
+        let Some(data) = frame_payload(&body[data_start..data_end], version, flags, frame_flags)
+        else {
+            debug!("skipping compressed or encrypted picture frame");
+            continue;
+        };
+
+        let picture = if version == 2 {
+            parse_pic(&data)
+        } else {
+            parse_apic(&data)
+        };
+        match picture {
+            Some(picture) => pictures.push(picture),
+            None => debug!("malformed {} frame", String::from_utf8_lossy(id)),
+        }
+    }
+
+    pictures
+}
+
+/// the frame data with any per-frame extras removed
+fn frame_payload(data: &[u8], version: u8, tag_flags: u8, frame_flags: u16) -> Option<Vec<u8>> {
+    match version {
+        2 => Some(data.to_vec()),
+        3 => {
+            if frame_flags & (V23_COMPRESSED | V23_ENCRYPTED) != 0 {
+                return None;
+            }
+            let skip = if frame_flags & V23_GROUPED != 0 { 1 } else { 0 };
+            data.get(skip..).map(|data| data.to_vec())
+        }
+        _ => {
+            if frame_flags & (V24_COMPRESSED | V24_ENCRYPTED) != 0 {
+                return None;
+            }
+            let mut skip = 0;
+            if frame_flags & V24_GROUPED != 0 {
+                skip += 1;
+            }
+            if frame_flags & V24_DATA_LENGTH != 0 {
+                skip += 4;
+            }
+            let data = data.get(skip..)?;
+            if tag_flags & TAG_UNSYNC != 0 || frame_flags & V24_UNSYNC != 0 {
+                Some(remove_unsync(data))
+            } else {
+                Some(data.to_vec())
+            }
+        }
+    }
+}
+
+/// v2.2: encoding, 3 char image format, picture type, description, data
+fn parse_pic(data: &[u8]) -> Option<Picture> {
+    let encoding = *data.first()?;
+    let format = String::from_utf8_lossy(data.get(1..4)?).to_uppercase();
+    let picture_type = *data.get(4)?;
+    let image_start = 5 + text_len(data.get(5..)?, encoding)?;
+
+    let mime = match format.as_str() {
+        "JPG" => "image/jpeg".to_string(),
+        "-->" => return None,
+        other => format!("image/{}", other.to_lowercase()),
+    };
+
+    Some(Picture {
+        mime,
+        picture_type,
+        data: data[image_start..].to_vec(),
+    })
+}
+
+/// v2.3/2.4: encoding, latin-1 mime type, picture type, description, data
+fn parse_apic(data: &[u8]) -> Option<Picture> {
+    let encoding = *data.first()?;
+    let mime_len = data[1..].iter().position(|&b| b == 0)?;
+    let mime = String::from_utf8_lossy(&data[1..1 + mime_len]).to_lowercase();
+    // a url instead of image data
+    if mime == "-->" {
+        return None;
+    }
+
+    let type_pos = 1 + mime_len + 1;
+    let picture_type = *data.get(type_pos)?;
+    let image_start = type_pos + 1 + text_len(data.get(type_pos + 1..)?, encoding)?;
+
+    Some(Picture {
+        mime,
+        picture_type,
+        data: data[image_start..].to_vec(),
+    })
+}
+
+/// length of a terminated string including the terminator
+///
+/// utf-16 encodings end in a double null on an even offset
+fn text_len(data: &[u8], encoding: u8) -> Option<usize> {
+    match encoding {
+        1 | 2 => data
+            .chunks_exact(2)
+            .position(|pair| pair == [0, 0])
+            .map(|pairs| pairs * 2 + 2),
+        _ => data.iter().position(|&b| b == 0).map(|len| len + 1),
+    }
+}
+
+/// undo unsynchronisation, every 0xFF 0x00 goes back to 0xFF
+fn remove_unsync(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut previous = 0;
+    for &byte in data {
+        if !(previous == 0xFF && byte == 0x00) {
+            out.push(byte);
+        }
+        previous = byte;
+    }
+    out
+}
+
+fn picture_extension(mime: &str, data: &[u8]) -> &'static str {
+    match mime {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/bmp" => "bmp",
+        "image/webp" => "webp",
+        "image/tiff" => "tiff",
+        // some taggers leave the mime type empty, go by the bytes instead
+        _ if data.starts_with(&[0xFF, 0xD8, 0xFF]) => "jpg",
+        _ if data.starts_with(b"\x89PNG") => "png",
+        _ if data.starts_with(b"GIF8") => "gif",
+        _ if data.starts_with(b"BM") => "bmp",
+        _ => "bin",
+    }
+}
+
+/// picture type names from the ID3v2 spec
+fn picture_type_name(picture_type: u8) -> &'static str {
+    match picture_type {
+        0x00 => "Other",
+        0x01 => "File icon",
+        0x02 => "Other file icon",
+        0x03 => "Front cover",
+        0x04 => "Back cover",
+        0x05 => "Leaflet page",
+        0x06 => "Media",
+        0x07 => "Lead artist",
+        0x08 => "Artist",
+        0x09 => "Conductor",
+        0x0A => "Band",
+        0x0B => "Composer",
+        0x0C => "Lyricist",
+        0x0D => "Recording location",
+        0x0E => "During recording",
+        0x0F => "During performance",
+        0x10 => "Screen capture",
+        0x11 => "Bright coloured fish",
+        0x12 => "Illustration",
+        0x13 => "Band logotype",
+        0x14 => "Publisher logotype",
+        _ => "Unknown",
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u24(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .fold(0, |size, &b| (size << 7) | (b & 0x7F) as u32)
+}
@@ -0,0 +1,150 @@
+use crate::analyzer;
+use crate::frame::{FrameCounts, ScanOptions};
+use crate::repair;
+use anyhow::Result;
+use colored::Colorize;
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// run analyze, or repair when extract_path is set, on every .mp3
+/// directly inside a directory
+///
+/// each file gets a subdirectory named after it for its graph and
+/// other outputs. a file that fails is noted and the batch moves on
+pub fn run_batch(
+    input_dir: &Path,
+    extract_path: Option<&str>,
+    options: &ScanOptions,
+) -> Result<()> {
+    let mut files: Vec<PathBuf> = fs::read_dir(input_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"))
+        })
+        .collect();
+    files.sort();
+    debug!("found {} mp3 files in {}", files.len(), input_dir.display());
+
+    if files.is_empty() {
+        println!(
+            "{} no .mp3 files in {}",
+            "「batch」".yellow().bold(),
+            input_dir.display().to_string().cyan()
+        );
+        return Ok(());
+    }
+
+    let base_dir = match extract_path {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => input_dir.to_path_buf(),
+    };
+
+    let mut results: Vec<(String, Result<FrameCounts, String>)> = Vec::new();
+    for file in &files {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let stem = file
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        let file_dir = base_dir.join(stem);
+        let file_options = per_file_options(options, &file_dir);
+
+        let result = fs::create_dir_all(&file_dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| match extract_path {
+                None => analyzer::analyze(file, &file_options),
+                Some(_) => repair::repair(file, &file_dir.to_string_lossy(), &file_options),
+            });
+
+        if let Err(err) = &result {
+            println!("{} {}: {}", "「failed」".red().bold(), name.yellow(), err);
+        }
+        println!();
+        results.push((name, result.map_err(|err| err.to_string())));
+    }
+
+    print_batch_summary(&results);
+    Ok(())
+}
+
+/// point every output path at the file's own directory
+///
+/// paths given on the command line keep their file name, so each
+/// file's json, csv, wav, art and graph end up side by side in its directory
+fn per_file_options(options: &ScanOptions, file_dir: &Path) -> ScanOptions {
+    let relocate = |path: &Option<PathBuf>| {
+        path.as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| file_dir.join(name))
+    };
+
+    ScanOptions {
+        runs_json: relocate(&options.runs_json),
+        wav: relocate(&options.wav),
+        csv: relocate(&options.csv),
+        extract_art: relocate(&options.extract_art),
+        graph: relocate(&options.graph),
+        output_dir: Some(file_dir.to_path_buf()),
+        ..options.clone()
+    }
+}
+
+fn print_batch_summary(results: &[(String, Result<FrameCounts, String>)]) {
+    let name_width = results
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+
+    println!("{}", "「batch summary」".magenta().bold());
+    println!(
+        "  {:<width$}  {:>8}  {:>8}  {:>8}",
+        "file",
+        "total",
+        "valid",
+        "corrupt",
+        width = name_width
+    );
+    for (name, result) in results {
+        match result {
+            Ok(counts) => {
+                let corrupt = format!("{:>8}", counts.corrupted);
+                println!(
+                    "  {:<width$}  {:>8}  {:>8}  {}",
+                    name,
+                    counts.total,
+                    counts.valid,
+                    if counts.corrupted > 0 {
+                        corrupt.red()
+                    } else {
+                        corrupt.normal()
+                    },
+                    width = name_width
+                );
+            }
+            Err(err) => println!(
+                "  {:<width$}  {}",
+                name,
+                format!("error: {}", err).red(),
+                width = name_width
+            ),
+        }
+    }
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    println!(
+        "{} {} files, {} failed",
+        "「batch done」".green().bold(),
+        results.len().to_string().yellow(),
+        failed.to_string().yellow()
+    );
+}
@@ -0,0 +1,61 @@
+use anyhow::{Result, anyhow};
+use rusty_chromaprint::{Configuration, Fingerprinter};
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer};
+
+/// chromaprint style fingerprint built up from decoded frames
+///
+/// the printer is started on the first frame since that's the first
+/// time we know the real sample rate and channel count
+pub struct AudioFingerprinter {
+    printer: Fingerprinter,
+    started: bool,
+}
+
+impl AudioFingerprinter {
+    pub fn new() -> Self {
+        Self {
+            printer: Fingerprinter::new(&Configuration::preset_test2()),
+            started: false,
+        }
+    }
+
+    pub fn consume(&mut self, decoded: AudioBufferRef) -> Result<()> {
+        let spec = *decoded.spec();
+        if !self.started {
+            self.printer
+                .start(spec.rate, spec.channels.count() as u32)
+                .map_err(|e| anyhow!("failed to start fingerprinter: {:?}", e))?;
+            self.started = true;
+        }
+
+        // chromaprint wants interleaved i16, symphonia converts from f32 for us
+        let mut sample_buf = SampleBuffer::<i16>::new(decoded.capacity() as u64, spec);
+        sample_buf.copy_interleaved_ref(decoded);
+        self.printer.consume(sample_buf.samples());
+
+        Ok(())
+    }
+
+    /// finish and return the raw fingerprint, empty if nothing decoded
+    pub fn finish(mut self) -> Vec<u32> {
+        if !self.started {
+            return Vec::new();
+        }
+        self.printer.finish();
+        self.printer.fingerprint().to_vec()
+    }
+}
+
+impl Default for AudioFingerprinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// hex encode the fingerprint as one string for easy diffing
+pub fn format_fingerprint(fingerprint: &[u32]) -> String {
+    fingerprint
+        .iter()
+        .map(|word| format!("{:08x}", word))
+        .collect()
+}
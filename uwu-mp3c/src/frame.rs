@@ -1,4 +1,44 @@
-use std::collections::HashMap;
+use crate::wav::WavBitDepth;
+use anyhow::Result;
+use log::debug;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// options shared by the analyze and repair passes
+#[derive(Debug, Default, Clone)]
+pub struct ScanOptions {
+    pub runs_json: Option<PathBuf>,
+    pub validate_crc: bool,
+    pub strict: bool,
+    pub frame_limit: Option<usize>,
+    pub wav_bit_depth: WavBitDepth,
+    pub fingerprint: bool,
+    pub graph_width: u32,
+    pub graph_height: u32,
+    pub strip_tags: bool,
+    pub wav: Option<PathBuf>,
+    pub fill_silence: bool,
+    pub csv: Option<PathBuf>,
+    /// directory for analyze's default graph, next to the input if unset.
+    /// batch sets it to each file's own output directory
+    pub output_dir: Option<PathBuf>,
+    pub entropy_sigma: f64,
+    pub extract_art: Option<PathBuf>,
+    pub keep_corrupted_inline: bool,
+    pub graph: Option<PathBuf>,
+    pub no_graph: bool,
+}
+
+/// frame totals for one file, used by the batch summary
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameCounts {
+    pub total: usize,
+    pub valid: usize,
+    pub corrupted: usize,
+}
 
 #[derive(Debug, Clone)]
 pub struct FrameInfo {
@@ -6,14 +46,137 @@ pub struct FrameInfo {
     pub entropy: f64,
     pub size: usize,
     pub byte_offset: usize,
+    /// None when the frame carries no crc
+    pub crc_ok: Option<bool>,
 }
 
-#[derive(Debug)]
+/// fields decoded from a 4 byte mpeg audio frame header
+#[derive(Debug, Clone, Copy)]
+pub struct FrameHeader {
+    /// kbps
+    pub bitrate: usize,
+    pub sample_rate: usize,
+    pub samples_per_frame: usize,
+    pub frame_size: usize,
+}
+
+#[derive(Debug, Serialize)]
 pub struct FrameRun {
     pub start_byte: usize,
     pub end_byte: usize,
     pub is_valid: bool,
     pub avg_entropy: f64,
+    pub frame_count: usize,
+    /// frames in the run whose crc didn't match
+    pub crc_failures: usize,
+}
+
+/// bytes between where a frame should have started and the next sync
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncLossRegion {
+    pub start: usize,
+    pub end: usize,
+    /// false if no frame turned up again before the end of the file
+    pub resynced: bool,
+}
+
+/// walk frames by header math from the first sync and note every gap
+///
+/// returns how many frames were walked along with each region where
+/// a frame header was expected but not found
+pub fn find_sync_loss(buffer: &[u8]) -> (usize, Vec<SyncLossRegion>) {
+    let mut pos = id3v2_size(buffer);
+    let buffer = &buffer[..audio_end(buffer)];
+    let mut frames_walked = 0;
+    let mut regions = Vec::new();
+    let mut free_size = None;
+
+    // skip to the first frame, nothing was expected before it so
+    // leading junk isn't reported as a sync loss region
+    while pos + 4 <= buffer.len() && frame_header_at(buffer, pos, &mut free_size).is_none() {
+        pos += 1;
+    }
+
+    while pos + 4 <= buffer.len() {
+        match frame_header_at(buffer, pos, &mut free_size) {
+            Some(header) => {
+                frames_walked += 1;
+                pos += header.frame_size;
+            }
+            None => {
+                let start = pos;
+                pos += 1;
+                while pos + 4 <= buffer.len()
+                    && frame_header_at(buffer, pos, &mut free_size).is_none()
+                {
+                    pos += 1;
+                }
+                let resynced = pos + 4 <= buffer.len();
+                let end = if resynced { pos } else { buffer.len() };
+                debug!("expected sync at {}, lost until {}", start, end);
+                regions.push(SyncLossRegion {
+                    start,
+                    end,
+                    resynced,
+                });
+            }
+        }
+    }
+
+    (frames_walked, regions)
+}
+
+/// frames of history the running entropy mean is taken over
+const ENTROPY_WINDOW: usize = 64;
+
+/// frames needed before anything can stand out from the mean
+const MIN_ENTROPY_HISTORY: usize = 16;
+
+/// a frame whose entropy is far from the frames before it
+#[derive(Debug, Clone, Serialize)]
+pub struct EntropyAnomaly {
+    pub index: usize,
+    pub byte_offset: usize,
+    pub entropy: f64,
+    pub running_mean: f64,
+}
+
+/// flag frames more than `sigma` standard deviations from the running mean
+///
+/// near 0 usually means zero fill, near 8 random bytes where audio
+/// should be. the anomalies themselves stay out of the window so a
+/// long bad run doesn't become the new normal
+pub fn find_entropy_anomalies(frames: &[FrameInfo], sigma: f64) -> Vec<EntropyAnomaly> {
+    let mut window: VecDeque<f64> = VecDeque::new();
+    let mut anomalies = Vec::new();
+
+    for (index, frame) in frames.iter().enumerate() {
+        if window.len() >= MIN_ENTROPY_HISTORY {
+            let mean = window.iter().sum::<f64>() / window.len() as f64;
+            let variance =
+                window.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / window.len() as f64;
+            // a perfectly flat window would flag any wobble at all
+            let deviation = variance.sqrt().max(0.01);
+
+            if (frame.entropy - mean).abs() > sigma * deviation {
+                anomalies.push(EntropyAnomaly {
+                    index,
+                    byte_offset: frame.byte_offset,
+                    entropy: frame.entropy,
+                    running_mean: mean,
+                });
+                continue;
+            }
+        }
+
+        window.push_back(frame.entropy);
+        if window.len() > ENTROPY_WINDOW {
+            window.pop_front();
+        }
+    }
+
+    debug!("{} entropy anomalies at {} sigma", anomalies.len(), sigma);
+    anomalies
 }
 
 pub fn calculate_entropy(data: &[u8]) -> f64 {
@@ -50,25 +213,31 @@ pub fn group_into_runs(frames: &[FrameInfo]) -> Vec<FrameRun> {
     let mut current_entropy_sum = frames[0].entropy;
     let mut current_count = 1;
     let mut current_end_byte = frames[0].byte_offset + frames[0].size;
+    let crc_failed = |frame: &FrameInfo| usize::from(frame.crc_ok == Some(false));
+    let mut current_crc_failures = crc_failed(&frames[0]);
 
-    for i in 1..frames.len() {
-        if frames[i].is_valid == current_is_valid {
-            current_entropy_sum += frames[i].entropy;
+    for frame in &frames[1..] {
+        if frame.is_valid == current_is_valid {
+            current_entropy_sum += frame.entropy;
             current_count += 1;
-            current_end_byte = frames[i].byte_offset + frames[i].size;
+            current_end_byte = frame.byte_offset + frame.size;
+            current_crc_failures += crc_failed(frame);
         } else {
             runs.push(FrameRun {
                 start_byte: current_run_start_byte,
                 end_byte: current_end_byte,
                 is_valid: current_is_valid,
                 avg_entropy: current_entropy_sum / current_count as f64,
+                frame_count: current_count,
+                crc_failures: current_crc_failures,
             });
 
-            current_run_start_byte = frames[i].byte_offset;
-            current_is_valid = frames[i].is_valid;
-            current_entropy_sum = frames[i].entropy;
+            current_run_start_byte = frame.byte_offset;
+            current_is_valid = frame.is_valid;
+            current_entropy_sum = frame.entropy;
             current_count = 1;
-            current_end_byte = frames[i].byte_offset + frames[i].size;
+            current_end_byte = frame.byte_offset + frame.size;
+            current_crc_failures = crc_failed(frame);
         }
     }
 
@@ -77,7 +246,350 @@ pub fn group_into_runs(frames: &[FrameInfo]) -> Vec<FrameRun> {
         end_byte: current_end_byte,
         is_valid: current_is_valid,
         avg_entropy: current_entropy_sum / current_count as f64,
+        frame_count: current_count,
+        crc_failures: current_crc_failures,
     });
 
     runs
 }
+
+/// dump the run segmentation as json
+///
+/// same runs the contiguity graph draws, just for scripts
+pub fn write_runs_json(runs: &[FrameRun], output_path: &Path) -> Result<()> {
+    let writer = BufWriter::new(File::create(output_path)?);
+    serde_json::to_writer_pretty(writer, runs)?;
+    Ok(())
+}
+
+/// dump every frame as a csv row, for plotting it yourself
+///
+/// crc_ok is left empty for frames that don't carry a crc
+pub fn write_frames_csv(frames: &[FrameInfo], output_path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    writeln!(writer, "index,byte_offset,size,entropy,is_valid,crc_ok")?;
+    for (index, frame) in frames.iter().enumerate() {
+        let crc_ok = frame.crc_ok.map(|ok| ok.to_string()).unwrap_or_default();
+        writeln!(
+            writer,
+            "{},{},{},{:.4},{},{}",
+            index, frame.byte_offset, frame.size, frame.entropy, frame.is_valid, crc_ok
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// check the crc-16 of a layer III frame, if it has one
+///
+/// returns None when the protection bit says there's no crc
+/// (or the frame isn't something we know how to check)
+pub fn check_frame_crc(frame: &[u8]) -> Option<bool> {
+    if frame.len() < 6 || frame[0] != 0xFF || (frame[1] & 0xE0) != 0xE0 {
+        return None;
+    }
+
+    let header = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]);
+    let version = (header >> 19) & 0x3;
+    let layer = (header >> 17) & 0x3;
+    let protection_absent = (header >> 16) & 0x1;
+
+    // protection bit is inverted, 0 means a crc follows the header
+    if protection_absent == 1 || layer != 1 || version == 1 {
+        return None;
+    }
+
+    let side_info_len = side_info_len(header);
+
+    if frame.len() < 6 + side_info_len {
+        return Some(false);
+    }
+
+    let stored_crc = u16::from_be_bytes([frame[4], frame[5]]);
+
+    let mut crc = 0xFFFF_u16;
+    for &byte in frame[2..4].iter().chain(&frame[6..6 + side_info_len]) {
+        crc = crc16_update(crc, byte);
+    }
+
+    Some(crc == stored_crc)
+}
+
+/// bytes of layer III side info after the header (and crc)
+///
+/// mpeg1 has twice as much as mpeg2/2.5, mono roughly half of stereo
+pub fn side_info_len(header: u32) -> usize {
+    let version = (header >> 19) & 0x3;
+    let channel_mode = (header >> 6) & 0x3;
+    match (version == 3, channel_mode == 3) {
+        (true, true) => 17,
+        (true, false) => 32,
+        (false, true) => 9,
+        (false, false) => 17,
+    }
+}
+
+/// a header in the same format as `template` whose frame holds `min_size` bytes
+///
+/// version, layer, sample rate and channel mode are kept so the new
+/// frame fits in with the stream around it. crc and padding are
+/// switched off and the lowest bitrate that's big enough is used
+pub fn fit_frame_header(template: u32, min_size: usize) -> Option<(u32, FrameHeader)> {
+    (1..15).find_map(|bitrate_index: u32| {
+        let header = (template & !0x0001_F200) | 0x0001_0000 | (bitrate_index << 12);
+        parse_frame_header(&header.to_be_bytes())
+            .filter(|parsed| parsed.frame_size >= min_size)
+            .map(|parsed| (header, parsed))
+    })
+}
+
+/// frame count from a Xing or Info tag in the first frame, if it has one
+///
+/// the tag sits where the side info would end, same place encoders
+/// like lame put it. returns None when the frames field isn't flagged
+pub fn xing_frame_count(frame: &[u8]) -> Option<u32> {
+    let header = u32::from_be_bytes(frame.get(0..4)?.try_into().ok()?);
+    let offset = 4 + side_info_len(header);
+    let tag = frame.get(offset..offset + 12)?;
+
+    if &tag[0..4] != b"Xing" && &tag[0..4] != b"Info" {
+        return None;
+    }
+
+    let flags = u32::from_be_bytes([tag[4], tag[5], tag[6], tag[7]]);
+    if flags & 0x1 == 0 {
+        return None;
+    }
+    Some(u32::from_be_bytes([tag[8], tag[9], tag[10], tag[11]]))
+}
+
+fn crc16_update(mut crc: u16, byte: u8) -> u16 {
+    for bit in (0..8).rev() {
+        let data_bit = (byte >> bit) & 1 == 1;
+        let top_bit = crc & 0x8000 != 0;
+        crc <<= 1;
+        if data_bit != top_bit {
+            crc ^= 0x8005;
+        }
+    }
+    crc
+}
+
+/// size of a leading ID3v2 tag, 0 if there isn't one
+pub fn id3v2_size(buffer: &[u8]) -> usize {
+    if buffer.len() > 10 && &buffer[0..3] == b"ID3" {
+        let size = ((buffer[6] as usize) << 21)
+            | ((buffer[7] as usize) << 14)
+            | ((buffer[8] as usize) << 7)
+            | (buffer[9] as usize);
+        10 + size
+    } else {
+        0
+    }
+}
+
+/// the 128 byte ID3v1 tag at the end of the file, if there is one
+pub fn id3v1_tag(buffer: &[u8]) -> Option<&[u8]> {
+    let start = buffer.len().checked_sub(128)?;
+    let tag = &buffer[start..];
+    (&tag[0..3] == b"TAG").then_some(tag)
+}
+
+/// where the audio stops, before a trailing ID3v1 tag if there is one
+pub fn audio_end(buffer: &[u8]) -> usize {
+    match id3v1_tag(buffer) {
+        Some(tag) => {
+            debug!("ID3v1 tag at {}", buffer.len() - tag.len());
+            buffer.len() - tag.len()
+        }
+        None => buffer.len(),
+    }
+}
+
+/// the text fields of an ID3v1 tag
+#[derive(Debug, Clone)]
+pub struct Id3v1Fields {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub year: String,
+}
+
+/// pull the fixed width fields out of a 128 byte ID3v1 tag
+///
+/// fields are latin-1, padded with nulls or spaces
+pub fn parse_id3v1(tag: &[u8]) -> Id3v1Fields {
+    let field = |range: std::ops::Range<usize>| {
+        let text: String = tag[range]
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| b as char)
+            .collect();
+        text.trim_end().to_string()
+    };
+
+    Id3v1Fields {
+        title: field(3..33),
+        artist: field(33..63),
+        album: field(63..93),
+        year: field(93..97),
+    }
+}
+
+/// free-format frames can't be bigger than this, 640kbps at 8kHz is 5760
+const MAX_FREE_FORMAT_SIZE: usize = 8192;
+
+/// header bits that stay the same across a free-format stream:
+/// sync, version, layer, protection, bitrate index and sample rate
+const FREE_FORMAT_MASK: u32 = 0xFFFF_FC00;
+
+/// parse the header at `pos`, free-format frames included
+///
+/// the first free-format frame found fixes `free_size` for the rest of
+/// the stream, the format needs every frame to be the same size
+pub fn frame_header_at(
+    buffer: &[u8],
+    pos: usize,
+    free_size: &mut Option<usize>,
+) -> Option<FrameHeader> {
+    let bytes = buffer.get(pos..)?;
+    if let Some(header) = parse_frame_header(bytes) {
+        return Some(header);
+    }
+    if bytes.len() < 4 || (bytes[2] >> 4) != 0 {
+        return None;
+    }
+
+    if free_size.is_none() {
+        *free_size = free_format_size(bytes);
+        if let Some(size) = free_size {
+            debug!("free-format stream, {} byte frames", size);
+        }
+    }
+    parse_free_format_header(bytes, (*free_size)?)
+}
+
+/// unpadded size of a free-format frame, from the distance to the next
+/// header with the same fixed bits
+fn free_format_size(bytes: &[u8]) -> Option<usize> {
+    let header = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    // make sure it's otherwise a real header before scanning
+    parse_free_format_header(bytes, 1)?;
+
+    let padding = ((header >> 9) & 0x1) as usize;
+    let slot = if (header >> 17) & 0x3 == 3 { 4 } else { 1 };
+    let limit = bytes.len().min(MAX_FREE_FORMAT_SIZE + 4);
+
+    (4..limit.saturating_sub(3))
+        .find(|&next| {
+            let candidate = u32::from_be_bytes([
+                bytes[next],
+                bytes[next + 1],
+                bytes[next + 2],
+                bytes[next + 3],
+            ]);
+            candidate & FREE_FORMAT_MASK == header & FREE_FORMAT_MASK
+        })
+        .and_then(|next| next.checked_sub(padding * slot))
+}
+
+/// parse a frame header purely from the header bits
+///
+/// returns None if there's no sync word or any field is reserved/invalid.
+/// bitrate index 0 is free format, which needs the frame size from
+/// outside the header, see frame_header_at. bitrate index 15 is invalid
+/// and is always skipped
+pub fn parse_frame_header(bytes: &[u8]) -> Option<FrameHeader> {
+    parse_header(bytes, None)
+}
+
+/// parse a free-format (bitrate index 0) header, every frame is
+/// `free_size` bytes plus padding and the bitrate follows from that
+pub fn parse_free_format_header(bytes: &[u8], free_size: usize) -> Option<FrameHeader> {
+    parse_header(bytes, Some(free_size))
+}
+
+fn parse_header(bytes: &[u8], free_size: Option<usize>) -> Option<FrameHeader> {
+    if bytes.len() < 4 || bytes[0] != 0xFF || (bytes[1] & 0xE0) != 0xE0 {
+        return None;
+    }
+
+    let header = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+    let version = (header >> 19) & 0x3;
+    let layer = (header >> 17) & 0x3;
+    let bitrate_index = (header >> 12) & 0xF;
+    let sample_rate_index = (header >> 10) & 0x3;
+    let padding = (header >> 9) & 0x1;
+
+    if version == 1 || layer == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+        return None;
+    }
+    // free format only when the caller knows the frame size
+    if (bitrate_index == 0) != free_size.is_some() {
+        return None;
+    }
+
+    let sample_rates = match version {
+        0 => [11025, 12000, 8000],
+        2 => [22050, 24000, 16000],
+        3 => [44100, 48000, 32000],
+        _ => return None,
+    };
+    let sample_rate = sample_rates[sample_rate_index as usize];
+
+    // layer bits count down, 3 is layer I and 1 is layer III
+    let bitrates = match (version == 3, layer) {
+        (true, 3) => [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448, 0,
+        ],
+        (true, 2) => [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384, 0,
+        ],
+        (true, _) => [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 0,
+        ],
+        (false, 3) => [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256, 0,
+        ],
+        (false, _) => [
+            0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0,
+        ],
+    };
+
+    // layer II keeps 1152 samples in mpeg2/2.5, only layer III halves it
+    let samples_per_frame = match layer {
+        3 => 384,
+        2 => 1152,
+        _ if version == 3 => 1152,
+        _ => 576,
+    };
+
+    let (bitrate, frame_size) = match free_size {
+        Some(size) => {
+            let slot = if layer == 3 { 4 } else { 1 };
+            let bitrate = size * sample_rate / (samples_per_frame / 8);
+            (bitrate, size + padding as usize * slot)
+        }
+        None => {
+            let bitrate = bitrates[bitrate_index as usize] * 1000;
+            let frame_size = if layer == 3 {
+                (12 * bitrate / sample_rate + padding as usize) * 4
+            } else {
+                (samples_per_frame / 8 * bitrate) / sample_rate + padding as usize
+            };
+            (bitrate, frame_size)
+        }
+    };
+
+    if frame_size == 0 {
+        return None;
+    }
+
+    Some(FrameHeader {
+        bitrate: bitrate / 1000,
+        sample_rate,
+        samples_per_frame,
+        frame_size,
+    })
+}
@@ -0,0 +1,143 @@
+use crate::frame::{EntropyAnomaly, FrameInfo, SyncLossRegion, group_into_runs};
+use anyhow::Result;
+use colored::Colorize;
+use plotters::prelude::*;
+use std::path::Path;
+
+/// graph size the label and caption sizes were originally tuned for
+const BASE_GRAPH_SIZE: (u32, u32) = (7200, 3600);
+
+/// scale a pixel size tuned for the original graph to the requested one
+///
+/// uses whichever axis shrank more so text never outgrows the plot
+fn scale_to_graph(pixels: u32, graph_size: (u32, u32)) -> u32 {
+    let scale = (graph_size.0 as f64 / BASE_GRAPH_SIZE.0 as f64)
+        .min(graph_size.1 as f64 / BASE_GRAPH_SIZE.1 as f64);
+    ((pixels as f64 * scale).round() as u32).max(1)
+}
+
+/// frames as green/red runs by byte position with their entropy on top
+///
+/// sync loss regions from the raw file are shaded over the runs and
+/// entropy anomalies get a marker. analyze and repair both draw this
+pub fn generate_contiguity_graph(
+    frames: &[FrameInfo],
+    output_path: &Path,
+    graph_size: (u32, u32),
+    (sync_loss, audio_start, audio_len): (&[SyncLossRegion], usize, usize),
+    anomalies: &[EntropyAnomaly],
+) -> Result<()> {
+    let runs = group_into_runs(frames);
+    let px = |pixels: u32| scale_to_graph(pixels, graph_size);
+
+    let root = BitMapBackend::new(output_path, graph_size).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    // never wider than the audio between the tags
+    let total_bytes = if let Some(last) = frames.last() {
+        (last.byte_offset + last.size).min(audio_len)
+    } else {
+        return Ok(());
+    };
+
+    let max_entropy = frames.iter().map(|f| f.entropy).fold(0.0_f64, f64::max);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            "Frame Contiguity and Entropy by Bytes",
+            ("sans-serif", px(120)),
+        )
+        .margin(px(40))
+        .x_label_area_size(px(160))
+        .y_label_area_size(px(200))
+        .build_cartesian_2d(0..total_bytes, 0.0..max_entropy.max(8.0))?;
+
+    chart
+        .configure_mesh()
+        .label_style(("sans-serif", px(60)))
+        .axis_desc_style(("sans-serif", px(72)))
+        .x_desc("Byte Position")
+        .y_desc("Entropy (bits)")
+        .draw()?;
+
+    for run in &runs {
+        let color = if run.is_valid {
+            GREEN.mix(0.3)
+        } else {
+            RED.mix(0.3)
+        };
+
+        chart.draw_series(std::iter::once(Rectangle::new(
+            [(run.start_byte, 0.0), (run.end_byte, run.avg_entropy)],
+            color.filled(),
+        )))?;
+    }
+
+    let legend_len = px(80) as i32;
+
+    // the x axis counts packet bytes, which leave out the tag and any
+    // garbage symphonia skipped, so raw offsets are shifted back by
+    // everything lost before them. each band keeps its real width
+    let mut skipped = audio_start;
+    let y_max = max_entropy.max(8.0);
+    let sync_bands: Vec<_> = sync_loss
+        .iter()
+        .map(|region| {
+            let start = region.start.saturating_sub(skipped);
+            skipped += region.end - region.start;
+            Rectangle::new(
+                [(start, 0.0), (start + region.end - region.start, y_max)],
+                RED.mix(0.6).filled(),
+            )
+        })
+        .collect();
+    if !sync_bands.is_empty() {
+        chart
+            .draw_series(sync_bands)?
+            .label("Sync Loss")
+            .legend(move |(x, y)| {
+                Rectangle::new([(x, y - 5), (x + legend_len, y + 5)], RED.mix(0.6).filled())
+            });
+    }
+
+    chart
+        .draw_series(LineSeries::new(
+            frames
+                .iter()
+                .filter(|f| f.is_valid)
+                .map(|f| (f.byte_offset, f.entropy)),
+            &BLUE,
+        ))?
+        .label("Valid Frame Entropy")
+        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + legend_len, y)], BLUE));
+
+    if !anomalies.is_empty() {
+        let marker_size = px(24);
+        chart
+            .draw_series(anomalies.iter().map(|anomaly| {
+                TriangleMarker::new(
+                    (anomaly.byte_offset, anomaly.entropy),
+                    marker_size,
+                    MAGENTA.filled(),
+                )
+            }))?
+            .label("Entropy Anomaly")
+            .legend(move |(x, y)| TriangleMarker::new((x, y), marker_size, MAGENTA.filled()));
+    }
+
+    chart
+        .configure_series_labels()
+        .label_font(("sans-serif", px(60)))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    println!(
+        "{} {}",
+        "「graph saved」".green().bold(),
+        output_path.display().to_string().cyan()
+    );
+
+    Ok(())
+}
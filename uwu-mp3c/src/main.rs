@@ -1,17 +1,29 @@
 mod analyzer;
+mod art;
+mod batch;
+mod fingerprint;
 mod frame;
+mod graph;
 mod repair;
+mod wav;
 
 use anyhow::Result;
 use clap::Parser;
+use frame::ScanOptions;
 use log::info;
 use std::path::PathBuf;
+use wav::WavBitDepth;
 
 #[derive(Parser)]
 #[command(name = "uwu-mp3c")]
 #[command(about = "🌸 「mp3 corruption analyzer and repair tool」 🌸")]
 struct Cli {
-    #[arg(short = 'i', long, value_name = "FILE")]
+    #[arg(
+        short = 'i',
+        long,
+        value_name = "FILE",
+        help = "MP3 file, or a directory to run on every .mp3 inside it."
+    )]
     input: PathBuf,
 
     #[arg(
@@ -23,6 +35,124 @@ struct Cli {
         help = "Extract and repair MP3. Optionally specify output directory."
     )]
     extract: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the frame run segmentation to a JSON file."
+    )]
+    runs_json: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Treat frames with a bad CRC-16 as corrupt, even if they decode."
+    )]
+    validate_crc: bool,
+
+    #[arg(
+        long,
+        help = "Check every frame boundary using only header math (analyze only)."
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Stop decoding after N frames, results will be partial."
+    )]
+    frame_limit: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "16",
+        help = "Sample format for any WAV output."
+    )]
+    wav_bit_depth: WavBitDepth,
+
+    #[arg(
+        long,
+        help = "Print an acoustic fingerprint of the decoded audio (analyze only)."
+    )]
+    fingerprint: bool,
+
+    #[arg(
+        long,
+        value_name = "PX",
+        default_value_t = 1920,
+        help = "Width of the contiguity graph in pixels."
+    )]
+    graph_width: u32,
+
+    #[arg(
+        long,
+        value_name = "PX",
+        default_value_t = 1080,
+        help = "Height of the contiguity graph in pixels."
+    )]
+    graph_height: u32,
+
+    #[arg(
+        long,
+        help = "Leave ID3v1/ID3v2 tags out of the repaired MP3 (extract only)."
+    )]
+    strip_tags: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Also write the decoded audio as a WAV, with silence for corrupt frames (extract only)."
+    )]
+    wav: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Put a silent frame in place of each corrupt frame to keep timing (extract only)."
+    )]
+    fill_silence: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write per-frame offset, size, entropy and validity to a CSV (analyze only)."
+    )]
+    csv: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "SIGMA",
+        default_value_t = 3.0,
+        help = "Flag frames whose entropy is this many standard deviations from the running mean."
+    )]
+    entropy_sigma: f64,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write pictures embedded in the ID3v2 tag (cover art etc) to a directory."
+    )]
+    extract_art: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "fill_silence",
+        help = "Write corrupt frames into the output as they are instead of dropping them (extract only)."
+    )]
+    keep_corrupted_inline: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Where to write the contiguity graph PNG."
+    )]
+    graph: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "graph",
+        help = "Don't draw the contiguity graph."
+    )]
+    no_graph: bool,
 }
 
 fn main() -> Result<()> {
@@ -34,14 +164,40 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
+    let options = ScanOptions {
+        runs_json: cli.runs_json,
+        validate_crc: cli.validate_crc,
+        strict: cli.strict,
+        frame_limit: cli.frame_limit,
+        wav_bit_depth: cli.wav_bit_depth,
+        fingerprint: cli.fingerprint,
+        graph_width: cli.graph_width,
+        graph_height: cli.graph_height,
+        strip_tags: cli.strip_tags,
+        wav: cli.wav,
+        fill_silence: cli.fill_silence,
+        csv: cli.csv,
+        output_dir: None,
+        entropy_sigma: cli.entropy_sigma,
+        extract_art: cli.extract_art,
+        keep_corrupted_inline: cli.keep_corrupted_inline,
+        graph: cli.graph,
+        no_graph: cli.no_graph,
+    };
+
+    if cli.input.is_dir() {
+        info!("batch processing directory: {}", cli.input.display());
+        return batch::run_batch(&cli.input, cli.extract.as_deref(), &options);
+    }
+
     match cli.extract {
         None => {
             info!("analyzing mp3 file: {}", cli.input.display());
-            analyzer::analyze(&cli.input)?;
+            analyzer::analyze(&cli.input, &options)?;
         }
         Some(extract_path) => {
             info!("repairing mp3 file: {}", cli.input.display());
-            repair::repair(&cli.input, &extract_path)?;
+            repair::repair(&cli.input, &extract_path, &options)?;
         }
     }
 
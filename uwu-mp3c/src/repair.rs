@@ -1,20 +1,130 @@
-use crate::frame::{calculate_entropy, group_into_runs, FrameInfo};
-use anyhow::{anyhow, Result};
+use crate::analyzer::print_entropy_anomalies;
+use crate::art;
+use crate::frame::{
+    FrameCounts, FrameInfo, ScanOptions, audio_end, calculate_entropy, check_frame_crc,
+    find_entropy_anomalies, find_sync_loss, fit_frame_header, group_into_runs, id3v1_tag,
+    id3v2_size, parse_frame_header, side_info_len, write_runs_json,
+};
+use crate::graph::generate_contiguity_graph;
+use crate::wav::WavOutput;
+use anyhow::{Result, anyhow};
 use colored::Colorize;
 use log::debug;
-use plotters::prelude::*;
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
-use symphonia::core::audio::{AudioBufferRef, Signal};
-use symphonia::core::codecs::{CodecParameters, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer, Signal};
+use symphonia::core::codecs::{CODEC_TYPE_NULL, CodecParameters, Decoder, DecoderOptions};
 use symphonia::core::errors::Error as SymphoniaError;
-use symphonia::core::formats::FormatOptions;
+use symphonia::core::formats::{FormatOptions, FormatReader};
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 
-pub fn repair(input_path: &Path, extract_path: &str) -> Result<()> {
+/// if the repaired file re-decodes with fewer than this share of the
+/// frames we wrote, treat it as unrecoverable and salvage the pcm
+const MIN_VERIFIED_FRAME_RATIO: f64 = 0.1;
+
+/// what repair_mp3 put in the repaired file
+struct RepairedFrames {
+    frames_written: usize,
+    counts: FrameCounts,
+}
+
+/// the audio track of an mp3, ready to decode
+struct AudioTrack {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    codec_params: CodecParameters,
+    track_id: u32,
+}
+
+fn open_audio_track(path: &Path) -> Result<AudioTrack> {
+    let file = File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    hint.with_extension("mp3");
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow!("no audio track found"))?;
+    let codec_params = track.codec_params.clone();
+    let track_id = track.id;
+    let decoder =
+        symphonia::default::get_codecs().make(&codec_params, &DecoderOptions::default())?;
+
+    Ok(AudioTrack {
+        format,
+        decoder,
+        codec_params,
+        track_id,
+    })
+}
+
+/// pcm of the frames a repair pass walks, streamed into a wav
+///
+/// corrupt frames are stood in for by silence, so the samples
+/// line up with the original file's timing
+struct PcmSink {
+    wav: WavOutput,
+    channels: usize,
+    /// silence for a corrupt frame is as long as its header says, or
+    /// failing that as long as the last frame that decoded
+    last_frame_samples: usize,
+    decoded_frames: usize,
+}
+
+impl PcmSink {
+    fn create(
+        wav_path: &Path,
+        codec_params: &CodecParameters,
+        options: &ScanOptions,
+    ) -> Result<Self> {
+        let Some(sample_rate) = codec_params.sample_rate else {
+            return Err(anyhow!("no sample rate known, cannot write wav"));
+        };
+        let channels = codec_params.channels.map(|c| c.count()).unwrap_or(2);
+
+        Ok(PcmSink {
+            wav: WavOutput::create(
+                wav_path,
+                sample_rate,
+                channels as u16,
+                options.wav_bit_depth,
+            )?,
+            channels,
+            last_frame_samples: 1152,
+            decoded_frames: 0,
+        })
+    }
+
+    fn decoded(&mut self, decoded: AudioBufferRef) -> Result<()> {
+        self.last_frame_samples = decoded.frames();
+        self.decoded_frames += 1;
+        let mut sample_buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        sample_buf.copy_interleaved_ref(decoded);
+        self.wav.write_samples(sample_buf.samples())
+    }
+
+    fn corrupt(&mut self, frame: &[u8]) -> Result<()> {
+        let silent_samples = parse_frame_header(frame)
+            .map_or(self.last_frame_samples, |header| header.samples_per_frame);
+        self.wav.write_silence(silent_samples * self.channels)
+    }
+}
+
+pub fn repair(input_path: &Path, extract_path: &str, options: &ScanOptions) -> Result<FrameCounts> {
     println!(
         "{} {}",
         "「repairing」".cyan().bold(),
@@ -22,6 +132,11 @@ pub fn repair(input_path: &Path, extract_path: &str) -> Result<()> {
     );
     println!();
 
+    if let Some(art_dir) = &options.extract_art {
+        art::extract_art(input_path, art_dir)?;
+        println!();
+    }
+
     let reported_duration = mp3_duration::from_path(input_path)?;
     println!(
         "{} {:.3}s",
@@ -53,7 +168,61 @@ pub fn repair(input_path: &Path, extract_path: &str) -> Result<()> {
     let corrupted_frames_dir = output_dir.join("corrupted_frames");
     fs::create_dir_all(&corrupted_frames_dir)?;
 
-    repair_mp3(input_path, &output_path, &corrupted_frames_dir, &output_dir)?;
+    let graph_path = options
+        .graph
+        .clone()
+        .unwrap_or_else(|| output_dir.join("contiguity_entropy.png"));
+
+    let repaired = repair_mp3(
+        input_path,
+        &output_path,
+        &corrupted_frames_dir,
+        &graph_path,
+        options,
+    )?;
+
+    if let Some(wav_path) = &options.wav {
+        println!(
+            "{} {}",
+            "「wav saved」".green().bold(),
+            wav_path.display().to_string().cyan()
+        );
+    }
+
+    let (verified_frames, failed_frames) = match verify_repaired(&output_path) {
+        Ok(counts) => counts,
+        Err(err) => {
+            debug!("repaired file failed to re-decode: {:?}", err);
+            (0, 0)
+        }
+    };
+    debug!(
+        "verified {} of {} written frames",
+        verified_frames, repaired.frames_written
+    );
+
+    println!();
+    println!(
+        "{} {} of {} frames decode",
+        "「verification」".cyan().bold(),
+        verified_frames.to_string().yellow(),
+        repaired.frames_written.to_string().yellow()
+    );
+    if failed_frames > 0 {
+        println!(
+            "{} {} frames in the repaired file still fail to decode",
+            "「still corrupt」".red().bold(),
+            failed_frames.to_string().yellow()
+        );
+    }
+
+    if (verified_frames as f64) < repaired.frames_written as f64 * MIN_VERIFIED_FRAME_RATIO
+        || verified_frames == 0
+    {
+        let wav_path = output_dir.join(output_filename.replace("-repaired.mp3", "-salvaged.wav"));
+        salvage_to_wav(input_path, &wav_path, options)?;
+        return Ok(repaired.counts);
+    }
 
     let repaired_duration = mp3_duration::from_path(&output_path)?;
     println!();
@@ -63,35 +232,222 @@ pub fn repair(input_path: &Path, extract_path: &str) -> Result<()> {
         repaired_duration.as_secs_f64()
     );
 
+    Ok(repaired.counts)
+}
+
+/// decode the repaired file again, returns (frames that decode, frames that don't)
+fn verify_repaired(output_path: &Path) -> Result<(usize, usize)> {
+    let AudioTrack {
+        mut format,
+        mut decoder,
+        track_id,
+        ..
+    } = open_audio_track(output_path)?;
+
+    let mut valid_frames = 0;
+    let mut failed_frames = 0;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::ResetRequired) | Err(SymphoniaError::IoError(_)) => break,
+            Err(err) => {
+                debug!("error reading packet during verification: {:?}", err);
+                continue;
+            }
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        match decoder.decode(&packet) {
+            Ok(_) => valid_frames += 1,
+            Err(err) => {
+                debug!("repaired frame failed to decode: {:?}", err);
+                failed_frames += 1;
+            }
+        }
+    }
+
+    Ok((valid_frames, failed_frames))
+}
+
+/// last resort when the rebuilt mp3 is no good, keep the decoded pcm
+///
+/// pcm isn't held on to during repair, so the original file is
+/// decoded a second time here, straight into the wav
+fn salvage_to_wav(input_path: &Path, wav_path: &Path, options: &ScanOptions) -> Result<()> {
+    println!();
+    println!(
+        "{} repaired mp3 does not decode, it is probably unrecoverable",
+        "「verification failed」".red().bold()
+    );
+
+    let AudioTrack {
+        mut format,
+        mut decoder,
+        codec_params,
+        track_id,
+    } = open_audio_track(input_path)?;
+    let mut pcm = PcmSink::create(wav_path, &codec_params, options)?;
+
+    let mut frame_count = 0;
+    loop {
+        if options
+            .frame_limit
+            .is_some_and(|limit| frame_count >= limit)
+        {
+            break;
+        }
+
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::ResetRequired) | Err(SymphoniaError::IoError(_)) => break,
+            Err(err) => {
+                debug!("error reading packet during salvage: {:?}", err);
+                continue;
+            }
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+        frame_count += 1;
+
+        // same rule as repair_mp3 for which frames become silence
+        let crc_failed = options.validate_crc && check_frame_crc(&packet.data) == Some(false);
+        match decoder.decode(&packet) {
+            Ok(decoded) if !crc_failed => pcm.decoded(decoded)?,
+            _ => pcm.corrupt(&packet.data)?,
+        }
+    }
+
+    let decoded_frames = pcm.decoded_frames;
+    pcm.wav.finish()?;
+
+    if decoded_frames == 0 {
+        fs::remove_file(wav_path)?;
+        println!(
+            "{} no decodable audio to salvage",
+            "「salvage failed」".red().bold()
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{} {}",
+        "「pcm salvaged」".green().bold(),
+        wav_path.display().to_string().cyan()
+    );
+
     Ok(())
 }
 
-fn create_xing_header(
-    frame_count: u32,
-    audio_data_size: u32,
-    _codec_params: &CodecParameters,
-) -> Vec<u8> {
+/// xing flag bits, one per optional field that follows the flags
+const XING_FRAMES: u32 = 0x1;
+const XING_BYTES: u32 = 0x2;
+const XING_TOC: u32 = 0x4;
+
+/// mpeg1 layer III, 64kbps, 44.1kHz, stereo. only used when there's
+/// no valid frame to copy the format from
+const FALLBACK_XING_HEADER: u32 = 0xFFFB_5000;
+
+/// 100 entry seek table, entry i is how far into the audio data
+/// (in 256ths) the frame at i percent of the way through starts
+fn xing_toc(frame_sizes: &[u32]) -> [u8; 100] {
+    let mut toc = [0u8; 100];
+    let total: u64 = frame_sizes.iter().map(|&size| size as u64).sum();
+    if total == 0 {
+        return toc;
+    }
+
+    let mut offsets = Vec::with_capacity(frame_sizes.len());
+    let mut offset = 0u64;
+    for &size in frame_sizes {
+        offsets.push(offset);
+        offset += size as u64;
+    }
+
+    for (percent, entry) in toc.iter_mut().enumerate() {
+        let frame = percent * frame_sizes.len() / 100;
+        *entry = (offsets[frame] * 256 / total).min(255) as u8;
+    }
+    toc
+}
+
+/// sync, version, layer, sample rate and channel mode bits of a header
+const FORMAT_BITS: u32 = 0xFFFE_0CC0;
+
+/// a silent frame to stand in for a corrupt one
+///
+/// the format comes from a good frame. if the corrupt frame's own
+/// header still parses and agrees on the format its bitrate and
+/// padding are kept too, so the frame is the same size as the one
+/// it replaces. all zero side info decodes as silence
+fn silent_frame(template: u32, corrupt: &[u8]) -> Option<Vec<u8>> {
+    let mut header = template;
+    if let Some(bytes) = corrupt.get(0..4) {
+        let corrupt_header = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if parse_frame_header(bytes).is_some()
+            && corrupt_header & FORMAT_BITS == template & FORMAT_BITS
+        {
+            header = corrupt_header;
+        }
+    }
+    // no crc, there's nothing for it to protect
+    header |= 0x0001_0000;
+
+    let parsed = parse_frame_header(&header.to_be_bytes())?;
+    let mut frame = vec![0u8; parsed.frame_size];
+    frame[0..4].copy_from_slice(&header.to_be_bytes());
+    Some(frame)
+}
+
+/// build the Xing frame that goes in front of the repaired audio
+///
+/// its header copies the format of `first_frame` so players don't
+/// see the stream change under them after the first frame
+fn create_xing_header(frame_sizes: &[u32], first_frame: Option<&[u8]>) -> Vec<u8> {
+    let frame_count = frame_sizes.len() as u32;
+    let audio_data_size: u32 = frame_sizes.iter().sum();
+    // a toc over zero frames would point nowhere, leave it out
+    let flags = if frame_sizes.is_empty() {
+        XING_FRAMES | XING_BYTES
+    } else {
+        XING_FRAMES | XING_BYTES | XING_TOC
+    };
+
+    let template = first_frame
+        .filter(|frame| parse_frame_header(frame).is_some())
+        .map(|frame| u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]))
+        .unwrap_or(FALLBACK_XING_HEADER);
+    let tag_len = if flags & XING_TOC != 0 { 116 } else { 16 };
+    let (header, frame_size) = fit_frame_header(template, 4 + side_info_len(template) + tag_len)
+        .map(|(header, parsed)| (header, parsed.frame_size))
+        .unwrap_or((FALLBACK_XING_HEADER, 208));
+    debug!("xing frame header {:08X}, {} bytes", header, frame_size);
+
     let mut xing_frame = Vec::new();
 
-    xing_frame.push(0xFF);
-    xing_frame.push(0xFB);
-    xing_frame.push(0x50);
-    xing_frame.push(0x00);
+    xing_frame.extend_from_slice(&header.to_be_bytes());
 
-    for _ in 0..32 {
+    for _ in 0..side_info_len(header) {
         xing_frame.push(0x00);
     }
 
     xing_frame.extend_from_slice(b"Xing");
 
-    xing_frame.extend_from_slice(&[0x00, 0x00, 0x00, 0x03]);
+    xing_frame.extend_from_slice(&flags.to_be_bytes());
 
     xing_frame.extend_from_slice(&frame_count.to_be_bytes());
 
     xing_frame.extend_from_slice(&audio_data_size.to_be_bytes());
 
-    let target_size = 208;
-    while xing_frame.len() < target_size {
+    if flags & XING_TOC != 0 {
+        xing_frame.extend_from_slice(&xing_toc(frame_sizes));
+    }
+
+    while xing_frame.len() < frame_size {
         xing_frame.push(0x00);
     }
 
@@ -102,41 +458,48 @@ fn repair_mp3(
     input_path: &Path,
     output_path: &Path,
     corrupted_frames_dir: &Path,
-    graph_dir: &Path,
-) -> Result<()> {
-    let file = File::open(input_path)?;
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
-
-    let mut hint = Hint::new();
-    hint.with_extension("mp3");
-
-    let meta_opts: MetadataOptions = Default::default();
-    let format_opts: FormatOptions = Default::default();
-
-    let probed = symphonia::default::get_probe().format(&hint, mss, &format_opts, &meta_opts)?;
-
-    let mut format = probed.format;
-
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .ok_or_else(|| anyhow!("no audio track found"))?;
-
-    let codec_params = track.codec_params.clone();
-    let track_id = track.id;
-
-    let decoder_opts: DecoderOptions = Default::default();
-    let mut decoder = symphonia::default::get_codecs().make(&codec_params, &decoder_opts)?;
+    graph_path: &Path,
+    options: &ScanOptions,
+) -> Result<RepairedFrames> {
+    let AudioTrack {
+        mut format,
+        mut decoder,
+        codec_params,
+        track_id,
+    } = open_audio_track(input_path)?;
+    // only --wav wants the pcm here, salvage decodes again if it comes to that
+    let mut pcm = match &options.wav {
+        Some(wav_path) => Some(PcmSink::create(wav_path, &codec_params, options)?),
+        None => None,
+    };
 
-    let mut valid_frames: Vec<Vec<u8>> = Vec::new();
+    // valid frames, plus silent ones with --fill-silence or the corrupt
+    // ones themselves with --keep-corrupted-inline
+    let mut output_frames: Vec<Vec<u8>> = Vec::new();
     let mut frame_infos: Vec<FrameInfo> = Vec::new();
     let mut frame_count = 0;
     let mut total_samples = 0u64;
     let mut byte_offset = 0;
     let mut corrupted_count = 0;
+    let mut crc_failures = 0;
+    let mut format_template: Option<u32> = None;
+    // corrupt frames seen before any good one had a format to copy
+    let mut pending_silence = 0;
+    let mut silence_frames = 0;
+    let mut inline_frames = 0;
+
+    let mut hit_frame_limit = false;
 
     loop {
+        if options
+            .frame_limit
+            .is_some_and(|limit| frame_count >= limit)
+        {
+            debug!("frame limit reached after {} frames", frame_count);
+            hit_frame_limit = true;
+            break;
+        }
+
         let packet = match format.next_packet() {
             Ok(packet) => packet,
             Err(SymphoniaError::ResetRequired) | Err(SymphoniaError::IoError(_)) => {
@@ -154,30 +517,76 @@ fn repair_mp3(
 
         let entropy = calculate_entropy(&packet.data);
         let size = packet.data.len();
+        // crc is always checked, --validate-crc decides if a bad one drops the frame
+        let crc_ok = check_frame_crc(&packet.data);
+        if crc_ok == Some(false) {
+            debug!("frame {} failed crc check", frame_count);
+            crc_failures += 1;
+        }
+        let crc_failed = options.validate_crc && crc_ok == Some(false);
 
         match decoder.decode(&packet) {
-            Ok(decoded) => {
-                valid_frames.push(packet.data.to_vec());
+            Ok(decoded) if !crc_failed => {
+                if format_template.is_none() && packet.data.len() >= 4 {
+                    let data = &packet.data;
+                    let template = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                    format_template = Some(template);
+                    for _ in 0..pending_silence {
+                        if let Some(frame) = silent_frame(template, &[]) {
+                            output_frames.push(frame);
+                            silence_frames += 1;
+                        }
+                    }
+                }
+                output_frames.push(packet.data.to_vec());
                 frame_infos.push(FrameInfo {
                     is_valid: true,
                     entropy,
                     size,
                     byte_offset,
+                    crc_ok,
                 });
 
-                if let AudioBufferRef::F32(buf) = decoded {
+                if let AudioBufferRef::F32(buf) = &decoded {
                     total_samples += buf.frames() as u64 * buf.spec().channels.count() as u64;
                 }
+
+                if let Some(pcm) = &mut pcm {
+                    pcm.decoded(decoded)?;
+                }
             }
-            Err(err) => {
-                debug!("skipping corrupted packet {}: {:?}", frame_count, err);
-                corrupted_count += 1;
+            result => {
+                // frames dropped only for their crc are counted under crc failures
+                if let Err(err) = result {
+                    debug!("skipping corrupted packet {}: {:?}", frame_count, err);
+                    corrupted_count += 1;
+                }
+
+                if let Some(pcm) = &mut pcm {
+                    pcm.corrupt(&packet.data)?;
+                }
+
+                if options.keep_corrupted_inline {
+                    output_frames.push(packet.data.to_vec());
+                    inline_frames += 1;
+                } else if options.fill_silence {
+                    match format_template {
+                        Some(template) => {
+                            if let Some(frame) = silent_frame(template, &packet.data) {
+                                output_frames.push(frame);
+                                silence_frames += 1;
+                            }
+                        }
+                        None => pending_silence += 1,
+                    }
+                }
 
                 frame_infos.push(FrameInfo {
                     is_valid: false,
                     entropy,
                     size,
                     byte_offset,
+                    crc_ok,
                 });
 
                 let frame_path = corrupted_frames_dir.join(format!("frame_{:06}.bin", frame_count));
@@ -190,8 +599,17 @@ fn repair_mp3(
         frame_count += 1;
     }
 
-    let valid_frame_count = valid_frames.len();
-    let audio_data_size: u32 = valid_frames.iter().map(|f| f.len() as u32).sum();
+    let valid_frame_count = output_frames.len() - silence_frames - inline_frames;
+    let dropped_count = frame_count - valid_frame_count;
+    let audio_data_size: u32 = output_frames.iter().map(|f| f.len() as u32).sum();
+
+    if hit_frame_limit {
+        println!(
+            "{} stopped after {} frames, results are partial",
+            "「frame limit」".yellow().bold(),
+            frame_count.to_string().yellow()
+        );
+    }
 
     println!(
         "{} {}",
@@ -210,6 +628,22 @@ fn repair_mp3(
             "「corrupted frames」".red().bold(),
             corrupted_count.to_string().yellow()
         );
+    }
+
+    if crc_failures > 0 {
+        println!(
+            "{} {}{}",
+            "「crc failures」".red().bold(),
+            crc_failures.to_string().yellow(),
+            if options.validate_crc {
+                ""
+            } else {
+                " (still counted valid, use --validate-crc to drop them)"
+            }
+        );
+    }
+
+    if dropped_count > 0 {
         println!(
             "{} {}",
             "「saved to」".cyan().bold(),
@@ -217,23 +651,80 @@ fn repair_mp3(
         );
     }
 
+    let anomalies = find_entropy_anomalies(&frame_infos, options.entropy_sigma);
+    print_entropy_anomalies(&anomalies, options.entropy_sigma);
+
+    if inline_frames > 0 {
+        println!(
+            "{} {} corrupt frames left in the output",
+            "「kept inline」".yellow().bold(),
+            inline_frames.to_string().yellow()
+        );
+    }
+
+    if silence_frames > 0 {
+        println!(
+            "{} {}",
+            "「silence inserted」".cyan().bold(),
+            silence_frames.to_string().yellow()
+        );
+    }
+
     debug!(
         "creating Xing header: {} frames, {} bytes",
-        valid_frame_count, audio_data_size
+        output_frames.len(),
+        audio_data_size
+    );
+    let frame_sizes: Vec<u32> = output_frames.iter().map(|f| f.len() as u32).collect();
+    let xing_header = create_xing_header(
+        &frame_sizes,
+        output_frames.first().map(|frame| frame.as_slice()),
+    );
+
+    // tags are copied byte for byte, symphonia only hands us audio packets
+    let input_bytes = fs::read(input_path)?;
+    let (id3v2, id3v1) = if options.strip_tags {
+        (&[][..], None)
+    } else {
+        (
+            &input_bytes[..id3v2_size(&input_bytes).min(input_bytes.len())],
+            id3v1_tag(&input_bytes),
+        )
+    };
+    debug!(
+        "keeping {} bytes of ID3v2, ID3v1 present: {}",
+        id3v2.len(),
+        id3v1.is_some()
     );
-    let xing_header = create_xing_header(valid_frame_count as u32, audio_data_size, &codec_params);
 
     let output_file = File::create(output_path)?;
     let mut writer = BufWriter::new(output_file);
 
+    writer.write_all(id3v2)?;
     writer.write_all(&xing_header)?;
 
-    for frame in &valid_frames {
+    for frame in &output_frames {
         writer.write_all(frame)?;
     }
 
+    if let Some(tag) = id3v1 {
+        writer.write_all(tag)?;
+    }
+
     writer.flush()?;
 
+    let kept_tags: Vec<&str> = [(!id3v2.is_empty(), "ID3v2"), (id3v1.is_some(), "ID3v1")]
+        .into_iter()
+        .filter_map(|(present, name)| present.then_some(name))
+        .collect();
+    if !kept_tags.is_empty() {
+        println!(
+            "{} {}",
+            "「tags kept」".cyan().bold(),
+            kept_tags.join(", ").yellow()
+        );
+    }
+
     println!(
         "{} {}",
         "「repaired file」".green().bold(),
@@ -245,8 +736,7 @@ fn repair_mp3(
             .channels
             .map(|c| c.count() as f64)
             .unwrap_or(2.0);
-        let actual_duration_secs =
-            total_samples as f64 / (sample_rate as f64 * channel_count);
+        let actual_duration_secs = total_samples as f64 / (sample_rate as f64 * channel_count);
         println!(
             "{} {:.3}s",
             "「decoded duration」".cyan().bold(),
@@ -254,77 +744,40 @@ fn repair_mp3(
         );
     }
 
-    println!();
-    println!("{}", "「generating analysis graph」".magenta().bold());
-    generate_contiguity_graph(&frame_infos, graph_dir)?;
-
-    Ok(())
-}
-
-fn generate_contiguity_graph(frames: &[FrameInfo], output_dir: &Path) -> Result<()> {
-    let runs = group_into_runs(frames);
-    let output_path = output_dir.join("contiguity_entropy.png");
-
-    let root = BitMapBackend::new(&output_path, (7200, 3600)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let total_bytes = if let Some(last) = frames.last() {
-        last.byte_offset + last.size
-    } else {
-        return Ok(());
-    };
-
-    let max_entropy = frames.iter().map(|f| f.entropy).fold(0.0_f64, f64::max);
-
-    let mut chart = ChartBuilder::on(&root)
-        .caption("Frame Contiguity and Entropy by Bytes", ("sans-serif", 120))
-        .margin(40)
-        .x_label_area_size(160)
-        .y_label_area_size(200)
-        .build_cartesian_2d(0..total_bytes, 0.0..max_entropy.max(8.0))?;
-
-    chart
-        .configure_mesh()
-        .x_desc("Byte Position")
-        .y_desc("Entropy (bits)")
-        .draw()?;
-
-    for run in &runs {
-        let color = if run.is_valid {
-            GREEN.mix(0.3)
-        } else {
-            RED.mix(0.3)
-        };
+    if let Some(runs_path) = &options.runs_json {
+        write_runs_json(&group_into_runs(&frame_infos), runs_path)?;
+        println!(
+            "{} {}",
+            "「runs saved」".green().bold(),
+            runs_path.display().to_string().cyan()
+        );
+    }
 
-        chart.draw_series(std::iter::once(Rectangle::new(
-            [(run.start_byte, 0.0), (run.end_byte, run.avg_entropy)],
-            color.filled(),
-        )))?;
+    if !options.no_graph {
+        println!();
+        println!("{}", "「generating analysis graph」".magenta().bold());
+        let audio_start = id3v2_size(&input_bytes);
+        let audio_len = audio_end(&input_bytes).saturating_sub(audio_start);
+        let (_, sync_loss) = find_sync_loss(&input_bytes);
+        generate_contiguity_graph(
+            &frame_infos,
+            graph_path,
+            (options.graph_width, options.graph_height),
+            (&sync_loss, audio_start, audio_len),
+            &anomalies,
+        )?;
     }
 
-    chart
-        .draw_series(LineSeries::new(
-            frames
-                .iter()
-                .filter(|f| f.is_valid)
-                .map(|f| (f.byte_offset, f.entropy)),
-            &BLUE,
-        ))?
-        .label("Valid Frame Entropy")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 80, y)], &BLUE));
-
-    chart
-        .configure_series_labels()
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()?;
-
-    root.present()?;
-    println!(
-        "{} {}",
-        "「graph saved」".green().bold(),
-        output_path.display().to_string().cyan()
-    );
+    if let Some(pcm) = pcm {
+        pcm.wav.finish()?;
+    }
 
-    Ok(())
+    Ok(RepairedFrames {
+        frames_written: output_frames.len(),
+        counts: FrameCounts {
+            total: frame_count,
+            valid: valid_frame_count,
+            corrupted: dropped_count,
+        },
+    })
 }
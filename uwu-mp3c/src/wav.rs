@@ -0,0 +1,129 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// sample format for wav output
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum WavBitDepth {
+    #[default]
+    #[value(name = "16")]
+    Pcm16,
+    #[value(name = "24")]
+    Pcm24,
+    #[value(name = "32f")]
+    Float32,
+}
+
+impl WavBitDepth {
+    fn bits_per_sample(self) -> u16 {
+        match self {
+            WavBitDepth::Pcm16 => 16,
+            WavBitDepth::Pcm24 => 24,
+            WavBitDepth::Float32 => 32,
+        }
+    }
+
+    fn sample_format(self) -> SampleFormat {
+        match self {
+            WavBitDepth::Pcm16 | WavBitDepth::Pcm24 => SampleFormat::Int,
+            WavBitDepth::Float32 => SampleFormat::Float,
+        }
+    }
+}
+
+/// tiny xorshift rng so dithering doesn't need a dependency
+struct Dither {
+    state: u32,
+}
+
+impl Dither {
+    fn new() -> Self {
+        Dither { state: 0x9E37_79B9 }
+    }
+
+    fn next_unit(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as f32 / u32::MAX as f32
+    }
+
+    /// triangular noise in the range (-1, 1) lsb
+    fn tpdf(&mut self) -> f32 {
+        self.next_unit() - self.next_unit()
+    }
+}
+
+/// a WAV at the given bit depth, written as the samples come in
+///
+/// nothing is held in memory, hound fills in the header sizes
+/// when the output is finished
+pub struct WavOutput {
+    writer: WavWriter<BufWriter<File>>,
+    bit_depth: WavBitDepth,
+    dither: Dither,
+}
+
+impl WavOutput {
+    pub fn create(
+        output_path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        bit_depth: WavBitDepth,
+    ) -> Result<Self> {
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: bit_depth.bits_per_sample(),
+            sample_format: bit_depth.sample_format(),
+        };
+        Ok(WavOutput {
+            writer: WavWriter::create(output_path, spec)?,
+            bit_depth,
+            dither: Dither::new(),
+        })
+    }
+
+    /// append interleaved f32 samples
+    ///
+    /// 16 bit output gets TPDF dither, 24 bit is just rounded
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        for &sample in samples {
+            self.write_sample(sample)?;
+        }
+        Ok(())
+    }
+
+    /// append `count` zero samples, across all channels
+    pub fn write_silence(&mut self, count: usize) -> Result<()> {
+        for _ in 0..count {
+            self.write_sample(0.0)?;
+        }
+        Ok(())
+    }
+
+    fn write_sample(&mut self, sample: f32) -> Result<()> {
+        match self.bit_depth {
+            WavBitDepth::Pcm16 => {
+                let scaled = sample * i16::MAX as f32 + self.dither.tpdf();
+                let value = scaled.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+                self.writer.write_sample(value)?;
+            }
+            WavBitDepth::Pcm24 => {
+                let max = 8_388_607.0_f32;
+                let value = (sample * max).round().clamp(-max - 1.0, max) as i32;
+                self.writer.write_sample(value)?;
+            }
+            WavBitDepth::Float32 => self.writer.write_sample(sample)?,
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.writer.finalize()?;
+        Ok(())
+    }
+}
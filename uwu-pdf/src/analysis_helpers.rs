@@ -1,15 +1,22 @@
 use colored::Colorize;
 use log::{debug, trace};
 use lopdf::Object;
-use std::collections::HashMap;
+use lopdf::xref::XrefEntry;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct PdfStats {
+    pub pdf_version: String,
+    pub header_offset: usize,
+    pub catalog_version: Option<String>,
     pub object_count: usize,
     pub page_count: usize,
     pub images: usize,
     pub fonts: usize,
     pub streams: usize,
+    pub object_streams: usize,
+    pub objstm_objects: usize,
     pub dictionaries: usize,
     pub arrays: usize,
     pub strings: usize,
@@ -23,9 +30,117 @@ pub struct PdfStats {
     pub form_xobjects: usize,
     pub filter_types: HashMap<String, usize>,
     pub color_spaces: HashMap<String, usize>,
+    pub rendering_intents: HashMap<String, usize>,
+    pub filter_chains: HashMap<String, usize>,
+    pub unsupported_filters: HashMap<String, usize>,
 }
 
-pub fn count_object_types(object: &Object, stats: &mut PdfStats) {
+/// a stream's decoded size and whether the catalog reaches it
+#[derive(Debug, Serialize)]
+pub struct StreamSize {
+    pub object_id: (u32, u16),
+    pub size: usize,
+    pub referenced: bool,
+}
+
+/// filters lopdf can undo when decompressing a stream
+const DECODABLE_FILTERS: &[&str] = &[
+    "FlateDecode",
+    "LZWDecode",
+    "ASCII85Decode",
+    "ASCIIHexDecode",
+    "RunLengthDecode",
+];
+
+/// image formats we write out as-is, so not decoding them is fine
+const PASSTHROUGH_FILTERS: &[&str] = &["DCTDecode", "JPXDecode", "JBIG2Decode"];
+
+/// every filter applied to a stream, in decode order
+pub fn filter_chain(dict: &lopdf::Dictionary) -> Vec<String> {
+    match dict.get(b"Filter") {
+        Ok(Object::Name(name)) => vec![String::from_utf8_lossy(name).to_string()],
+        Ok(Object::Array(arr)) => arr
+            .iter()
+            .filter_map(|item| item.as_name().ok())
+            .map(|name| String::from_utf8_lossy(name).to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// filters (and decode parms) in a chain that we can't actually undo
+///
+/// anything flagged here means extracted content is probably still encoded
+pub fn unsupported_filters(dict: &lopdf::Dictionary) -> Vec<String> {
+    let mut unsupported: Vec<String> = filter_chain(dict)
+        .into_iter()
+        .filter(|f| !DECODABLE_FILTERS.contains(&f.as_str()))
+        .filter(|f| !PASSTHROUGH_FILTERS.contains(&f.as_str()))
+        .collect();
+
+    let parms: Vec<&lopdf::Dictionary> = match dict.get(b"DecodeParms") {
+        Ok(Object::Dictionary(parms)) => vec![parms],
+        Ok(Object::Array(arr)) => arr.iter().filter_map(|p| p.as_dict().ok()).collect(),
+        _ => Vec::new(),
+    };
+
+    for parm in parms {
+        if let Ok(predictor) = parm.get(b"Predictor").and_then(|p| p.as_i64())
+            && !(predictor == 1 || predictor == 2 || (10..=15).contains(&predictor))
+        {
+            unsupported.push(format!("Predictor {}", predictor));
+        }
+        if parm.has(b"JBIG2Globals") {
+            unsupported.push("JBIG2Globals".to_string());
+        }
+    }
+
+    unsupported
+}
+
+/// count every object in a loaded document, nothing is printed
+pub fn gather_pdf_stats(doc: &lopdf::Document) -> PdfStats {
+    let catalog_version = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Version").ok())
+        .and_then(|version| version.as_name().ok())
+        .map(|version| String::from_utf8_lossy(version).to_string());
+
+    let mut stats = PdfStats {
+        pdf_version: doc.version.clone(),
+        catalog_version,
+        object_count: doc.objects.len(),
+        page_count: doc.get_pages().len(),
+        ..Default::default()
+    };
+
+    debug!(
+        "analyzing {} objects across {} pages",
+        stats.object_count, stats.page_count
+    );
+
+    for object in doc.objects.values() {
+        count_object_types(object, &mut stats, doc);
+    }
+
+    // lopdf may drop the containers once their objects are loaded,
+    // the xref still says which objects were compressed
+    if stats.object_streams == 0 {
+        let mut containers = HashSet::new();
+        for entry in doc.reference_table.entries.values() {
+            if let XrefEntry::Compressed { container, .. } = entry {
+                containers.insert(*container);
+                stats.objstm_objects += 1;
+            }
+        }
+        stats.object_streams = containers.len();
+    }
+
+    stats
+}
+
+pub fn count_object_types(object: &Object, stats: &mut PdfStats, doc: &lopdf::Document) {
     match object {
         Object::Boolean(_) => {
             stats.booleans += 1;
@@ -61,27 +176,35 @@ pub fn count_object_types(object: &Object, stats: &mut PdfStats) {
         }
 
         Object::Stream(stream) => {
+            let dict = &stream.dict;
+
+            // containers get their own count so they aren't double counted
+            if matches!(dict.get(b"Type"), Ok(Object::Name(t)) if t == b"ObjStm") {
+                stats.object_streams += 1;
+                stats.objstm_objects += dict
+                    .get(b"N")
+                    .and_then(|n| n.as_i64())
+                    .map_or(0, |n| n.max(0) as usize);
+                trace!("found object stream");
+                return;
+            }
+
             stats.streams += 1;
             trace!("found stream object");
 
-            let dict = &stream.dict;
+            let chain = filter_chain(dict);
+            for name in &chain {
+                *stats.filter_types.entry(name.clone()).or_insert(0) += 1;
+            }
 
-            if let Ok(filter) = dict.get(b"Filter") {
-                let filter_name = match filter {
-                    Object::Name(name) => Some(String::from_utf8_lossy(name).to_string()),
-                    Object::Array(arr) if !arr.is_empty() => {
-                        if let Object::Name(name) = &arr[0] {
-                            Some(String::from_utf8_lossy(name).to_string())
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                };
+            if chain.len() > 1 {
+                trace!("stream has filter chain {:?}", chain);
+                *stats.filter_chains.entry(chain.join(" > ")).or_insert(0) += 1;
+            }
 
-                if let Some(name) = filter_name {
-                    *stats.filter_types.entry(name).or_insert(0) += 1;
-                }
+            for unsupported in unsupported_filters(dict) {
+                debug!("stream uses unsupported filter {}", unsupported);
+                *stats.unsupported_filters.entry(unsupported).or_insert(0) += 1;
             }
 
             if let Ok(Object::Name(subtype)) = dict.get(b"Subtype") {
@@ -89,11 +212,18 @@ pub fn count_object_types(object: &Object, stats: &mut PdfStats) {
                     stats.images += 1;
                     debug!("found image stream");
 
-                    if let Ok(Object::Name(cs)) = dict.get(b"ColorSpace") {
-                        let cs_name = String::from_utf8_lossy(cs).to_string();
+                    if let Ok(cs) = dict.get(b"ColorSpace")
+                        && let Some(cs_name) = color_space_base_name(cs, doc)
+                    {
                         trace!("Image color space: {}", cs_name);
                         *stats.color_spaces.entry(cs_name).or_insert(0) += 1;
                     }
+
+                    if let Ok(Object::Name(intent)) = dict.get(b"Intent") {
+                        let intent_name = String::from_utf8_lossy(intent).to_string();
+                        trace!("Image rendering intent: {}", intent_name);
+                        *stats.rendering_intents.entry(intent_name).or_insert(0) += 1;
+                    }
                 }
             }
 
@@ -128,13 +258,55 @@ pub fn count_object_types(object: &Object, stats: &mut PdfStats) {
     }
 }
 
-pub fn print_pdf_stats(stats: PdfStats) {
+/// get the base family of a colorspace
+///
+/// simple ones are just a name, the rest are arrays like
+/// [/ICCBased 5 0 R] or [/CalRGB <<...>>] where the first entry
+/// says what kind it is
+fn color_space_base_name(cs: &Object, doc: &lopdf::Document) -> Option<String> {
+    match cs {
+        Object::Name(name) => Some(String::from_utf8_lossy(name).to_string()),
+        Object::Array(arr) => match arr.first() {
+            Some(Object::Name(name)) => Some(String::from_utf8_lossy(name).to_string()),
+            _ => None,
+        },
+        Object::Reference(id) => match doc.get_object(*id) {
+            Ok(resolved @ (Object::Name(_) | Object::Array(_))) => {
+                color_space_base_name(resolved, doc)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn print_pdf_stats(stats: &PdfStats) {
     println!("{}", "「pdf stats」".cyan().bold());
+    println!("  {}: {}", "PDF Version".green(), stats.pdf_version);
+    println!("  {}: {}", "Header Offset".green(), stats.header_offset);
+    if let Some(catalog_version) = &stats.catalog_version
+        && *catalog_version != stats.pdf_version
+    {
+        println!(
+            "  {} header says {} but the catalog /Version says {}",
+            "「version mismatch」".yellow().bold(),
+            stats.pdf_version.yellow(),
+            catalog_version.yellow()
+        );
+    }
     println!("  {}: {}", "Pages".green(), stats.page_count);
     println!("  {}: {}", "Total Objects".green(), stats.object_count);
     println!("  {}: {}", "Images".green(), stats.images);
     println!("  {}: {}", "Fonts".green(), stats.fonts);
     println!("  {}: {}", "Streams".green(), stats.streams);
+    if stats.object_streams > 0 {
+        println!(
+            "  {} {} object streams holding {} compressed objects",
+            "「object streams」".cyan(),
+            stats.object_streams.to_string().yellow(),
+            stats.objstm_objects.to_string().yellow()
+        );
+    }
     println!("  {}: {}", "Dictionaries".green(), stats.dictionaries);
     println!("  {}: {}", "Arrays".green(), stats.arrays);
     println!("  {}: {}", "Strings".green(), stats.strings);
@@ -154,10 +326,283 @@ pub fn print_pdf_stats(stats: PdfStats) {
         }
     }
 
+    if !stats.filter_chains.is_empty() {
+        println!("  {}:", "Filter Chains".green());
+        for (chain, count) in &stats.filter_chains {
+            println!("    {}: {}", chain.cyan(), count);
+        }
+    }
+
+    if !stats.unsupported_filters.is_empty() {
+        println!("  {}:", "Unsupported Filters".yellow());
+        for (filter, count) in &stats.unsupported_filters {
+            println!("    {}: {}", filter.yellow(), count);
+        }
+    }
+
     if !stats.color_spaces.is_empty() {
         println!("  {}:", "Color Spaces".green());
         for (cs, count) in &stats.color_spaces {
             println!("    {}: {}", cs.cyan(), count);
         }
     }
+
+    if !stats.rendering_intents.is_empty() {
+        println!("  {}:", "Rendering Intents".green());
+        for (intent, count) in &stats.rendering_intents {
+            println!("    {}: {}", intent.cyan(), count);
+        }
+    }
+}
+
+/// the top biggest streams by decoded size, largest first
+///
+/// streams that fail to decode are counted by their raw size
+pub fn largest_streams(
+    doc: &lopdf::Document,
+    referenced_ids: &HashSet<(u32, u16)>,
+    top: usize,
+) -> Vec<StreamSize> {
+    let mut sizes: Vec<StreamSize> = doc
+        .objects
+        .iter()
+        .filter_map(|(object_id, object)| {
+            let Object::Stream(stream) = object else {
+                return None;
+            };
+            let size = stream
+                .decompressed_content()
+                .map(|content| content.len())
+                .unwrap_or(stream.content.len());
+            Some(StreamSize {
+                object_id: *object_id,
+                size,
+                referenced: referenced_ids.contains(object_id),
+            })
+        })
+        .collect();
+
+    sizes.sort_by_key(|stream| std::cmp::Reverse(stream.size));
+    sizes.truncate(top);
+    sizes
+}
+
+pub fn print_largest_streams(streams: &[StreamSize]) {
+    if streams.is_empty() {
+        return;
+    }
+
+    println!("{}", "「largest streams」".cyan().bold());
+    for stream in streams {
+        let reference_note = if stream.referenced {
+            "referenced".green()
+        } else {
+            "unreferenced".red().bold()
+        };
+        println!(
+            "  Object {} {}: {} bytes ({})",
+            stream.object_id.0.to_string().cyan(),
+            stream.object_id.1,
+            stream.size.to_string().yellow(),
+            reference_note
+        );
+    }
+    println!();
+}
+
+/// one csv row per object, header included
+///
+/// object_id,generation,type,subtype,filter,stream_len,referenced
+pub fn object_table_csv(doc: &lopdf::Document, referenced_ids: &HashSet<(u32, u16)>) -> String {
+    let mut csv = String::from("object_id,generation,type,subtype,filter,stream_len,referenced\n");
+
+    for (object_id, object) in doc.objects.iter() {
+        let dict = match object {
+            Object::Dictionary(dict) => Some(dict),
+            Object::Stream(stream) => Some(&stream.dict),
+            _ => None,
+        };
+        let name = |key: &[u8]| {
+            dict.and_then(|d| d.get(key).ok())
+                .and_then(|value| value.as_name().ok())
+                .map(|value| String::from_utf8_lossy(value).to_string())
+        };
+
+        let type_name = name(b"Type").unwrap_or_else(|| object.enum_variant().to_string());
+        let subtype = name(b"Subtype").unwrap_or_default();
+        let (filter, stream_len) = match object {
+            Object::Stream(stream) => (
+                filter_chain(&stream.dict).join(" > "),
+                stream.content.len().to_string(),
+            ),
+            _ => (String::new(), String::new()),
+        };
+
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            object_id.0,
+            object_id.1,
+            csv_field(&type_name),
+            csv_field(&subtype),
+            csv_field(&filter),
+            stream_len,
+            referenced_ids.contains(object_id)
+        ));
+    }
+
+    csv
+}
+
+/// quote a csv value if it needs it
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// how deep the catalog tree goes before we stop descending
+const MAX_TREE_DEPTH: usize = 8;
+
+/// catalog entries worth showing in the tree, in display order
+const CATALOG_TREE_KEYS: &[&[u8]] = &[
+    b"Pages",
+    b"Outlines",
+    b"Names",
+    b"Dests",
+    b"AcroForm",
+    b"OpenAction",
+    b"AA",
+    b"Metadata",
+    b"StructTreeRoot",
+];
+
+/// print an indented map of the catalog and what it references
+///
+/// every object is only expanded once, so /Parent back-links and
+/// other cycles just show up as "seen"
+pub fn print_catalog_tree(doc: &lopdf::Document) {
+    println!("{}", "「catalog tree」".cyan().bold());
+
+    let root = match doc.trailer.get(b"Root") {
+        Ok(root) => root,
+        Err(_) => {
+            println!("  {}", "no /Root in trailer".yellow());
+            println!();
+            return;
+        }
+    };
+
+    let mut visited = HashSet::new();
+    print_tree_node(doc, "Root", root, 0, &mut visited);
+    println!();
+}
+
+fn print_tree_node(
+    doc: &lopdf::Document,
+    label: &str,
+    object: &Object,
+    depth: usize,
+    visited: &mut HashSet<(u32, u16)>,
+) {
+    let indent = "  ".repeat(depth + 1);
+
+    let (resolved, id_label) = match object {
+        Object::Reference(id) => {
+            let id_label = format!("{} {} R", id.0, id.1);
+            if !visited.insert(*id) {
+                println!(
+                    "{}{} {} {}",
+                    indent,
+                    format!("/{}", label).green(),
+                    id_label.cyan(),
+                    "(seen)".dimmed()
+                );
+                return;
+            }
+            match doc.get_object(*id) {
+                Ok(resolved) => (resolved, id_label),
+                Err(_) => {
+                    println!(
+                        "{}{} {} {}",
+                        indent,
+                        format!("/{}", label).green(),
+                        id_label.cyan(),
+                        "(missing)".red()
+                    );
+                    return;
+                }
+            }
+        }
+        other => (other, "direct".to_string()),
+    };
+
+    let dict = match resolved {
+        Object::Dictionary(dict) => Some(dict),
+        Object::Stream(stream) => Some(&stream.dict),
+        _ => None,
+    };
+
+    let type_name = dict
+        .and_then(|d| d.get(b"Type").ok())
+        .and_then(|t| t.as_name().ok())
+        .map(|t| String::from_utf8_lossy(t).to_string())
+        .unwrap_or_else(|| resolved.enum_variant().to_string());
+
+    println!(
+        "{}{} {} {}",
+        indent,
+        format!("/{}", label).green(),
+        id_label.cyan(),
+        format!("({})", type_name).yellow()
+    );
+
+    let Some(dict) = dict else {
+        return;
+    };
+
+    if depth >= MAX_TREE_DEPTH {
+        trace!("catalog tree depth limit hit at /{}", label);
+        return;
+    }
+
+    if depth == 0 {
+        for key in CATALOG_TREE_KEYS {
+            if let Ok(value) = dict.get(key) {
+                print_tree_node(
+                    doc,
+                    &String::from_utf8_lossy(key),
+                    value,
+                    depth + 1,
+                    visited,
+                );
+            }
+        }
+        return;
+    }
+
+    if type_name == "Pages" {
+        if let Ok(Object::Array(kids)) = dict.get(b"Kids") {
+            for kid in kids {
+                print_tree_node(doc, "Kids", kid, depth + 1, visited);
+            }
+        }
+        return;
+    }
+
+    for (key, value) in dict.iter() {
+        if key.as_slice() == b"Parent" || key.as_slice() == b"P" {
+            continue;
+        }
+        if let Object::Reference(_) = value {
+            print_tree_node(
+                doc,
+                &String::from_utf8_lossy(key),
+                value,
+                depth + 1,
+                visited,
+            );
+        }
+    }
 }
@@ -0,0 +1,118 @@
+use colored::Colorize;
+use lopdf::Object;
+
+use crate::analysis_helpers::filter_chain;
+
+/// render an object roughly the way it would appear in the file
+///
+/// dictionaries and arrays are spread over indented lines,
+/// stream content is left out
+pub fn format_object(object: &Object, depth: usize) -> String {
+    let indent = "  ".repeat(depth + 1);
+    let closing_indent = "  ".repeat(depth);
+
+    match object {
+        Object::Null => "null".to_string(),
+        Object::Boolean(value) => value.to_string(),
+        Object::Integer(value) => value.to_string(),
+        Object::Real(value) => value.to_string(),
+        Object::Name(name) => format!("/{}", String::from_utf8_lossy(name)),
+        Object::String(bytes, _) => format_string(bytes),
+        Object::Reference(id) => format!("{} {} R", id.0, id.1),
+        Object::Array(items) => {
+            if items.is_empty() {
+                return "[]".to_string();
+            }
+            let mut out = String::from("[\n");
+            for item in items {
+                out.push_str(&format!("{}{}\n", indent, format_object(item, depth + 1)));
+            }
+            out.push_str(&format!("{}]", closing_indent));
+            out
+        }
+        Object::Dictionary(dict) => format_dictionary(dict, depth),
+        Object::Stream(stream) => format!("{} stream", format_dictionary(&stream.dict, depth)),
+    }
+}
+
+fn format_dictionary(dict: &lopdf::Dictionary, depth: usize) -> String {
+    if dict.is_empty() {
+        return "<< >>".to_string();
+    }
+
+    let indent = "  ".repeat(depth + 1);
+    let mut out = String::from("<<\n");
+    for (key, value) in dict.iter() {
+        out.push_str(&format!(
+            "{}/{} {}\n",
+            indent,
+            String::from_utf8_lossy(key),
+            format_object(value, depth + 1)
+        ));
+    }
+    out.push_str(&format!("{}>>", "  ".repeat(depth)));
+    out
+}
+
+/// printable strings as (literal), anything else as <hex>
+fn format_string(bytes: &[u8]) -> String {
+    if bytes
+        .iter()
+        .all(|b| b.is_ascii_graphic() || *b == b' ' || *b == b'\n' || *b == b'\r' || *b == b'\t')
+    {
+        let text = String::from_utf8_lossy(bytes)
+            .replace('\\', "\\\\")
+            .replace('(', "\\(")
+            .replace(')', "\\)");
+        format!("({})", text)
+    } else {
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("<{}>", hex)
+    }
+}
+
+/// the /Length a stream claims, following an indirect reference
+pub fn declared_length(doc: &lopdf::Document, stream: &lopdf::Stream) -> Option<i64> {
+    match stream.dict.get(b"Length").ok()? {
+        Object::Integer(length) => Some(*length),
+        Object::Reference(id) => doc.get_object(*id).ok()?.as_i64().ok(),
+        _ => None,
+    }
+}
+
+/// header for a dumped object: id, type and the object itself
+pub fn print_object_dump(doc: &lopdf::Document, object_id: (u32, u16), object: &Object) {
+    println!(
+        "{} {} {} {}",
+        "「object」".cyan().bold(),
+        format!("{} {} obj", object_id.0, object_id.1).green(),
+        "type".cyan(),
+        object.enum_variant().yellow()
+    );
+    println!("{}", format_object(object, 0));
+
+    let Object::Stream(stream) = object else {
+        println!();
+        return;
+    };
+
+    let filters = filter_chain(&stream.dict);
+    println!(
+        "  {} {}",
+        "Filters:".green(),
+        if filters.is_empty() {
+            "none".to_string()
+        } else {
+            filters.join(" -> ")
+        }
+    );
+    println!(
+        "  {} {}",
+        "Declared length:".green(),
+        declared_length(doc, stream)
+            .map(|length| length.to_string())
+            .unwrap_or_else(|| "missing".to_string())
+    );
+    println!("  {} {}", "Actual length:".green(), stream.content.len());
+    println!();
+}
@@ -0,0 +1,181 @@
+use colored::Colorize;
+use log::{debug, info, trace, warn};
+use lopdf::Object;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::extraction_helpers::ByteBudget;
+use crate::metadata_helpers::decode_text_string;
+
+/// name trees deeper than this are almost certainly malicious
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+/// an attachment from the /EmbeddedFiles name tree
+pub struct EmbeddedFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// walk /Names/EmbeddedFiles and pull out every /EF /F stream
+pub fn collect_embedded_files(doc: &lopdf::Document) -> Vec<EmbeddedFile> {
+    let mut files = Vec::new();
+
+    let Some(tree) = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| resolve_dict(doc, catalog.get(b"Names").ok()))
+        .and_then(|names| resolve_dict(doc, names.get(b"EmbeddedFiles").ok()))
+    else {
+        return files;
+    };
+
+    let mut entries = Vec::new();
+    let mut visited = HashSet::new();
+    walk_name_tree(doc, tree, 0, &mut visited, &mut entries);
+    debug!("found {} entries in EmbeddedFiles tree", entries.len());
+
+    for (key, filespec) in entries {
+        let Some(filespec) = resolve_dict(doc, Some(filespec)) else {
+            continue;
+        };
+        let Some(embedded) = resolve_dict(doc, filespec.get(b"EF").ok()) else {
+            trace!("filespec {} has no /EF", key);
+            continue;
+        };
+        let stream = match embedded.get(b"F") {
+            Ok(Object::Reference(id)) => doc.get_object(*id).and_then(|o| o.as_stream()).ok(),
+            Ok(Object::Stream(stream)) => Some(stream),
+            _ => None,
+        };
+        let Some(stream) = stream else {
+            continue;
+        };
+
+        let name = [&b"UF"[..], &b"F"[..]]
+            .iter()
+            .find_map(|field| match filespec.get(field) {
+                Ok(Object::String(bytes, _)) => Some(decode_text_string(bytes)),
+                _ => None,
+            })
+            .unwrap_or(key);
+
+        files.push(EmbeddedFile {
+            name,
+            data: stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone()),
+        });
+    }
+
+    files
+}
+
+/// write every embedded file to output_dir/embedded
+pub fn extract_embedded_files(
+    doc: &lopdf::Document,
+    output_dir: &Path,
+    budget: &mut ByteBudget,
+) -> Result<usize, String> {
+    let files = collect_embedded_files(doc);
+    if files.is_empty() {
+        return Ok(0);
+    }
+
+    let embedded_dir = output_dir.join("embedded");
+    if let Err(e) = fs::create_dir_all(&embedded_dir) {
+        warn!("failed to create embedded directory: {}", e);
+        return Ok(0);
+    }
+
+    let mut count = 0;
+    let mut used_names = HashSet::new();
+
+    for (index, file) in files.iter().enumerate() {
+        let mut filename = sanitize_filename(&file.name);
+        if !used_names.insert(filename.clone()) {
+            filename = format!("{}_{}", index, filename);
+        }
+
+        budget.reserve(&filename, file.data.len())?;
+        if let Err(e) = fs::write(embedded_dir.join(&filename), &file.data) {
+            warn!("failed to write embedded file {}: {}", filename, e);
+        } else {
+            println!(
+                "  {} {} ({} bytes)",
+                "「embedded file」".green().bold(),
+                filename.cyan(),
+                file.data.len().to_string().yellow()
+            );
+            count += 1;
+        }
+    }
+
+    info!("extracted {} embedded files", count);
+    Ok(count)
+}
+
+fn walk_name_tree<'a>(
+    doc: &'a lopdf::Document,
+    node: &'a lopdf::Dictionary,
+    depth: usize,
+    visited: &mut HashSet<(u32, u16)>,
+    entries: &mut Vec<(String, &'a Object)>,
+) {
+    if depth > MAX_NAME_TREE_DEPTH {
+        warn!("EmbeddedFiles name tree is too deep, stopping");
+        return;
+    }
+
+    if let Ok(Object::Array(names)) = node.get(b"Names") {
+        for pair in names.chunks_exact(2) {
+            if let Object::String(key, _) = &pair[0] {
+                entries.push((decode_text_string(key), &pair[1]));
+            }
+        }
+    }
+
+    if let Ok(Object::Array(kids)) = node.get(b"Kids") {
+        for kid in kids {
+            if let Object::Reference(id) = kid
+                && !visited.insert(*id)
+            {
+                continue;
+            }
+            if let Some(kid) = resolve_dict(doc, Some(kid)) {
+                walk_name_tree(doc, kid, depth + 1, visited, entries);
+            }
+        }
+    }
+}
+
+fn resolve_dict<'a>(
+    doc: &'a lopdf::Document,
+    object: Option<&'a Object>,
+) -> Option<&'a lopdf::Dictionary> {
+    match object? {
+        Object::Dictionary(dict) => Some(dict),
+        Object::Reference(id) => doc.get_dictionary(*id).ok(),
+        _ => None,
+    }
+}
+
+/// keep only the last path component and safe characters
+fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let sanitized: String = base
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    match sanitized.trim_matches('.') {
+        "" => "attachment.bin".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
@@ -1,16 +1,173 @@
+use clap::ValueEnum;
 use colored::Colorize;
-use image::{GrayImage, ImageBuffer, RgbImage};
+use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage, RgbaImage};
+use indicatif::{ProgressBar, ProgressStyle};
 use log::{debug, info, trace, warn};
 use lopdf::Object;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
 
+use crate::analysis_helpers::{csv_field, filter_chain, unsupported_filters};
+use crate::embedded_file_helpers::extract_embedded_files;
+use crate::font_helpers::extract_fonts;
+use crate::inline_image_helpers::extract_inline_images;
+use crate::metadata_helpers::extract_metadata;
 use crate::pdf_pre_parse_sec_checks::PreParseResults;
+use crate::rich_media_helpers::extract_rich_media;
+use crate::text_helpers::extract_page_text;
+use crate::xfa_helpers::extract_xfa;
 
 pub struct ExtractionCounts {
     pub text: usize,
     pub images: usize,
     pub binary: usize,
+    pub inline_images: usize,
+    pub unchanged: usize,
+    pub too_small: usize,
+    pub rich_media: usize,
+    pub page_text: usize,
+    pub embedded: usize,
+    pub fonts: usize,
+    pub metadata: usize,
+    pub xfa: usize,
+    /// images skipped with --dedup because an identical one was written
+    pub duplicate_images: usize,
+    pub bytes_written: u64,
+}
+
+/// the categories --only can narrow extraction down to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExtractKind {
+    Text,
+    Image,
+    Binary,
+    Font,
+    Embedded,
+}
+
+/// how extract_pdf_streams picks and records streams
+pub struct StreamOptions<'a> {
+    /// streams whose content hash is in here are skipped
+    pub base_hashes: Option<&'a HashSet<u64>>,
+    pub min_size: usize,
+    pub hash: bool,
+    pub show_progress: bool,
+    /// write each distinct image once
+    pub dedup: bool,
+    /// empty means extract everything
+    pub only: &'a [ExtractKind],
+}
+
+impl StreamOptions<'_> {
+    fn wants(&self, kind: ExtractKind) -> bool {
+        self.only.is_empty() || self.only.contains(&kind)
+    }
+}
+
+/// running total of bytes written by extract
+///
+/// every writer reserves its bytes here first, a write that would
+/// push the total past limit is refused and extraction stops there
+pub struct ByteBudget {
+    pub limit: u64,
+    pub written: u64,
+}
+
+impl ByteBudget {
+    pub fn new(limit: u64) -> Self {
+        Self { limit, written: 0 }
+    }
+
+    pub(crate) fn reserve(&mut self, filename: &str, len: usize) -> Result<(), String> {
+        let total = self.written + len as u64;
+        if total > self.limit {
+            return Err(format!(
+                "writing {} would bring output to {} bytes, over the --max-total-bytes limit of {}",
+                filename, total, self.limit
+            ));
+        }
+        self.written = total;
+        Ok(())
+    }
+}
+
+/// rows of manifest.csv, only kept when --hash is on
+#[derive(Default)]
+pub struct Manifest {
+    rows: Vec<String>,
+}
+
+impl Manifest {
+    /// hash what we tried to write and note the failure if there was one
+    fn record(
+        &mut self,
+        filename: &str,
+        object_id: &(u32, u16),
+        data: &[u8],
+        result: &io::Result<()>,
+    ) -> String {
+        let sha256 = format!("{:x}", Sha256::digest(data));
+        let error = match result {
+            Ok(()) => String::new(),
+            Err(e) => csv_field(&e.to_string()),
+        };
+        self.push_row(filename, object_id, data, &sha256, &error);
+        sha256
+    }
+
+    /// a stream that wasn't written because it matched one that was
+    fn record_duplicate(
+        &mut self,
+        filename: &str,
+        object_id: &(u32, u16),
+        data: &[u8],
+        original: &str,
+    ) -> String {
+        let sha256 = format!("{:x}", Sha256::digest(data));
+        let note = csv_field(&format!("duplicate of {}", original));
+        self.push_row(filename, object_id, data, &sha256, &note);
+        sha256
+    }
+
+    fn push_row(
+        &mut self,
+        filename: &str,
+        object_id: &(u32, u16),
+        data: &[u8],
+        sha256: &str,
+        note: &str,
+    ) {
+        self.rows.push(format!(
+            "{},{}_{},{},{},{}",
+            csv_field(filename),
+            object_id.0,
+            object_id.1,
+            data.len(),
+            sha256,
+            note
+        ));
+    }
+
+    pub fn write(&self, output_dir: &Path) -> io::Result<()> {
+        let mut csv = String::from("filename,object_id,size,sha256,note\n");
+        for row in &self.rows {
+            csv.push_str(row);
+            csv.push('\n');
+        }
+        fs::write(output_dir.join("manifest.csv"), csv)
+    }
+}
+
+/// the sha256 suffix for an extraction console line
+fn hash_note(sha256: Option<&str>) -> String {
+    sha256
+        .map(|hash| format!(" {}", hash.dimmed()))
+        .unwrap_or_default()
 }
 
 pub fn print_extraction_header(input_file: &PathBuf, output_dir: &PathBuf) {
@@ -25,18 +182,23 @@ pub fn print_extraction_header(input_file: &PathBuf, output_dir: &PathBuf) {
 }
 
 /// pull any padding added before or after boundary headers
-pub fn extract_padding(output_dir: &PathBuf, pre_parse_results: &PreParseResults) {
+pub fn extract_padding(
+    output_dir: &PathBuf,
+    pre_parse_results: &PreParseResults,
+    budget: &mut ByteBudget,
+) -> Result<(), String> {
     if let Some(ref prepended_data) = pre_parse_results.prepended_data {
         debug!(
             "Found {} bytes of prepended data before PDF header",
             prepended_data.len()
         );
+        budget.reserve("prepended.bin", prepended_data.len())?;
         let prepend_path = output_dir.join("prepended.bin");
-        if let Err(e) = fs::write(&prepend_path, prepended_data) {
+        fs::write(&prepend_path, prepended_data).map_err(|e| {
             warn!("Failed to write prepended data: {}", e);
-        } else {
-            info!("Extracted prepended data ({} bytes)", prepended_data.len());
-        }
+            e.to_string()
+        })?;
+        info!("Extracted prepended data ({} bytes)", prepended_data.len());
     }
 
     if let Some(ref appended_data) = pre_parse_results.appended_data {
@@ -44,16 +206,48 @@ pub fn extract_padding(output_dir: &PathBuf, pre_parse_results: &PreParseResults
             "Found {} bytes of appended data after EOF marker",
             appended_data.len()
         );
+        budget.reserve("appended.bin", appended_data.len())?;
         let append_path = output_dir.join("appended.bin");
-        if let Err(e) = fs::write(&append_path, appended_data) {
+        fs::write(&append_path, appended_data).map_err(|e| {
             warn!("Failed to write appended data: {}", e);
-        } else {
-            info!("Extracted appended data ({} bytes)", appended_data.len());
-        }
+            e.to_string()
+        })?;
+        info!("Extracted appended data ({} bytes)", appended_data.len());
     }
+
+    Ok(())
+}
+
+/// hash the raw content of every stream in a document
+///
+/// used with --diff-base so we can skip anything the reference already has
+pub fn hash_stream_contents(doc: &lopdf::Document) -> HashSet<u64> {
+    doc.objects
+        .values()
+        .filter_map(|object| match object {
+            Object::Stream(stream) => Some(content_hash(&stream.content)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn content_hash(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
 }
 
-pub fn extract_pdf_streams(doc: &lopdf::Document, output_dir: &PathBuf) -> ExtractionCounts {
+/// write out every stream, then run the extra passes
+///
+/// with --only, kinds that weren't asked for are skipped and their
+/// directories never created. metadata and xfa only come out when
+/// nothing was filtered
+pub fn extract_pdf_streams(
+    doc: &lopdf::Document,
+    output_dir: &PathBuf,
+    options: &StreamOptions,
+    budget: &mut ByteBudget,
+) -> Result<ExtractionCounts, String> {
     info!("Starting stream extraction from PDF");
     debug!("Total objects in PDF: {}", doc.objects.len());
 
@@ -61,47 +255,205 @@ pub fn extract_pdf_streams(doc: &lopdf::Document, output_dir: &PathBuf) -> Extra
     let images_dir = output_dir.join("images");
     let binary_dir = output_dir.join("binary");
 
-    let _ = fs::create_dir_all(&text_dir);
-    let _ = fs::create_dir_all(&images_dir);
-    let _ = fs::create_dir_all(&binary_dir);
+    for (kind, dir) in [
+        (ExtractKind::Text, &text_dir),
+        (ExtractKind::Image, &images_dir),
+        (ExtractKind::Binary, &binary_dir),
+    ] {
+        if options.wants(kind) {
+            let _ = fs::create_dir_all(dir);
+        }
+    }
 
     let mut counts = ExtractionCounts {
         text: 0,
         images: 0,
         binary: 0,
+        inline_images: 0,
+        unchanged: 0,
+        too_small: 0,
+        rich_media: 0,
+        page_text: 0,
+        embedded: 0,
+        fonts: 0,
+        metadata: 0,
+        xfa: 0,
+        duplicate_images: 0,
+        bytes_written: 0,
+    };
+    let mut manifest = options.hash.then(Manifest::default);
+    let mut seen_images = options.dedup.then(HashMap::new);
+    let mut aborted = None;
+
+    let progress = if options.show_progress {
+        let bar = ProgressBar::new(doc.objects.len() as u64);
+        if let Ok(style) =
+            ProgressStyle::with_template("{bar:30.cyan/blue} {pos}/{len} objects {msg}")
+        {
+            bar.set_style(style);
+        }
+        bar
+    } else {
+        ProgressBar::hidden()
     };
 
     for (object_id, object) in doc.objects.iter() {
+        progress.inc(1);
+        progress.set_message(format!(
+            "text {} images {} binary {}",
+            counts.text, counts.images, counts.binary
+        ));
+
         if let Object::Stream(stream) = object {
-            if let Ok(content) = stream.decompressed_content() {
-                let dict = &stream.dict;
+            if let Some(hashes) = options.base_hashes
+                && hashes.contains(&content_hash(&stream.content))
+            {
+                trace!("skipping unchanged object {}_{}", object_id.0, object_id.1);
+                counts.unchanged += 1;
+                continue;
+            }
 
-                if let Ok(Object::Name(subtype)) = dict.get(b"Subtype") {
-                    if subtype == b"Image" {
-                        extract_and_save_image(stream, object_id, &images_dir, &mut counts.images);
-                        continue;
-                    }
+            if let Ok(content) = stream.decompressed_content() {
+                if content.len() < options.min_size {
+                    trace!(
+                        "skipping object {}_{}: {} bytes is below min size",
+                        object_id.0,
+                        object_id.1,
+                        content.len()
+                    );
+                    counts.too_small += 1;
+                    continue;
                 }
 
-                if is_text_content(&content) {
-                    extract_and_save_text(&content, object_id, &text_dir, &mut counts.text);
+                let is_image = matches!(
+                    stream.dict.get(b"Subtype"),
+                    Ok(Object::Name(subtype)) if subtype == b"Image"
+                );
+                let kind = if is_image {
+                    ExtractKind::Image
+                } else if is_text_content(&content) {
+                    ExtractKind::Text
                 } else {
-                    extract_and_save_binary(&content, object_id, &binary_dir, &mut counts.binary);
+                    ExtractKind::Binary
+                };
+                if !options.wants(kind) {
+                    continue;
+                }
+
+                // the bar is cleared while the per-file lines print
+                let result = progress.suspend(|| match kind {
+                    ExtractKind::Image => extract_and_save_image(
+                        extract_image_data(stream, doc),
+                        object_id,
+                        &images_dir,
+                        &mut counts,
+                        seen_images.as_mut(),
+                        manifest.as_mut(),
+                        budget,
+                    ),
+                    ExtractKind::Text => extract_and_save_text(
+                        &content,
+                        object_id,
+                        &text_dir,
+                        &mut counts.text,
+                        manifest.as_mut(),
+                        budget,
+                    ),
+                    _ => extract_and_save_binary(
+                        &content,
+                        object_id,
+                        &binary_dir,
+                        &mut counts.binary,
+                        manifest.as_mut(),
+                        budget,
+                    ),
+                });
+
+                if let Err(e) = result {
+                    aborted = Some(e);
+                    break;
+                }
+            } else {
+                let unsupported = unsupported_filters(&stream.dict);
+                if !unsupported.is_empty() {
+                    progress.suspend(|| {
+                        println!(
+                            "  {} object {}_{} uses {}",
+                            "「undecodable」".yellow().bold(),
+                            object_id.0,
+                            object_id.1,
+                            unsupported.join(", ").yellow()
+                        )
+                    });
                 }
             }
         }
     }
 
-    counts
+    progress.finish_and_clear();
+
+    if aborted.is_none() {
+        let extras = extract_extras(doc, output_dir, &images_dir, options, budget, &mut counts);
+        aborted = extras.err();
+    }
+    counts.bytes_written = budget.written;
+
+    if let Some(manifest) = manifest {
+        if let Err(e) = manifest.write(output_dir) {
+            warn!("failed to write manifest.csv: {}", e);
+        } else {
+            info!("wrote manifest with {} rows", manifest.rows.len());
+        }
+    }
+
+    match aborted {
+        Some(e) => Err(e),
+        None => Ok(counts),
+    }
 }
 
+/// everything that isn't a plain stream object, after the object walk
+fn extract_extras(
+    doc: &lopdf::Document,
+    output_dir: &Path,
+    images_dir: &Path,
+    options: &StreamOptions,
+    budget: &mut ByteBudget,
+    counts: &mut ExtractionCounts,
+) -> Result<(), String> {
+    if options.wants(ExtractKind::Image) {
+        counts.inline_images = extract_inline_images(doc, images_dir, budget)?;
+    }
+    if options.wants(ExtractKind::Embedded) {
+        counts.rich_media = extract_rich_media(doc, output_dir, budget)?;
+        counts.embedded = extract_embedded_files(doc, output_dir, budget)?;
+    }
+    if options.wants(ExtractKind::Text) {
+        counts.page_text = extract_page_text(doc, output_dir, budget)?;
+    }
+    if options.wants(ExtractKind::Font) {
+        counts.fonts = extract_fonts(doc, output_dir, budget)?;
+    }
+    if options.only.is_empty() {
+        counts.metadata = extract_metadata(doc, output_dir, budget)?;
+        counts.xfa = extract_xfa(doc, output_dir, budget)?;
+    }
+    Ok(())
+}
+
+/// write an image as returned by extract_image_data
+///
+/// with seen_images, bytes identical to an image already written
+/// are only noted in the manifest, keyed by their hash
 fn extract_and_save_image(
-    stream: &lopdf::Stream,
+    (image_data, extension): (Vec<u8>, &str),
     object_id: &(u32, u16),
     images_dir: &PathBuf,
-    counter: &mut usize,
-) {
-    let (image_data, extension) = extract_image_data(stream);
+    counts: &mut ExtractionCounts,
+    seen_images: Option<&mut HashMap<u64, String>>,
+    manifest: Option<&mut Manifest>,
+    budget: &mut ByteBudget,
+) -> Result<(), String> {
     let filename = format!("image_{}_{}.{}", object_id.0, object_id.1, extension);
     let output_path = images_dir.join(&filename);
 
@@ -110,17 +462,43 @@ fn extract_and_save_image(
         object_id.0, object_id.1, extension
     );
 
-    if let Err(e) = fs::write(&output_path, &image_data) {
+    if let Some(seen) = seen_images {
+        let original = seen
+            .entry(content_hash(&image_data))
+            .or_insert_with(|| filename.clone());
+        if *original != filename {
+            let sha256 =
+                manifest.map(|m| m.record_duplicate(&filename, object_id, &image_data, original));
+            println!(
+                "  {} {} same as {}{}",
+                "「duplicate」".dimmed().bold(),
+                filename.cyan(),
+                original.cyan(),
+                hash_note(sha256.as_deref())
+            );
+            counts.duplicate_images += 1;
+            return Ok(());
+        }
+    }
+
+    budget.reserve(&filename, image_data.len())?;
+    let result = fs::write(&output_path, &image_data);
+    let sha256 = manifest.map(|m| m.record(&filename, object_id, &image_data, &result));
+
+    if let Err(e) = result {
         warn!("failed to write image {}: {}", filename, e);
     } else {
         println!(
-            "  {} {} ({} bytes)",
+            "  {} {} ({} bytes){}",
             "「image」".green().bold(),
             filename.cyan(),
-            image_data.len().to_string().yellow()
+            image_data.len().to_string().yellow(),
+            hash_note(sha256.as_deref())
         );
-        *counter += 1;
+        counts.images += 1;
     }
+
+    Ok(())
 }
 
 fn extract_and_save_text(
@@ -128,23 +506,32 @@ fn extract_and_save_text(
     object_id: &(u32, u16),
     text_dir: &PathBuf,
     counter: &mut usize,
-) {
+    manifest: Option<&mut Manifest>,
+    budget: &mut ByteBudget,
+) -> Result<(), String> {
     let filename = format!("text_{}_{}.txt", object_id.0, object_id.1);
     let output_path = text_dir.join(&filename);
 
     debug!("Extracting text object {}_{}", object_id.0, object_id.1);
 
-    if let Err(e) = fs::write(&output_path, content) {
+    budget.reserve(&filename, content.len())?;
+    let result = fs::write(&output_path, content);
+    let sha256 = manifest.map(|m| m.record(&filename, object_id, content, &result));
+
+    if let Err(e) = result {
         warn!("failed to write text {}: {}", filename, e);
     } else {
         println!(
-            "  {} {} ({} bytes)",
+            "  {} {} ({} bytes){}",
             "「text」".green().bold(),
             filename.cyan(),
-            content.len().to_string().yellow()
+            content.len().to_string().yellow(),
+            hash_note(sha256.as_deref())
         );
         *counter += 1;
     }
+
+    Ok(())
 }
 
 fn extract_and_save_binary(
@@ -152,23 +539,32 @@ fn extract_and_save_binary(
     object_id: &(u32, u16),
     binary_dir: &PathBuf,
     counter: &mut usize,
-) {
+    manifest: Option<&mut Manifest>,
+    budget: &mut ByteBudget,
+) -> Result<(), String> {
     let filename = format!("binary_{}_{}.bin", object_id.0, object_id.1);
     let output_path = binary_dir.join(&filename);
 
     debug!("Extracting binary object {}_{}", object_id.0, object_id.1);
 
-    if let Err(e) = fs::write(&output_path, content) {
+    budget.reserve(&filename, content.len())?;
+    let result = fs::write(&output_path, content);
+    let sha256 = manifest.map(|m| m.record(&filename, object_id, content, &result));
+
+    if let Err(e) = result {
         warn!("failed to write binary {}: {}", filename, e);
     } else {
         println!(
-            "  {} {} ({} bytes)",
+            "  {} {} ({} bytes){}",
             "「binary」".green().bold(),
             filename.cyan(),
-            content.len().to_string().yellow()
+            content.len().to_string().yellow(),
+            hash_note(sha256.as_deref())
         );
         *counter += 1;
     }
+
+    Ok(())
 }
 
 pub fn print_extraction_summary(counts: &ExtractionCounts, pre_parse_results: &PreParseResults) {
@@ -177,6 +573,35 @@ pub fn print_extraction_summary(counts: &ExtractionCounts, pre_parse_results: &P
     println!("  {} {}", "Text files:".green(), counts.text);
     println!("  {} {}", "Image files:".green(), counts.images);
     println!("  {} {}", "Binary files:".green(), counts.binary);
+    println!("  {} {}", "Inline images:".green(), counts.inline_images);
+    println!("  {} {}", "Pages with text:".green(), counts.page_text);
+    println!("  {} {}", "Fonts:".green(), counts.fonts);
+    println!("  {} {}", "Bytes written:".green(), counts.bytes_written);
+    if counts.metadata > 0 {
+        println!("  {} {}", "XMP metadata:".green(), counts.metadata);
+    }
+    if counts.embedded > 0 {
+        println!("  {} {}", "Embedded files:".yellow(), counts.embedded);
+    }
+    if counts.xfa > 0 {
+        println!("  {} {}", "XFA packets:".yellow(), counts.xfa);
+    }
+    if counts.rich_media > 0 {
+        println!("  {} {}", "Rich media:".yellow(), counts.rich_media);
+    }
+    if counts.duplicate_images > 0 {
+        println!(
+            "  {} {}",
+            "Duplicate images (skipped):".yellow(),
+            counts.duplicate_images
+        );
+    }
+    if counts.unchanged > 0 {
+        println!("  {} {}", "Unchanged (skipped):".yellow(), counts.unchanged);
+    }
+    if counts.too_small > 0 {
+        println!("  {} {}", "Too small (skipped):".yellow(), counts.too_small);
+    }
     if pre_parse_results.prepended_data.is_some() {
         println!("  {} {}", "Prepended data:".yellow(), "1");
     }
@@ -186,9 +611,19 @@ pub fn print_extraction_summary(counts: &ExtractionCounts, pre_parse_results: &P
     println!();
 }
 
-fn extract_image_data(stream: &lopdf::Stream) -> (Vec<u8>, &'static str) {
+pub fn extract_image_data(
+    stream: &lopdf::Stream,
+    doc: &lopdf::Document,
+) -> (Vec<u8>, &'static str) {
     let dict = &stream.dict;
 
+    if filter_chain(dict) == ["CCITTFaxDecode"] {
+        if let Some(tiff_data) = wrap_ccitt_in_tiff(&stream.content, dict) {
+            return (tiff_data, "tiff");
+        }
+        debug!("CCITTFaxDecode image without usable decode parms, keeping raw data");
+    }
+
     if let Ok(filter) = dict.get(b"Filter") {
         match filter {
             Object::Name(name) => match name.as_slice() {
@@ -226,7 +661,7 @@ fn extract_image_data(stream: &lopdf::Stream) -> (Vec<u8>, &'static str) {
     }
 
     if let Ok(content) = stream.decompressed_content() {
-        if let Some(png_data) = encode_raw_to_png(&content, dict) {
+        if let Some(png_data) = encode_raw_to_png(&content, dict, doc) {
             return (png_data, "png");
         }
         let extension = detect_image_format(&content, dict);
@@ -236,82 +671,511 @@ fn extract_image_data(stream: &lopdf::Stream) -> (Vec<u8>, &'static str) {
     }
 }
 
+/// wrap raw fax data in a minimal single strip tiff
+///
+/// tiff can carry group 3/4 data directly so nothing gets decoded,
+/// we just describe it with the stream's DecodeParms
+fn wrap_ccitt_in_tiff(fax_data: &[u8], dict: &lopdf::Dictionary) -> Option<Vec<u8>> {
+    let parms = match dict.get(b"DecodeParms").ok()? {
+        Object::Dictionary(parms) => parms,
+        Object::Array(arr) => arr.first()?.as_dict().ok()?,
+        _ => return None,
+    };
+
+    let k = parms.get(b"K").and_then(|k| k.as_i64()).unwrap_or(0);
+    let columns = parms
+        .get(b"Columns")
+        .and_then(|c| c.as_i64())
+        .unwrap_or(1728);
+    let rows = match parms.get(b"Rows").and_then(|r| r.as_i64()) {
+        Ok(rows) if rows > 0 => rows,
+        _ => dict.get(b"Height").ok()?.as_i64().ok()?,
+    };
+    let black_is_1 = parms
+        .get(b"BlackIs1")
+        .and_then(|b| b.as_bool())
+        .unwrap_or(false);
+
+    if columns <= 0 || rows <= 0 {
+        return None;
+    }
+
+    trace!(
+        "wrapping CCITT data: K={} {}x{} BlackIs1={}",
+        k, columns, rows, black_is_1
+    );
+
+    // K < 0 is pure 2D group 4, otherwise group 3 (1D or mixed)
+    let (compression, options_tag, options) = if k < 0 {
+        (4u32, 293u16, 0u32)
+    } else {
+        (3, 292, if k > 0 { 1 } else { 0 })
+    };
+    let photometric = if black_is_1 { 1 } else { 0 };
+
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    let entry_count = 10u16;
+    let data_offset = 8 + 2 + entry_count as u32 * 12 + 4;
+
+    let entries: [(u16, u16, u32); 10] = [
+        (256, LONG, columns as u32),
+        (257, LONG, rows as u32),
+        (258, SHORT, 1),
+        (259, SHORT, compression),
+        (262, SHORT, photometric),
+        (273, LONG, data_offset),
+        (277, SHORT, 1),
+        (278, LONG, rows as u32),
+        (279, LONG, fax_data.len() as u32),
+        (options_tag, LONG, options),
+    ];
+
+    let mut tiff = Vec::with_capacity(data_offset as usize + fax_data.len());
+    tiff.extend_from_slice(b"II\x2a\x00");
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+    tiff.extend_from_slice(&entry_count.to_le_bytes());
+    for (tag, field_type, value) in entries {
+        tiff.extend_from_slice(&tag.to_le_bytes());
+        tiff.extend_from_slice(&field_type.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        if field_type == SHORT {
+            tiff.extend_from_slice(&(value as u16).to_le_bytes());
+            tiff.extend_from_slice(&[0, 0]);
+        } else {
+            tiff.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff.extend_from_slice(fax_data);
+
+    Some(tiff)
+}
+
 /// re-encode raw pixels into png
-fn encode_raw_to_png(raw_data: &[u8], dict: &lopdf::Dictionary) -> Option<Vec<u8>> {
+///
+/// the document is needed to resolve palettes and soft masks
+/// stored as references, an /SMask becomes the png's alpha channel
+fn encode_raw_to_png(
+    raw_data: &[u8],
+    dict: &lopdf::Dictionary,
+    doc: &lopdf::Document,
+) -> Option<Vec<u8>> {
     let width = dict.get(b"Width").ok()?.as_i64().ok()? as u32;
     let height = dict.get(b"Height").ok()?.as_i64().ok()? as u32;
     let bpc = dict.get(b"BitsPerComponent").ok()?.as_i64().ok()? as u8;
 
     trace!("Attempting PNG encoding: {}x{}, {} bpc", width, height, bpc);
 
-    if bpc != 8 {
-        debug!(
-            "Skipping PNG encoding: unsupported bits per component ({})",
-            bpc
-        );
-        return None;
-    }
-
-    let colorspace = dict.get(b"ColorSpace").ok()?;
-    let colorspace_name = match colorspace {
-        Object::Name(name) => name.as_slice(),
-        _ => return None,
-    };
+    let (pixels, channels) = if let Some(palette) = dict
+        .get(b"ColorSpace")
+        .ok()
+        .and_then(|cs| indexed_palette(cs, doc))
+    {
+        (expand_indexed(raw_data, width, height, bpc, &palette)?, 3)
+    } else {
+        let components = match dict.get(b"ColorSpace").ok()? {
+            Object::Name(name) => match name.as_slice() {
+                b"DeviceGray" => 1,
+                b"DeviceRGB" => 3,
+                b"DeviceCMYK" => 4,
+                _ => return None,
+            },
+            _ => return None,
+        };
 
-    let mut png_buffer = Vec::new();
+        if !matches!(bpc, 1 | 2 | 4 | 8 | 16) {
+            debug!(
+                "Skipping PNG encoding: unsupported bits per component ({})",
+                bpc
+            );
+            return None;
+        }
 
-    match colorspace_name {
-        b"DeviceRGB" => {
-            let expected_size = (width * height * 3) as usize;
+        let expected_size = expected_image_size(dict)?;
+        if raw_data.len() != expected_size {
+            println!(
+                "  {} declared {} bytes, stream has {} bytes",
+                "「image size mismatch」".yellow().bold(),
+                expected_size.to_string().yellow(),
+                raw_data.len().to_string().yellow()
+            );
             if raw_data.len() < expected_size {
                 return None;
             }
+        }
 
-            let img: RgbImage =
-                ImageBuffer::from_raw(width, height, raw_data[..expected_size].to_vec())?;
-            img.write_to(
-                &mut std::io::Cursor::new(&mut png_buffer),
-                image::ImageFormat::Png,
-            )
-            .ok()?;
+        let data = &raw_data[..expected_size];
+        let invert = has_inverted_decode(dict);
+
+        if bpc == 16 && components != 4 {
+            return encode_16_bit_png(data, width, height, components, invert);
         }
-        b"DeviceGray" => {
-            let expected_size = (width * height) as usize;
-            if raw_data.len() < expected_size {
-                return None;
+
+        let mut samples = match bpc {
+            8 => data.to_vec(),
+            16 => data.chunks_exact(2).map(|pair| pair[0]).collect(),
+            _ => unpack_samples(data, width, height, components, bpc),
+        };
+
+        if components == 4 {
+            (cmyk_to_rgb(&samples, invert), 3)
+        } else {
+            if invert {
+                samples
+                    .iter_mut()
+                    .for_each(|sample| *sample = 255 - *sample);
             }
+            (samples, components)
+        }
+    };
 
-            let img: GrayImage =
-                ImageBuffer::from_raw(width, height, raw_data[..expected_size].to_vec())?;
-            img.write_to(
-                &mut std::io::Cursor::new(&mut png_buffer),
-                image::ImageFormat::Png,
-            )
-            .ok()?;
+    let alpha = soft_mask_alpha(dict, doc, width, height);
+    let mut png_buffer = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut png_buffer);
+
+    match (channels, alpha) {
+        (_, Some(alpha)) => {
+            let mut rgba = Vec::with_capacity(alpha.len() * 4);
+            for (pixel, a) in pixels.chunks_exact(channels).zip(alpha) {
+                match pixel {
+                    [gray] => rgba.extend_from_slice(&[*gray, *gray, *gray, a]),
+                    rgb => rgba.extend_from_slice(&[rgb[0], rgb[1], rgb[2], a]),
+                }
+            }
+            let img: RgbaImage = ImageBuffer::from_raw(width, height, rgba)?;
+            img.write_to(&mut cursor, image::ImageFormat::Png).ok()?;
+        }
+        (3, None) => {
+            let img: RgbImage = ImageBuffer::from_raw(width, height, pixels)?;
+            img.write_to(&mut cursor, image::ImageFormat::Png).ok()?;
+        }
+        _ => {
+            let img: GrayImage = ImageBuffer::from_raw(width, height, pixels)?;
+            img.write_to(&mut cursor, image::ImageFormat::Png).ok()?;
         }
-        _ => return None,
     }
 
     Some(png_buffer)
 }
 
-/// check common image formats
-fn detect_image_format(content: &[u8], dict: &lopdf::Dictionary) -> &'static str {
-    if content.len() > 8 && &content[0..8] == b"\x89PNG\r\n\x1a\n" {
-        return "png";
+/// widen packed 1, 2 or 4 bit samples to 8 bits, rows are byte aligned
+///
+/// bilevel images come out as clean 0/255 black and white
+fn unpack_samples(data: &[u8], width: u32, height: u32, components: usize, bpc: u8) -> Vec<u8> {
+    let samples_per_row = width as usize * components;
+    let row_bytes = (samples_per_row * bpc as usize).div_ceil(8);
+    let max = (1u16 << bpc) - 1;
+
+    let mut samples = Vec::with_capacity(samples_per_row * height as usize);
+    for row in data.chunks_exact(row_bytes).take(height as usize) {
+        for i in 0..samples_per_row {
+            let bit = i * bpc as usize;
+            let shift = 8 - bpc as usize - bit % 8;
+            let value = (row[bit / 8] >> shift) as u16 & max;
+            samples.push((value * 255 / max) as u8);
+        }
     }
+    samples
+}
 
-    if content.len() > 2 && &content[0..2] == b"\xff\xd8" {
-        return "jpg";
+/// 16 bit gray or rgb goes straight to a 16 bit png
+///
+/// samples are big endian in the pdf, soft masks aren't applied here
+fn encode_16_bit_png(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    components: usize,
+    invert: bool,
+) -> Option<Vec<u8>> {
+    let samples: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| {
+            let value = u16::from_be_bytes([pair[0], pair[1]]);
+            if invert { u16::MAX - value } else { value }
+        })
+        .collect();
+
+    let mut png_buffer = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut png_buffer);
+    if components == 3 {
+        let img: ImageBuffer<Rgb<u16>, Vec<u16>> = ImageBuffer::from_raw(width, height, samples)?;
+        img.write_to(&mut cursor, image::ImageFormat::Png).ok()?;
+    } else {
+        let img: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_raw(width, height, samples)?;
+        img.write_to(&mut cursor, image::ImageFormat::Png).ok()?;
     }
 
-    if content.len() > 4 && &content[0..4] == b"GIF8" {
-        return "gif";
+    trace!("encoded 16 bit png, {} components", components);
+    Some(png_buffer)
+}
+
+/// convert 8 bit cmyk pixels to rgb, R = 255 * (1 - C) * (1 - K)
+///
+/// with invert the components are flipped first, the way a
+/// /Decode [1 0 1 0 1 0 1 0] array asks for
+fn cmyk_to_rgb(data: &[u8], invert: bool) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(data.len() / 4 * 3);
+    for pixel in data.chunks_exact(4) {
+        let [c, m, y, k] = [pixel[0], pixel[1], pixel[2], pixel[3]]
+            .map(|v| if invert { 255 - v as u32 } else { v as u32 });
+        let channel = |v: u32| ((255 - v) * (255 - k) / 255) as u8;
+        rgb.extend_from_slice(&[channel(c), channel(m), channel(y)]);
     }
+    rgb
+}
 
-    if content.len() > 4 && ((&content[0..4] == b"II\x2a\x00") || (&content[0..4] == b"MM\x00\x2a"))
-    {
-        return "tiff";
+/// whether /Decode maps the first component from 1 down to 0
+fn has_inverted_decode(dict: &lopdf::Dictionary) -> bool {
+    let Ok(Object::Array(decode)) = dict.get(b"Decode") else {
+        return false;
+    };
+    let value = |index: usize| decode.get(index).and_then(|v| v.as_float().ok());
+    matches!((value(0), value(1)), (Some(low), Some(high)) if low > high)
+}
+
+/// decode an image's /SMask into one alpha byte per pixel
+///
+/// masks with other dimensions get bilinearly resampled to fit
+fn soft_mask_alpha(
+    dict: &lopdf::Dictionary,
+    doc: &lopdf::Document,
+    width: u32,
+    height: u32,
+) -> Option<Vec<u8>> {
+    let mask_id = dict.get(b"SMask").ok()?.as_reference().ok()?;
+    let mask = doc.get_object(mask_id).ok()?.as_stream().ok()?;
+
+    let mask_width = mask.dict.get(b"Width").ok()?.as_i64().ok()? as usize;
+    let mask_height = mask.dict.get(b"Height").ok()?.as_i64().ok()? as usize;
+    let mask_bpc = mask.dict.get(b"BitsPerComponent").ok()?.as_i64().ok()?;
+    if mask_bpc != 8 || mask_width == 0 || mask_height == 0 {
+        debug!("ignoring SMask {:?}: unsupported {} bpc", mask_id, mask_bpc);
+        return None;
+    }
+
+    let data = mask.decompressed_content().ok()?;
+    if data.len() < mask_width * mask_height {
+        debug!("SMask {:?} is shorter than its dimensions", mask_id);
+        return None;
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    if mask_width == width && mask_height == height {
+        return Some(data[..width * height].to_vec());
+    }
+
+    trace!(
+        "resampling SMask {}x{} to {}x{}",
+        mask_width, mask_height, width, height
+    );
+
+    let sample = |x: usize, y: usize| data[y * mask_width + x] as f32;
+    let scale_x = mask_width as f32 / width as f32;
+    let scale_y = mask_height as f32 / height as f32;
+    let mut alpha = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        let src_y = ((y as f32 + 0.5) * scale_y - 0.5).clamp(0.0, (mask_height - 1) as f32);
+        let y0 = src_y.floor() as usize;
+        let y1 = (y0 + 1).min(mask_height - 1);
+        let fy = src_y - y0 as f32;
+
+        for x in 0..width {
+            let src_x = ((x as f32 + 0.5) * scale_x - 0.5).clamp(0.0, (mask_width - 1) as f32);
+            let x0 = src_x.floor() as usize;
+            let x1 = (x0 + 1).min(mask_width - 1);
+            let fx = src_x - x0 as f32;
+
+            let top = sample(x0, y0) * (1.0 - fx) + sample(x1, y0) * fx;
+            let bottom = sample(x0, y1) * (1.0 - fx) + sample(x1, y1) * fx;
+            alpha.push((top * (1.0 - fy) + bottom * fy).round() as u8);
+        }
+    }
+
+    Some(alpha)
+}
+
+/// resolve an [/Indexed base hival lookup] colorspace into rgb entries
+fn indexed_palette(cs: &Object, doc: &lopdf::Document) -> Option<Vec<[u8; 3]>> {
+    let cs = match cs {
+        Object::Reference(id) => doc.get_object(*id).ok()?,
+        other => other,
+    };
+    let Object::Array(parts) = cs else {
+        return None;
+    };
+    if parts.len() != 4 || parts[0].as_name().ok()? != b"Indexed" {
+        return None;
+    }
+
+    let components = base_components(&parts[1], doc)?;
+    let hival = parts[2].as_i64().ok()?.clamp(0, 255) as usize;
+
+    let lookup = match &parts[3] {
+        Object::String(bytes, _) => bytes.clone(),
+        Object::Reference(id) => match doc.get_object(*id).ok()? {
+            Object::Stream(stream) => stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone()),
+            Object::String(bytes, _) => bytes.clone(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let palette: Vec<[u8; 3]> = lookup
+        .chunks_exact(components)
+        .take(hival + 1)
+        .map(|entry| match entry {
+            [gray] => [*gray, *gray, *gray],
+            [c, m, y, k] => {
+                let channel = |v: u8| ((255 - v as u32) * (255 - *k as u32) / 255) as u8;
+                [channel(*c), channel(*m), channel(*y)]
+            }
+            rgb => [rgb[0], rgb[1], rgb[2]],
+        })
+        .collect();
+
+    trace!(
+        "indexed palette with {} of {} entries",
+        palette.len(),
+        hival + 1
+    );
+
+    if palette.is_empty() {
+        None
+    } else {
+        Some(palette)
+    }
+}
+
+/// how many bytes each palette entry has for a base colorspace
+fn base_components(base: &Object, doc: &lopdf::Document) -> Option<usize> {
+    match base {
+        Object::Reference(id) => base_components(doc.get_object(*id).ok()?, doc),
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" | b"CalGray" => Some(1),
+            b"DeviceRGB" | b"CalRGB" | b"Lab" => Some(3),
+            b"DeviceCMYK" => Some(4),
+            _ => None,
+        },
+        Object::Array(arr) => match arr.first()?.as_name().ok()? {
+            b"ICCBased" => {
+                let profile_id = arr.get(1)?.as_reference().ok()?;
+                let profile = doc.get_object(profile_id).ok()?.as_stream().ok()?;
+                match profile.dict.get(b"N").ok()?.as_i64().ok()? {
+                    n @ (1 | 3 | 4) => Some(n as usize),
+                    _ => None,
+                }
+            }
+            _ => base_components(arr.first()?, doc),
+        },
+        _ => None,
+    }
+}
+
+/// expand palette indices (1, 2, 4 or 8 bits, rows byte aligned) to rgb
+fn expand_indexed(
+    raw_data: &[u8],
+    width: u32,
+    height: u32,
+    bpc: u8,
+    palette: &[[u8; 3]],
+) -> Option<Vec<u8>> {
+    if !matches!(bpc, 1 | 2 | 4 | 8) {
+        debug!("Skipping indexed PNG encoding: unsupported bpc ({})", bpc);
+        return None;
+    }
+
+    let row_bytes = (width as usize * bpc as usize).div_ceil(8);
+    if raw_data.len() < row_bytes * height as usize {
+        debug!(
+            "indexed image data too short: {} < {}",
+            raw_data.len(),
+            row_bytes * height as usize
+        );
+        return None;
+    }
+
+    let mask = ((1u16 << bpc) - 1) as u8;
+    let mut rgb = Vec::with_capacity(width as usize * height as usize * 3);
+    for row in raw_data.chunks_exact(row_bytes).take(height as usize) {
+        for x in 0..width as usize {
+            let bit = x * bpc as usize;
+            let shift = 8 - bpc as usize - bit % 8;
+            let index = (row[bit / 8] >> shift) & mask;
+            let color = palette
+                .get(index as usize)
+                .or(palette.last())
+                .copied()
+                .unwrap_or_default();
+            rgb.extend_from_slice(&color);
+        }
+    }
+
+    Some(rgb)
+}
+
+/// bytes of pixel data an image dictionary says it should have
+///
+/// only knows the device colorspaces, anything else is None
+pub fn expected_image_size(dict: &lopdf::Dictionary) -> Option<usize> {
+    let width = dict.get(b"Width").ok()?.as_i64().ok()?;
+    let height = dict.get(b"Height").ok()?.as_i64().ok()?;
+    let bpc = dict.get(b"BitsPerComponent").ok()?.as_i64().ok()?;
+
+    if width <= 0 || height <= 0 || bpc <= 0 {
+        return None;
+    }
+
+    let components = match dict.get(b"ColorSpace").ok()? {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" => 1,
+            b"DeviceRGB" => 3,
+            b"DeviceCMYK" => 4,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let row_bytes = (width as usize)
+        .checked_mul(components)?
+        .checked_mul(bpc as usize)?
+        .div_ceil(8);
+    row_bytes.checked_mul(height as usize)
+}
+
+/// compare an image's declared size with its decoded pixel data
+///
+/// returns (declared, actual) when they differ, images stored in
+/// their own compressed format (jpeg etc) are skipped
+pub fn image_size_mismatch(stream: &lopdf::Stream) -> Option<(usize, usize)> {
+    let dict = &stream.dict;
+    if filter_chain(dict).iter().any(|f| {
+        matches!(
+            f.as_str(),
+            "DCTDecode" | "JPXDecode" | "JBIG2Decode" | "CCITTFaxDecode"
+        )
+    }) {
+        return None;
+    }
+
+    let expected = expected_image_size(dict)?;
+    let actual = stream.decompressed_content().ok()?.len();
+
+    if actual != expected {
+        Some((expected, actual))
+    } else {
+        None
+    }
+}
+
+/// check common image formats
+fn detect_image_format(content: &[u8], dict: &lopdf::Dictionary) -> &'static str {
+    if let Some(format) = image_magic(content) {
+        return format;
     }
 
     if let Ok(filter) = dict.get(b"Filter") {
@@ -347,7 +1211,51 @@ fn detect_image_format(content: &[u8], dict: &lopdf::Dictionary) -> &'static str
     "dat"
 }
 
+/// image format from magic bytes alone
+pub fn image_magic(content: &[u8]) -> Option<&'static str> {
+    if content.len() > 8 && &content[0..8] == b"\x89PNG\r\n\x1a\n" {
+        return Some("png");
+    }
+
+    if content.len() > 2 && &content[0..2] == b"\xff\xd8" {
+        return Some("jpg");
+    }
+
+    if content.len() > 4 && &content[0..4] == b"GIF8" {
+        return Some("gif");
+    }
+
+    if content.len() > 4 && ((&content[0..4] == b"II\x2a\x00") || (&content[0..4] == b"MM\x00\x2a"))
+    {
+        return Some("tiff");
+    }
+
+    None
+}
+
+/// what kind of executable or archive the magic bytes say this is
+///
+/// none of these have any business in a stream that isn't an
+/// embedded file. the dos header alone is 64 bytes, so shorter
+/// data starting with MZ is left alone
+pub fn is_executable(content: &[u8]) -> Option<&'static str> {
+    if content.len() >= 64 && content.starts_with(b"MZ") {
+        return Some("an MZ executable");
+    }
+    if content.starts_with(b"\x7fELF") {
+        return Some("an ELF executable");
+    }
+    if content.starts_with(b"PK\x03\x04") {
+        return Some("a ZIP archive");
+    }
+    None
+}
+
 // check if extracted content is valid text
+//
+// content with a utf-8 or utf-16 byte order mark is decoded before
+// counting, other valid utf-8 is counted by char, and anything else
+// falls back to counting printable ascii bytes
 fn is_text_content(content: &[u8]) -> bool {
     if content.is_empty() {
         return false;
@@ -356,6 +1264,10 @@ fn is_text_content(content: &[u8]) -> bool {
     let sample_size = content.len().min(512);
     let sample = &content[..sample_size];
 
+    if let Some(ratio) = bom_text_ratio(sample).or_else(|| utf8_text_ratio(sample)) {
+        return ratio > 0.75;
+    }
+
     let mut text_chars = 0;
     let mut total_chars = 0;
 
@@ -375,3 +1287,60 @@ fn is_text_content(content: &[u8]) -> bool {
     let text_ratio = (text_chars as f64) / (total_chars as f64);
     text_ratio > 0.75
 }
+
+/// share of printable chars after decoding by byte order mark
+fn bom_text_ratio(sample: &[u8]) -> Option<f64> {
+    let units = |body: &[u8], from_bytes: fn([u8; 2]) -> u16| -> Vec<u16> {
+        body.chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]))
+            .collect()
+    };
+
+    let chars: Vec<char> = if let Some(body) = sample.strip_prefix(b"\xEF\xBB\xBF") {
+        String::from_utf8_lossy(body).chars().collect()
+    } else if let Some(body) = sample.strip_prefix(b"\xFF\xFE") {
+        char::decode_utf16(units(body, u16::from_le_bytes))
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    } else if let Some(body) = sample.strip_prefix(b"\xFE\xFF") {
+        char::decode_utf16(units(body, u16::from_be_bytes))
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    } else {
+        return None;
+    };
+
+    trace!("byte order mark found, {} chars decoded", chars.len());
+    Some(char_text_ratio(&chars))
+}
+
+/// share of printable chars if the sample is utf-8 with no nulls
+///
+/// the sample can end part way through a multibyte sequence,
+/// so an incomplete last char is dropped rather than failing
+fn utf8_text_ratio(sample: &[u8]) -> Option<f64> {
+    let text = match std::str::from_utf8(sample) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&sample[..e.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    if text.is_empty() || text.contains('\0') {
+        return None;
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    Some(char_text_ratio(&chars))
+}
+
+fn char_text_ratio(chars: &[char]) -> f64 {
+    if chars.is_empty() {
+        return 0.0;
+    }
+    let text_chars = chars
+        .iter()
+        .filter(|c| (!c.is_control() || c.is_whitespace()) && **c != char::REPLACEMENT_CHARACTER)
+        .count();
+    text_chars as f64 / chars.len() as f64
+}
@@ -0,0 +1,139 @@
+use colored::Colorize;
+use log::{debug, info, trace, warn};
+use lopdf::Object;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::extraction_helpers::ByteBudget;
+
+/// font descriptor keys that hold embedded font programs
+const FONT_FILE_KEYS: &[&[u8]] = &[b"FontFile", b"FontFile2", b"FontFile3"];
+
+/// write every embedded font program to output_dir/fonts
+///
+/// FontFile is type 1, FontFile2 truetype and FontFile3 is either
+/// OpenType or a bare CFF depending on its /Subtype
+pub fn extract_fonts(
+    doc: &lopdf::Document,
+    output_dir: &Path,
+    budget: &mut ByteBudget,
+) -> Result<usize, String> {
+    let fonts_dir = output_dir.join("fonts");
+    let base_fonts = base_font_names(doc);
+    let mut count = 0;
+
+    for (descriptor_id, object) in doc.objects.iter() {
+        let Object::Dictionary(descriptor) = object else {
+            continue;
+        };
+        if !matches!(descriptor.get(b"Type"), Ok(Object::Name(t)) if t == b"FontDescriptor") {
+            continue;
+        }
+
+        for key in FONT_FILE_KEYS {
+            let Ok(font_ref) = descriptor.get(key).and_then(|f| f.as_reference()) else {
+                continue;
+            };
+            let Ok(stream) = doc.get_object(font_ref).and_then(|o| o.as_stream()) else {
+                debug!(
+                    "{} in descriptor {:?} is not a stream",
+                    String::from_utf8_lossy(key),
+                    descriptor_id
+                );
+                continue;
+            };
+
+            let extension = match *key {
+                b"FontFile" => "pfb",
+                b"FontFile2" => "ttf",
+                _ => match stream.dict.get(b"Subtype") {
+                    Ok(Object::Name(subtype)) if subtype == b"OpenType" => "otf",
+                    _ => "cff",
+                },
+            };
+
+            let name = base_fonts
+                .get(descriptor_id)
+                .cloned()
+                .or_else(|| {
+                    descriptor
+                        .get(b"FontName")
+                        .and_then(|n| n.as_name())
+                        .map(|n| String::from_utf8_lossy(n).to_string())
+                        .ok()
+                })
+                .unwrap_or_else(|| "font".to_string());
+
+            let data = stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone());
+            let filename = format!(
+                "{}_{}_{}.{}",
+                sanitize_font_name(&name),
+                font_ref.0,
+                font_ref.1,
+                extension
+            );
+            trace!("font {} from descriptor {:?}", filename, descriptor_id);
+
+            if let Err(e) = fs::create_dir_all(&fonts_dir) {
+                warn!("failed to create fonts directory: {}", e);
+                return Ok(count);
+            }
+            budget.reserve(&filename, data.len())?;
+            if let Err(e) = fs::write(fonts_dir.join(&filename), &data) {
+                warn!("failed to write font {}: {}", filename, e);
+            } else {
+                println!(
+                    "  {} {} ({} bytes)",
+                    "「font」".green().bold(),
+                    filename.cyan(),
+                    data.len().to_string().yellow()
+                );
+                count += 1;
+            }
+        }
+    }
+
+    info!("extracted {} fonts", count);
+    Ok(count)
+}
+
+/// map each font descriptor to the /BaseFont of the font using it
+fn base_font_names(doc: &lopdf::Document) -> HashMap<(u32, u16), String> {
+    let mut names = HashMap::new();
+
+    for object in doc.objects.values() {
+        let Object::Dictionary(font) = object else {
+            continue;
+        };
+        if !matches!(font.get(b"Type"), Ok(Object::Name(t)) if t == b"Font") {
+            continue;
+        }
+        let (Ok(descriptor_id), Ok(base_font)) = (
+            font.get(b"FontDescriptor").and_then(|d| d.as_reference()),
+            font.get(b"BaseFont").and_then(|b| b.as_name()),
+        ) else {
+            continue;
+        };
+        names.insert(
+            descriptor_id,
+            String::from_utf8_lossy(base_font).to_string(),
+        );
+    }
+
+    names
+}
+
+fn sanitize_font_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '+' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,284 @@
+use colored::Colorize;
+use log::{debug, info, trace, warn};
+use lopdf::{Dictionary, Object, Stream};
+use std::fs;
+use std::path::Path;
+
+use crate::extraction_helpers::{ByteBudget, extract_image_data};
+
+/// an inline image pulled out of a content stream
+pub struct InlineImage {
+    pub dict: Dictionary,
+    pub data: Vec<u8>,
+}
+
+/// walk every page's content for BI/ID/EI blocks and write them out
+///
+/// inline images don't live in their own objects so the normal
+/// object walk never sees them
+pub fn extract_inline_images(
+    doc: &lopdf::Document,
+    images_dir: &Path,
+    budget: &mut ByteBudget,
+) -> Result<usize, String> {
+    let mut count = 0;
+
+    for (page_num, page_id) in doc.get_pages() {
+        let content = match doc.get_page_content(page_id) {
+            Ok(content) => content,
+            Err(e) => {
+                debug!("could not read content for page {}: {:?}", page_num, e);
+                continue;
+            }
+        };
+
+        let inline_images = scan_inline_images(&content);
+        if !inline_images.is_empty() {
+            debug!(
+                "found {} inline image(s) on page {}",
+                inline_images.len(),
+                page_num
+            );
+        }
+
+        for (index, inline_image) in inline_images.into_iter().enumerate() {
+            let stream = Stream::new(inline_image.dict, inline_image.data);
+            let (image_data, extension) = extract_image_data(&stream, doc);
+            let filename = format!("inline_{}_{}.{}", page_num, index, extension);
+            let output_path = images_dir.join(&filename);
+
+            budget.reserve(&filename, image_data.len())?;
+            if let Err(e) = fs::write(&output_path, &image_data) {
+                warn!("failed to write inline image {}: {}", filename, e);
+            } else {
+                println!(
+                    "  {} {} ({} bytes)",
+                    "「inline image」".green().bold(),
+                    filename.cyan(),
+                    image_data.len().to_string().yellow()
+                );
+                count += 1;
+            }
+        }
+    }
+
+    info!("extracted {} inline images", count);
+    Ok(count)
+}
+
+/// find BI ... ID ... EI sequences in a decoded content stream
+pub fn scan_inline_images(content: &[u8]) -> Vec<InlineImage> {
+    let mut images = Vec::new();
+    let mut pos = 0;
+
+    while let Some(bi_pos) = find_operator(content, b"BI", pos) {
+        let mut cursor = bi_pos + 2;
+        let mut dict = Dictionary::new();
+
+        loop {
+            skip_whitespace(content, &mut cursor);
+            if cursor >= content.len() {
+                break;
+            }
+
+            if is_operator_at(content, cursor, b"ID") {
+                cursor += 2;
+                break;
+            }
+
+            let key = match parse_object(content, &mut cursor) {
+                Some(Object::Name(key)) => key,
+                _ => {
+                    trace!("malformed inline image dictionary at {}", cursor);
+                    break;
+                }
+            };
+            skip_whitespace(content, &mut cursor);
+            let value = match parse_object(content, &mut cursor) {
+                Some(value) => value,
+                None => break,
+            };
+
+            dict.set(expand_key(&key), expand_value(&key, value));
+        }
+
+        // exactly one whitespace byte separates ID from the data
+        let data_start = (cursor + 1).min(content.len());
+        let Some(ei_pos) = find_operator(content, b"EI", data_start) else {
+            debug!("inline image at {} has no EI marker", bi_pos);
+            break;
+        };
+
+        let mut data_end = ei_pos;
+        if data_end > data_start && content[data_end - 1].is_ascii_whitespace() {
+            data_end -= 1;
+        }
+
+        images.push(InlineImage {
+            dict,
+            data: content[data_start..data_end].to_vec(),
+        });
+
+        pos = ei_pos + 2;
+    }
+
+    images
+}
+
+/// expand abbreviated inline image keys to their full names
+fn expand_key(key: &[u8]) -> Vec<u8> {
+    let full: &[u8] = match key {
+        b"W" => b"Width",
+        b"H" => b"Height",
+        b"BPC" => b"BitsPerComponent",
+        b"CS" => b"ColorSpace",
+        b"F" => b"Filter",
+        b"DP" => b"DecodeParms",
+        b"IM" => b"ImageMask",
+        b"D" => b"Decode",
+        b"I" => b"Interpolate",
+        other => other,
+    };
+    full.to_vec()
+}
+
+/// expand abbreviated filter and colorspace names
+fn expand_value(key: &[u8], value: Object) -> Object {
+    let expand_name = |name: Vec<u8>| -> Vec<u8> {
+        let full: &[u8] = match (key, name.as_slice()) {
+            (b"CS" | b"ColorSpace", b"G") => b"DeviceGray",
+            (b"CS" | b"ColorSpace", b"RGB") => b"DeviceRGB",
+            (b"CS" | b"ColorSpace", b"CMYK") => b"DeviceCMYK",
+            (b"CS" | b"ColorSpace", b"I") => b"Indexed",
+            (b"F" | b"Filter", b"AHx") => b"ASCIIHexDecode",
+            (b"F" | b"Filter", b"A85") => b"ASCII85Decode",
+            (b"F" | b"Filter", b"LZW") => b"LZWDecode",
+            (b"F" | b"Filter", b"Fl") => b"FlateDecode",
+            (b"F" | b"Filter", b"RL") => b"RunLengthDecode",
+            (b"F" | b"Filter", b"CCF") => b"CCITTFaxDecode",
+            (b"F" | b"Filter", b"DCT") => b"DCTDecode",
+            _ => return name,
+        };
+        full.to_vec()
+    };
+
+    match value {
+        Object::Name(name) => Object::Name(expand_name(name)),
+        Object::Array(items) => Object::Array(
+            items
+                .into_iter()
+                .map(|item| match item {
+                    Object::Name(name) => Object::Name(expand_name(name)),
+                    other => other,
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+fn skip_whitespace(content: &[u8], cursor: &mut usize) {
+    while *cursor < content.len() && content[*cursor].is_ascii_whitespace() {
+        *cursor += 1;
+    }
+}
+
+/// check for a standalone operator token at a position
+fn is_operator_at(content: &[u8], pos: usize, operator: &[u8]) -> bool {
+    let end = pos + operator.len();
+    if end > content.len() || &content[pos..end] != operator {
+        return false;
+    }
+    let boundary_before = pos == 0 || content[pos - 1].is_ascii_whitespace();
+    let boundary_after =
+        end == content.len() || content[end].is_ascii_whitespace() || is_delimiter(content[end]);
+    boundary_before && boundary_after
+}
+
+fn find_operator(content: &[u8], operator: &[u8], from: usize) -> Option<usize> {
+    (from..content.len()).find(|&pos| is_operator_at(content, pos, operator))
+}
+
+/// tiny object parser, just enough for inline image dictionaries
+fn parse_object(content: &[u8], cursor: &mut usize) -> Option<Object> {
+    skip_whitespace(content, cursor);
+    let byte = *content.get(*cursor)?;
+
+    match byte {
+        b'/' => {
+            *cursor += 1;
+            let start = *cursor;
+            while *cursor < content.len()
+                && !content[*cursor].is_ascii_whitespace()
+                && !is_delimiter(content[*cursor])
+            {
+                *cursor += 1;
+            }
+            Some(Object::Name(content[start..*cursor].to_vec()))
+        }
+        b'[' => {
+            *cursor += 1;
+            let mut items = Vec::new();
+            loop {
+                skip_whitespace(content, cursor);
+                match content.get(*cursor)? {
+                    b']' => {
+                        *cursor += 1;
+                        break;
+                    }
+                    _ => items.push(parse_object(content, cursor)?),
+                }
+            }
+            Some(Object::Array(items))
+        }
+        b'<' if content.get(*cursor + 1) == Some(&b'<') => {
+            *cursor += 2;
+            let mut dict = Dictionary::new();
+            loop {
+                skip_whitespace(content, cursor);
+                if content.get(*cursor..*cursor + 2)? == b">>" {
+                    *cursor += 2;
+                    break;
+                }
+                let Object::Name(key) = parse_object(content, cursor)? else {
+                    return None;
+                };
+                let value = parse_object(content, cursor)?;
+                dict.set(key, value);
+            }
+            Some(Object::Dictionary(dict))
+        }
+        _ => {
+            let start = *cursor;
+            while *cursor < content.len()
+                && !content[*cursor].is_ascii_whitespace()
+                && !is_delimiter(content[*cursor])
+            {
+                *cursor += 1;
+            }
+            let token = std::str::from_utf8(&content[start..*cursor]).ok()?;
+            match token {
+                "" => None,
+                "true" => Some(Object::Boolean(true)),
+                "false" => Some(Object::Boolean(false)),
+                "null" => Some(Object::Null),
+                _ => {
+                    if let Ok(value) = token.parse::<i64>() {
+                        Some(Object::Integer(value))
+                    } else if let Ok(value) = token.parse::<f32>() {
+                        Some(Object::Real(value))
+                    } else {
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
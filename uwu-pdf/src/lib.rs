@@ -0,0 +1,24 @@
+//! pdf analysis behind the uwu-pdf cli
+//!
+//! only analyze and what it returns are public. reports,
+//! extraction and the rest of the printing live in the binary,
+//! which declares its own copy of these modules
+
+// the binary is what uses the printing half of these
+#![allow(dead_code)]
+
+mod analysis_helpers;
+mod pdf_loader;
+mod pdf_pre_parse_sec_checks;
+mod xref_helpers;
+
+pub use analysis_helpers::PdfStats;
+pub use lopdf::Error;
+
+/// load a pdf from memory and count what's in it
+pub fn analyze(bytes: &[u8]) -> Result<PdfStats, Error> {
+    let (doc, pre_parse_results) = pdf_loader::load_pdf_bytes(bytes.to_vec(), None)?;
+    let mut stats = analysis_helpers::gather_pdf_stats(&doc);
+    stats.header_offset = pre_parse_results.prepended_bytes.unwrap_or(0);
+    Ok(stats)
+}
@@ -4,10 +4,27 @@ use pretty_env_logger;
 use std::path::PathBuf;
 
 mod analysis_helpers;
+mod dump_helpers;
+mod embedded_file_helpers;
 mod extraction_helpers;
+mod font_helpers;
+mod inline_image_helpers;
+mod metadata_helpers;
+mod objstm_helpers;
+mod page_helpers;
+mod pdf_crypt;
+mod pdf_loader;
 mod pdf_ops;
 mod pdf_post_parse_sec_checks;
 mod pdf_pre_parse_sec_checks;
+mod rich_media_helpers;
+mod search_helpers;
+mod signature_helpers;
+mod text_helpers;
+mod xfa_helpers;
+mod xref_helpers;
+
+use extraction_helpers::ExtractKind;
 
 #[derive(Parser)]
 #[command(name = "uwu-pdf")]
@@ -24,10 +41,96 @@ enum Commands {
         input_file: PathBuf,
         #[arg(short = 'o', long, value_name = "DIR")]
         output_dir: Option<PathBuf>,
+        // reference pdf, streams it already contains won't be extracted
+        #[arg(long, value_name = "FILE")]
+        diff_base: Option<PathBuf>,
+        // streams smaller than this many bytes are skipped
+        #[arg(long, value_name = "BYTES", default_value_t = 0)]
+        min_size: usize,
+        // refuse documents with more objects than this
+        #[arg(long, value_name = "N")]
+        max_objects: Option<usize>,
+        // with --max-objects, process the first N objects instead of failing
+        #[arg(long, requires = "max_objects")]
+        sample: bool,
+        // user password for encrypted pdfs
+        #[arg(long)]
+        password: Option<String>,
+        // sha256 every extracted stream and write manifest.csv
+        #[arg(long)]
+        hash: bool,
+        // write identical images once, repeats only go in the manifest
+        #[arg(long)]
+        dedup: bool,
+        // stop extracting once this many bytes have been written
+        #[arg(long, value_name = "BYTES", default_value_t = 2 * 1024 * 1024 * 1024)]
+        max_total_bytes: u64,
+        // no progress bar
+        #[arg(short = 'q', long)]
+        quiet: bool,
+        // only extract these kinds, can be given more than once
+        #[arg(long, value_enum, value_name = "KIND")]
+        only: Vec<ExtractKind>,
     },
     Analyze {
         #[arg(short = 'i', long, value_name = "FILE")]
         input_file: PathBuf,
+        // print an indented map of the document catalog
+        #[arg(long)]
+        tree: bool,
+        // show which object stream each compressed object lives in
+        #[arg(long)]
+        objstm_map: bool,
+        // per page size, rotation and content summary
+        #[arg(long)]
+        pages: bool,
+        // how many of the biggest streams to list
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        top: usize,
+        // refuse documents with more objects than this
+        #[arg(long, value_name = "N")]
+        max_objects: Option<usize>,
+        // with --max-objects, process the first N objects instead of failing
+        #[arg(long, requires = "max_objects")]
+        sample: bool,
+        // json prints a single machine readable object, csv one row per object
+        #[arg(long, value_enum, default_value = "text")]
+        format: pdf_ops::OutputFormat,
+        // user password for encrypted pdfs
+        #[arg(long)]
+        password: Option<String>,
+        // exit 1 on javascript, actions, launch or hidden streams
+        #[arg(long, conflicts_with = "fail_on")]
+        fail_on_suspicious: bool,
+        // warnings also fails on data around the pdf body
+        #[arg(long, value_enum, value_name = "LEVEL")]
+        fail_on: Option<pdf_ops::FailOn>,
+    },
+    Dump {
+        #[arg(short = 'i', long, value_name = "FILE")]
+        input_file: PathBuf,
+        // object number to show
+        #[arg(long, value_name = "N")]
+        id: u32,
+        // generation number, almost always 0
+        #[arg(long = "gen", value_name = "G", default_value_t = 0)]
+        generation: u16,
+        // write stream content here instead of stdout
+        #[arg(short = 'o', long, value_name = "FILE")]
+        output: Option<PathBuf>,
+        // user password for encrypted pdfs
+        #[arg(long)]
+        password: Option<String>,
+    },
+    Grep {
+        #[arg(short = 'i', long, value_name = "FILE")]
+        input_file: PathBuf,
+        // regex matched against decoded streams and dictionary strings
+        #[arg(short = 'p', long, value_name = "REGEX")]
+        pattern: String,
+        // user password for encrypted pdfs
+        #[arg(long)]
+        password: Option<String>,
     },
 }
 
@@ -42,6 +145,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Extract {
             input_file,
             output_dir,
+            diff_base,
+            min_size,
+            max_objects,
+            sample,
+            password,
+            hash,
+            dedup,
+            max_total_bytes,
+            quiet,
+            only,
         } => {
             let output_path = output_dir.unwrap_or_else(|| {
                 let mut path = input_file.clone();
@@ -55,11 +168,78 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 output_path.display()
             );
 
-            pdf_ops::extract_pdf(&input_file, &output_path);
+            let options = pdf_ops::ExtractOptions {
+                diff_base,
+                min_size,
+                object_limit: pdf_ops::ObjectLimit {
+                    max_objects,
+                    sample,
+                },
+                password,
+                hash,
+                dedup,
+                max_total_bytes,
+                quiet,
+                only,
+            };
+            pdf_ops::extract_pdf(&input_file, &output_path, &options)?;
         }
-        Commands::Analyze { input_file } => {
+        Commands::Analyze {
+            input_file,
+            tree,
+            objstm_map,
+            pages,
+            top,
+            max_objects,
+            sample,
+            format,
+            password,
+            fail_on_suspicious,
+            fail_on,
+        } => {
             info!("analyzing pdf: {}", input_file.display());
-            pdf_ops::analyze_pdf(&input_file)?;
+            let options = pdf_ops::AnalyzeOptions {
+                show_tree: tree,
+                show_objstm_map: objstm_map,
+                show_pages: pages,
+                top,
+                object_limit: pdf_ops::ObjectLimit {
+                    max_objects,
+                    sample,
+                },
+                format,
+                password,
+                fail_on: fail_on.or(fail_on_suspicious.then_some(pdf_ops::FailOn::Suspicious)),
+            };
+            pdf_ops::analyze_pdf(&input_file, &options)?;
+        }
+        Commands::Dump {
+            input_file,
+            id,
+            generation,
+            output,
+            password,
+        } => {
+            info!(
+                "dumping object {} {} from {}",
+                id,
+                generation,
+                input_file.display()
+            );
+            pdf_ops::dump_object(
+                &input_file,
+                (id, generation),
+                output.as_ref(),
+                password.as_deref(),
+            )?;
+        }
+        Commands::Grep {
+            input_file,
+            pattern,
+            password,
+        } => {
+            info!("searching {} for {}", input_file.display(), pattern);
+            pdf_ops::grep_pdf(&input_file, &pattern, password.as_deref())?;
         }
     }
 
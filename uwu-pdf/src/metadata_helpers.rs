@@ -0,0 +1,376 @@
+use colored::Colorize;
+use log::{debug, info, trace, warn};
+use lopdf::Object;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dump_helpers::format_object;
+use crate::extraction_helpers::ByteBudget;
+
+/// unix time for 1990-01-01, anything older is probably made up
+const PLAUSIBLE_DATE_FLOOR: i64 = 631_152_000;
+
+/// a date pulled from the Info dict or XMP
+#[derive(Debug, Serialize)]
+pub struct MetadataDate {
+    pub source: String,
+    pub raw: String,
+    pub utc: Option<String>,
+    pub implausible: bool,
+}
+
+/// one entry of the trailer's /Info dict
+#[derive(Debug, Serialize)]
+pub struct InfoField {
+    pub key: String,
+    pub value: String,
+    pub utc: Option<String>,
+}
+
+/// every /Info entry (Author, Producer and so on) as text
+///
+/// values that parse as pdf dates also get a normalized utc form
+pub fn collect_info_fields(doc: &lopdf::Document) -> Vec<InfoField> {
+    let Some(info) = info_dictionary(doc) else {
+        return Vec::new();
+    };
+
+    info.iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Object::Reference(id) => doc.get_object(*id).unwrap_or(value),
+                other => other,
+            };
+            let value = match value {
+                Object::String(bytes, _) => decode_text_string(bytes),
+                other => format_object(other, 0),
+            };
+            let utc = if value.trim_start().starts_with("D:") {
+                parse_pdf_date(&value).map(format_rfc3339)
+            } else {
+                None
+            };
+            InfoField {
+                key: String::from_utf8_lossy(key).to_string(),
+                value,
+                utc,
+            }
+        })
+        .collect()
+}
+
+pub fn print_info_fields(fields: &[InfoField]) {
+    if fields.is_empty() {
+        return;
+    }
+
+    println!("{}", "「document info」".cyan().bold());
+    for field in fields {
+        match &field.utc {
+            Some(utc) => println!(
+                "  {}: {} ({})",
+                field.key.green(),
+                utc.green(),
+                field.value.yellow()
+            ),
+            None => println!("  {}: {}", field.key.green(), field.value.yellow()),
+        }
+    }
+    println!();
+}
+
+/// write the catalog's XMP packet to output_dir/metadata.xml
+pub fn extract_metadata(
+    doc: &lopdf::Document,
+    output_dir: &Path,
+    budget: &mut ByteBudget,
+) -> Result<usize, String> {
+    let Some(xmp) = xmp_packet(doc) else {
+        return Ok(0);
+    };
+
+    budget.reserve("metadata.xml", xmp.len())?;
+    if let Err(e) = fs::write(output_dir.join("metadata.xml"), &xmp) {
+        warn!("failed to write metadata.xml: {}", e);
+        return Ok(0);
+    }
+
+    println!(
+        "  {} {} ({} bytes)",
+        "「metadata」".green().bold(),
+        "metadata.xml".cyan(),
+        xmp.len().to_string().yellow()
+    );
+    info!("extracted XMP metadata");
+    Ok(1)
+}
+
+/// find and normalize every creation/modification date in the document
+pub fn collect_metadata_dates(doc: &lopdf::Document) -> Vec<MetadataDate> {
+    let mut dates = Vec::new();
+
+    if let Some(info) = info_dictionary(doc) {
+        for key in [&b"CreationDate"[..], &b"ModDate"[..]] {
+            if let Ok(Object::String(bytes, _)) = info.get(key) {
+                let raw = decode_text_string(bytes);
+                let source = format!("Info/{}", String::from_utf8_lossy(key));
+                dates.push(build_date(source, raw, parse_pdf_date));
+            }
+        }
+    }
+
+    if let Some(xmp) = xmp_packet(doc) {
+        for key in ["xmp:CreateDate", "xmp:ModifyDate", "xmp:MetadataDate"] {
+            if let Some(raw) = find_xmp_value(&xmp, key) {
+                dates.push(build_date(format!("XMP/{}", key), raw, parse_xmp_date));
+            }
+        }
+    }
+
+    dates
+}
+
+pub fn print_metadata_dates(dates: &[MetadataDate]) {
+    if dates.is_empty() {
+        return;
+    }
+
+    println!("{}", "「metadata dates」".cyan().bold());
+    for date in dates {
+        let normalized = match &date.utc {
+            Some(utc) => utc.green().to_string(),
+            None => "unparseable".red().to_string(),
+        };
+        println!(
+            "  {}: {} ({})",
+            date.source.green(),
+            normalized,
+            date.raw.yellow()
+        );
+        if date.implausible {
+            println!(
+                "    {} date is in the future or before 1990",
+                "「implausible date」".yellow().bold()
+            );
+        }
+    }
+    println!();
+}
+
+fn build_date(source: String, raw: String, parser: fn(&str) -> Option<i64>) -> MetadataDate {
+    let epoch = parser(&raw);
+    trace!("parsed {} {:?} -> {:?}", source, raw, epoch);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(i64::MAX);
+
+    MetadataDate {
+        source,
+        utc: epoch.map(format_rfc3339),
+        implausible: epoch.is_some_and(|t| t > now || t < PLAUSIBLE_DATE_FLOOR),
+        raw,
+    }
+}
+
+pub fn info_dictionary(doc: &lopdf::Document) -> Option<&lopdf::Dictionary> {
+    match doc.trailer.get(b"Info").ok()? {
+        Object::Reference(id) => doc.get_dictionary(*id).ok(),
+        Object::Dictionary(dict) => Some(dict),
+        _ => None,
+    }
+}
+
+/// the catalog's /Metadata stream as text
+pub fn xmp_packet(doc: &lopdf::Document) -> Option<String> {
+    let catalog = doc.catalog().ok()?;
+    let metadata_id = catalog.get(b"Metadata").ok()?.as_reference().ok()?;
+    let stream = doc.get_object(metadata_id).ok()?.as_stream().ok()?;
+    let content = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    debug!("found XMP packet ({} bytes)", content.len());
+    Some(String::from_utf8_lossy(&content).to_string())
+}
+
+/// pdf text strings are either PDFDocEncoding or UTF-16BE with a BOM
+pub fn decode_text_string(bytes: &[u8]) -> String {
+    if bytes.len() >= 2 && bytes[0] == 0xFE && bytes[1] == 0xFF {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+/// pull a value out of an XMP packet, either element or attribute form
+pub fn find_xmp_value(xmp: &str, key: &str) -> Option<String> {
+    let open_tag = format!("<{}>", key);
+    let close_tag = format!("</{}>", key);
+    if let Some(start) = xmp.find(&open_tag) {
+        let value_start = start + open_tag.len();
+        let value_end = value_start + xmp[value_start..].find(&close_tag)?;
+        return Some(xmp[value_start..value_end].trim().to_string());
+    }
+
+    let attribute = format!("{}=\"", key);
+    let start = xmp.find(&attribute)? + attribute.len();
+    let end = start + xmp[start..].find('"')?;
+    Some(xmp[start..end].trim().to_string())
+}
+
+/// parse D:YYYYMMDDHHmmSSOHH'mm' into unix seconds
+///
+/// everything after the year is optional, missing parts default
+/// to the start of the period and a missing offset means UTC
+fn parse_pdf_date(raw: &str) -> Option<i64> {
+    let s = raw.trim();
+    let s = s.strip_prefix("D:").unwrap_or(s);
+    let bytes = s.as_bytes();
+
+    let mut pos = 0;
+    let mut take = |len: usize, default: i64| -> Option<i64> {
+        if pos + len <= bytes.len() && bytes[pos..pos + len].iter().all(u8::is_ascii_digit) {
+            let value = s[pos..pos + len].parse().ok();
+            pos += len;
+            value
+        } else {
+            Some(default)
+        }
+    };
+
+    let year = take(4, -1)?;
+    if year < 0 {
+        return None;
+    }
+    let month = take(2, 1)?;
+    let day = take(2, 1)?;
+    let hour = take(2, 0)?;
+    let minute = take(2, 0)?;
+    let second = take(2, 0)?;
+
+    let offset = match bytes.get(pos) {
+        Some(b'+') | Some(b'-') => {
+            let sign = if bytes[pos] == b'-' { -1 } else { 1 };
+            let rest: String = s[pos + 1..]
+                .chars()
+                .filter(|c| c.is_ascii_digit())
+                .collect();
+            let offset_hours: i64 = rest.get(0..2)?.parse().ok()?;
+            let offset_minutes: i64 = rest.get(2..4).and_then(|m| m.parse().ok()).unwrap_or(0);
+            sign * (offset_hours * 3600 + offset_minutes * 60)
+        }
+        _ => 0,
+    };
+
+    to_unix_seconds(year, month, day, hour, minute, second).map(|t| t - offset)
+}
+
+/// parse an ISO 8601 date from XMP into unix seconds
+fn parse_xmp_date(raw: &str) -> Option<i64> {
+    let s = raw.trim();
+    let (date_part, time_part) = match s.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+
+    let mut date_fields = date_part.split('-');
+    let year: i64 = date_fields.next()?.parse().ok()?;
+    let month: i64 = date_fields.next().map_or(Some(1), |m| m.parse().ok())?;
+    let day: i64 = date_fields.next().map_or(Some(1), |d| d.parse().ok())?;
+
+    let (mut hour, mut minute, mut second, mut offset) = (0, 0, 0, 0);
+    if let Some(time) = time_part {
+        let (clock, zone) = match time.find(['Z', '+', '-']) {
+            Some(idx) => time.split_at(idx),
+            None => (time, ""),
+        };
+
+        let mut clock_fields = clock.split(':');
+        hour = clock_fields.next()?.parse().ok()?;
+        minute = clock_fields.next().map_or(Some(0), |m| m.parse().ok())?;
+        second = clock_fields
+            .next()
+            .map_or(Some(0.0), |s| s.parse::<f64>().ok())? as i64;
+
+        if let Some(sign_char) = zone.chars().next()
+            && sign_char != 'Z'
+        {
+            let sign = if sign_char == '-' { -1 } else { 1 };
+            let zone_digits = &zone[1..];
+            let (zone_hours, zone_minutes) = match zone_digits.split_once(':') {
+                Some(parts) => parts,
+                None if zone_digits.len() == 4 => zone_digits.split_at(2),
+                None => (zone_digits, "0"),
+            };
+            offset = sign
+                * (zone_hours.parse::<i64>().ok()? * 3600 + zone_minutes.parse::<i64>().ok()? * 60);
+        }
+    }
+
+    to_unix_seconds(year, month, day, hour, minute, second).map(|t| t - offset)
+}
+
+fn to_unix_seconds(
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+) -> Option<i64> {
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..=23).contains(&hour)
+        || !(0..=59).contains(&minute)
+        || !(0..=60).contains(&second)
+    {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// days since 1970-01-01 for a proleptic gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn format_rfc3339(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(86_400));
+    let secs = timestamp.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
@@ -0,0 +1,99 @@
+use colored::Colorize;
+use log::{debug, trace};
+use lopdf::xref::XrefEntry;
+use std::collections::HashMap;
+
+/// one compressed object and where it lives
+#[derive(Debug)]
+pub struct ObjStmEntry {
+    pub object_id: u32,
+    pub container_id: u32,
+    pub index: u16,
+    pub offset: Option<usize>,
+}
+
+/// map every compressed object to its containing object stream
+///
+/// the container and index come straight from the xref, the byte
+/// offset is read from the ObjStm's own header of number/offset pairs
+pub fn collect_objstm_map(doc: &lopdf::Document) -> Vec<ObjStmEntry> {
+    let mut headers: HashMap<u32, Option<Vec<(u32, usize)>>> = HashMap::new();
+    let mut entries = Vec::new();
+
+    for (object_id, entry) in doc.reference_table.entries.iter() {
+        let XrefEntry::Compressed { container, index } = entry else {
+            continue;
+        };
+
+        let header = headers
+            .entry(*container)
+            .or_insert_with(|| parse_objstm_header(doc, *container));
+
+        let offset = header
+            .as_ref()
+            .and_then(|pairs| pairs.get(*index as usize))
+            .map(|(_, offset)| *offset);
+
+        entries.push(ObjStmEntry {
+            object_id: *object_id,
+            container_id: *container,
+            index: *index,
+            offset,
+        });
+    }
+
+    debug!(
+        "found {} compressed objects in {} object streams",
+        entries.len(),
+        headers.len()
+    );
+    entries
+}
+
+/// read the (object number, absolute offset) pairs at the start of an ObjStm
+fn parse_objstm_header(doc: &lopdf::Document, container: u32) -> Option<Vec<(u32, usize)>> {
+    let stream = doc.get_object((container, 0)).ok()?.as_stream().ok()?;
+    let count = stream.dict.get(b"N").ok()?.as_i64().ok()? as usize;
+    let first = stream.dict.get(b"First").ok()?.as_i64().ok()? as usize;
+    let content = stream.decompressed_content().ok()?;
+
+    let header = std::str::from_utf8(content.get(..first)?).ok()?;
+    let numbers: Vec<usize> = header
+        .split_ascii_whitespace()
+        .filter_map(|n| n.parse().ok())
+        .collect();
+
+    let pairs: Vec<(u32, usize)> = numbers
+        .chunks_exact(2)
+        .take(count)
+        .map(|pair| (pair[0] as u32, first + pair[1]))
+        .collect();
+
+    trace!("ObjStm {} header: {:?}", container, pairs);
+    Some(pairs)
+}
+
+pub fn print_objstm_map(entries: &[ObjStmEntry]) {
+    println!("{}", "「object stream map」".cyan().bold());
+
+    if entries.is_empty() {
+        println!("  {}", "no compressed objects".green());
+        println!();
+        return;
+    }
+
+    for entry in entries {
+        let offset = match entry.offset {
+            Some(offset) => offset.to_string().yellow(),
+            None => "unknown".red(),
+        };
+        println!(
+            "  Object {} in ObjStm {} (index {}, offset {})",
+            entry.object_id.to_string().cyan(),
+            entry.container_id.to_string().cyan(),
+            entry.index.to_string().yellow(),
+            offset
+        );
+    }
+    println!();
+}
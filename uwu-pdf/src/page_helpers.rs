@@ -0,0 +1,150 @@
+use colored::Colorize;
+use log::{debug, trace};
+use lopdf::Object;
+use lopdf::content::Content;
+use serde::Serialize;
+
+use crate::text_helpers::page_text;
+
+/// how far up /Parent we look for inherited attributes
+const MAX_INHERIT_DEPTH: usize = 32;
+
+/// what a single page looks like from the outside
+#[derive(Debug, Serialize)]
+pub struct PageSummary {
+    pub number: u32,
+    pub object_id: (u32, u16),
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+    pub rotation: i64,
+    pub content_streams: usize,
+    pub has_annotations: bool,
+    pub text_bytes: usize,
+    /// no content operators at all
+    pub blank: bool,
+}
+
+pub fn collect_page_summaries(doc: &lopdf::Document) -> Vec<PageSummary> {
+    let mut summaries = Vec::new();
+
+    for (number, page_id) in doc.get_pages() {
+        let Ok(page) = doc.get_dictionary(page_id) else {
+            debug!("page {} ({:?}) is not a dictionary", number, page_id);
+            continue;
+        };
+
+        let media_box = inherited(doc, page, b"MediaBox").and_then(|object| {
+            let values: Vec<f32> = resolve(doc, object)
+                .as_array()
+                .ok()?
+                .iter()
+                .filter_map(|value| value.as_float().ok())
+                .collect();
+            match values[..] {
+                [x0, y0, x1, y1] => Some(((x1 - x0).abs(), (y1 - y0).abs())),
+                _ => None,
+            }
+        });
+        let rotation = inherited(doc, page, b"Rotate")
+            .and_then(|rotate| resolve(doc, rotate).as_i64().ok())
+            .unwrap_or(0);
+
+        let content_streams = match page.get(b"Contents") {
+            Ok(Object::Array(items)) => items.len(),
+            Ok(Object::Reference(id)) => match doc.get_object(*id) {
+                Ok(Object::Array(items)) => items.len(),
+                Ok(_) => 1,
+                Err(_) => 0,
+            },
+            Ok(_) => 1,
+            Err(_) => 0,
+        };
+        let has_annotations = match page.get(b"Annots").map(|annots| resolve(doc, annots)) {
+            Ok(Object::Array(items)) => !items.is_empty(),
+            _ => false,
+        };
+
+        let content = doc.get_page_content(page_id).unwrap_or_default();
+        let blank = Content::decode(&content)
+            .map(|decoded| decoded.operations.is_empty())
+            .unwrap_or(content.is_empty());
+        let text_bytes = page_text(&content).trim().len();
+
+        trace!(
+            "page {}: {} content streams, {} text bytes",
+            number, content_streams, text_bytes
+        );
+
+        summaries.push(PageSummary {
+            number,
+            object_id: page_id,
+            width: media_box.map(|(width, _)| width),
+            height: media_box.map(|(_, height)| height),
+            rotation,
+            content_streams,
+            has_annotations,
+            text_bytes,
+            blank,
+        });
+    }
+
+    summaries
+}
+
+/// look a key up on the page, then on each /Parent in turn
+fn inherited<'a>(
+    doc: &'a lopdf::Document,
+    page: &'a lopdf::Dictionary,
+    key: &[u8],
+) -> Option<&'a Object> {
+    let mut node = page;
+    for _ in 0..MAX_INHERIT_DEPTH {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        let parent_id = node.get(b"Parent").ok()?.as_reference().ok()?;
+        node = doc.get_dictionary(parent_id).ok()?;
+    }
+    None
+}
+
+fn resolve<'a>(doc: &'a lopdf::Document, object: &'a Object) -> &'a Object {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).unwrap_or(object),
+        other => other,
+    }
+}
+
+pub fn print_page_summaries(pages: &[PageSummary]) {
+    if pages.is_empty() {
+        return;
+    }
+
+    println!("{}", "「pages」".cyan().bold());
+    for page in pages {
+        let size = match (page.width, page.height) {
+            (Some(width), Some(height)) => format!("{:.0}x{:.0}", width, height),
+            _ => "no MediaBox".to_string(),
+        };
+        println!(
+            "  {} {} rotate {}, {} content streams, {} text bytes{}",
+            format!("Page {}:", page.number).green(),
+            size.cyan(),
+            page.rotation,
+            page.content_streams,
+            page.text_bytes.to_string().yellow(),
+            if page.has_annotations {
+                ", annotations"
+            } else {
+                ""
+            }
+        );
+        if page.blank {
+            println!(
+                "    {} page has no content operators",
+                "「blank page」".yellow().bold()
+            );
+        }
+    }
+    println!();
+}
@@ -0,0 +1,35 @@
+/// /P bits (1 based, per the spec) and what they allow
+const PERMISSION_BITS: &[(u32, &str)] = &[
+    (3, "print"),
+    (4, "modify"),
+    (5, "copy text and images"),
+    (6, "annotate"),
+    (9, "fill forms"),
+    (10, "accessibility extraction"),
+    (11, "assemble"),
+    (12, "high quality print"),
+];
+
+/// true while /Encrypt is still in the trailer
+///
+/// lopdf drops it once it has decrypted the document, so this
+/// only stays true for pdfs that none of the passwords opened
+pub fn is_encrypted(doc: &lopdf::Document) -> bool {
+    doc.trailer.get(b"Encrypt").is_ok()
+}
+
+/// what the /P entry withholds, empty when everything is allowed
+///
+/// reads the permissions lopdf kept from /Encrypt when it decrypted
+/// the document, so a pdf that never got decrypted reports nothing
+pub fn denied_permissions(doc: &lopdf::Document) -> Vec<&'static str> {
+    let Some(state) = &doc.encryption_state else {
+        return Vec::new();
+    };
+    let permissions = state.permissions().bits();
+    PERMISSION_BITS
+        .iter()
+        .filter(|(bit, _)| permissions & (1 << (bit - 1)) == 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
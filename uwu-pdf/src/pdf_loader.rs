@@ -0,0 +1,88 @@
+use log::{debug, error, info, warn};
+use std::path::PathBuf;
+
+use crate::pdf_pre_parse_sec_checks;
+use crate::pdf_pre_parse_sec_checks::PreParseResults;
+use crate::xref_helpers::rebuild_xref;
+
+/// load pdf, check for issues and try repair
+///
+/// we'll look for things like appended and prepended data
+/// and report these as possible security issues since
+/// data could be hidden here, the padding itself is kept
+/// in the returned pre-parse results
+pub fn repair_and_load_pdf(
+    file_path: &PathBuf,
+    password: Option<&str>,
+) -> Result<(lopdf::Document, PreParseResults), lopdf::Error> {
+    info!("loading and repairing PDF: {}", file_path.display());
+
+    let pdf_bytes = std::fs::read(file_path).map_err(|e| {
+        error!("could not read file: {}", e);
+        lopdf::Error::IO(e)
+    })?;
+
+    debug!("read {} bytes from PDF file", pdf_bytes.len());
+
+    load_pdf_bytes(pdf_bytes, password)
+}
+
+/// same as repair_and_load_pdf but for bytes already in memory
+///
+/// encrypted pdfs are decrypted by lopdf as they load, with the
+/// empty user password or the one given here
+pub fn load_pdf_bytes(
+    mut pdf_bytes: Vec<u8>,
+    password: Option<&str>,
+) -> Result<(lopdf::Document, PreParseResults), lopdf::Error> {
+    let mut pre_parse_results = pdf_pre_parse_sec_checks::pre_parse_sec_checks(&pdf_bytes);
+
+    if let Some(prepend_bytes) = pre_parse_results.prepended_bytes {
+        pdf_bytes = pdf_bytes[prepend_bytes..].to_vec();
+    }
+
+    if pre_parse_results.appended_bytes.is_some() {
+        if let Some(eof_position) = pdf_bytes.windows(5).rposition(|window| window == b"%%EOF") {
+            pdf_bytes.truncate(eof_position + 5);
+        }
+    }
+
+    let load = |bytes: &[u8]| match password {
+        Some(password) => lopdf::Document::load_mem_with_password(bytes, password),
+        None => lopdf::Document::load_mem(bytes),
+    };
+
+    let load_error = match load(&pdf_bytes) {
+        Ok(doc) => {
+            info!("pdf loaded successfully");
+            return Ok((doc, pre_parse_results));
+        }
+        Err(lopdf::Error::InvalidPassword) => {
+            error!("could not decrypt pdf, the password is wrong");
+            return Err(lopdf::Error::InvalidPassword);
+        }
+        Err(e) => e,
+    };
+
+    warn!(
+        "failed to load PDF ({:?}), trying to rebuild the xref",
+        load_error
+    );
+    let rebuilt = rebuild_xref(&pdf_bytes).and_then(|bytes| {
+        load(&bytes)
+            .inspect_err(|e| debug!("rebuilt xref didn't load either: {:?}", e))
+            .ok()
+    });
+
+    match rebuilt {
+        Some(doc) => {
+            warn!("xref table was rebuilt by scanning for objects");
+            pre_parse_results.xref_rebuilt = true;
+            Ok((doc, pre_parse_results))
+        }
+        None => {
+            error!("failed to load PDF: {:?}", load_error);
+            Err(load_error)
+        }
+    }
+}
@@ -1,88 +1,183 @@
-use log::{debug, error, info};
+use clap::ValueEnum;
+use colored::Colorize;
+use log::{debug, error, info, warn};
+use serde::Serialize;
 use std::fs;
+use std::io::{IsTerminal, Write};
 use std::path::PathBuf;
 
-use crate::analysis_helpers::{PdfStats, count_object_types, print_pdf_stats};
+use crate::analysis_helpers::{
+    PdfStats, StreamSize, gather_pdf_stats, largest_streams, object_table_csv, print_catalog_tree,
+    print_largest_streams, print_pdf_stats,
+};
+use crate::dump_helpers::print_object_dump;
 use crate::extraction_helpers::{
-    extract_padding, extract_pdf_streams, print_extraction_header, print_extraction_summary,
+    ByteBudget, ExtractKind, StreamOptions, extract_padding, extract_pdf_streams,
+    hash_stream_contents, print_extraction_header, print_extraction_summary,
+};
+use crate::metadata_helpers::{
+    InfoField, MetadataDate, collect_info_fields, collect_metadata_dates, print_info_fields,
+    print_metadata_dates,
+};
+use crate::objstm_helpers::{collect_objstm_map, print_objstm_map};
+use crate::page_helpers::{PageSummary, collect_page_summaries, print_page_summaries};
+use crate::pdf_crypt;
+use crate::pdf_loader::repair_and_load_pdf;
+use crate::pdf_post_parse_sec_checks::{
+    SuspiciousFeatures, detect_suspicious_features, print_post_parse_warnings,
 };
-use crate::pdf_post_parse_sec_checks;
-use crate::pdf_pre_parse_sec_checks;
-use crate::pdf_pre_parse_sec_checks::PreParseResults;
+use crate::pdf_pre_parse_sec_checks::{PreParseResults, print_pre_parse_warnings};
+use crate::search_helpers::{print_search_matches, search_document};
+use crate::signature_helpers::check_signature_coverage;
 
-/// load pdf, check for issues and try repair
-///
-/// we'll look for things like appended and prepended data
-/// and report these as possible security issues since
-/// data could be hidden here
-pub fn repair_and_load_pdf(
-    file_path: &PathBuf,
-) -> Result<(lopdf::Document, PreParseResults), lopdf::Error> {
-    info!("loading and repairing PDF: {}", file_path.display());
+/// how analyze reports its results
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    /// one row per object instead of a report
+    Csv,
+}
 
-    let mut repair_info = PreParseResults::default();
+/// what makes analyze exit non-zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FailOn {
+    /// javascript, auto or open actions, launch actions, hidden streams
+    Suspicious,
+    /// suspicious plus data before the header or after the last %%EOF
+    Warnings,
+}
 
-    let mut pdf_bytes = std::fs::read(file_path).map_err(|e| {
-        error!("could not read file: {}", e);
-        lopdf::Error::IO(e)
-    })?;
+/// optional extra reports for analyze
+#[derive(Debug, Default)]
+pub struct AnalyzeOptions {
+    pub show_tree: bool,
+    pub show_objstm_map: bool,
+    pub show_pages: bool,
+    pub top: usize,
+    pub object_limit: ObjectLimit,
+    pub format: OutputFormat,
+    pub password: Option<String>,
+    /// none keeps the exit code at 0 whatever is found
+    pub fail_on: Option<FailOn>,
+}
 
-    debug!("read {} bytes from PDF file", pdf_bytes.len());
+/// settings for extract
+#[derive(Debug)]
+pub struct ExtractOptions {
+    pub diff_base: Option<PathBuf>,
+    pub min_size: usize,
+    pub object_limit: ObjectLimit,
+    pub password: Option<String>,
+    pub hash: bool,
+    /// write identical images only once
+    pub dedup: bool,
+    pub max_total_bytes: u64,
+    pub quiet: bool,
+    /// empty means every kind
+    pub only: Vec<ExtractKind>,
+}
 
-    let pre_parse_results = pdf_pre_parse_sec_checks::pre_parse_sec_checks(&pdf_bytes);
+/// everything analyze found, as written by --format json
+#[derive(Debug, Serialize)]
+struct AnalysisReport<'a> {
+    file: String,
+    partial: bool,
+    pre_parse: &'a PreParseResults,
+    stats: &'a PdfStats,
+    suspicious_features: &'a SuspiciousFeatures,
+    info: &'a [InfoField],
+    largest_streams: &'a [StreamSize],
+    pages: &'a [PageSummary],
+    metadata_dates: &'a [MetadataDate],
+}
 
-    if let Some(prepend_bytes) = pre_parse_results.prepended_bytes {
-        repair_info.prepended_bytes = Some(prepend_bytes);
-        pdf_bytes = pdf_bytes[prepend_bytes..].to_vec();
-    }
+/// cap on how many objects we're willing to walk
+///
+/// without sample an oversized document is refused outright,
+/// with it only the first max_objects objects get processed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ObjectLimit {
+    pub max_objects: Option<usize>,
+    pub sample: bool,
+}
 
-    if let Some(append_bytes) = pre_parse_results.appended_bytes {
-        repair_info.appended_bytes = Some(append_bytes);
+/// apply the object limit, trimming the object table in sample mode
+///
+/// returns the original object count if anything was dropped
+fn enforce_object_limit(
+    doc: &mut lopdf::Document,
+    limit: ObjectLimit,
+) -> Result<Option<usize>, String> {
+    let Some(max_objects) = limit.max_objects else {
+        return Ok(None);
+    };
 
-        if let Some(eof_position) = pdf_bytes.windows(5).position(|window| window == b"%%EOF") {
-            pdf_bytes.truncate(eof_position + 5);
-        }
+    let object_count = doc.objects.len();
+    if object_count <= max_objects {
+        return Ok(None);
     }
 
-    match lopdf::Document::load_mem(&pdf_bytes) {
-        Ok(doc) => {
-            info!("pdf loaded successfully");
-            Ok((doc, repair_info))
-        }
-        Err(e) => {
-            error!("failed to load PDF: {:?}", e);
-            Err(e)
-        }
+    if !limit.sample {
+        return Err(format!(
+            "document has {} objects, over the --max-objects limit of {} (use --sample to process the first {})",
+            object_count, max_objects, max_objects
+        ));
     }
-}
 
-/// load pdf from bytes with pre-parse results already computed
-fn load_pdf_from_bytes(
-    mut pdf_bytes: Vec<u8>,
-    pre_parse_results: &PreParseResults,
-) -> Result<lopdf::Document, lopdf::Error> {
-    use log::error;
+    warn!(
+        "sampling {} of {} objects, results will be partial",
+        max_objects, object_count
+    );
+    doc.objects = std::mem::take(&mut doc.objects)
+        .into_iter()
+        .take(max_objects)
+        .collect();
 
-    if let Some(prepend_bytes) = pre_parse_results.prepended_bytes {
-        pdf_bytes = pdf_bytes[prepend_bytes..].to_vec();
+    Ok(Some(object_count))
+}
+
+/// see how lopdf got on with an encrypted pdf
+///
+/// the decrypting itself happens while loading, lopdf tries the
+/// empty user password and then --password. a wrong --password
+/// already failed the load, so anything still encrypted here just
+/// needs a password we weren't given.
+/// returns true when the empty password is what opened it
+fn check_decryption(doc: &lopdf::Document, password: Option<&str>) -> bool {
+    // already decrypted, a second pass would re-encrypt rc4 data
+    if doc.encryption_state.is_some() {
+        return password.is_none();
     }
 
-    if pre_parse_results.appended_bytes.is_some() {
-        if let Some(eof_position) = pdf_bytes.windows(5).position(|window| window == b"%%EOF") {
-            pdf_bytes.truncate(eof_position + 5);
-        }
+    if pdf_crypt::is_encrypted(doc) {
+        warn!("pdf is encrypted and the empty password didn't work, try --password");
     }
+    false
+}
 
-    match lopdf::Document::load_mem(&pdf_bytes) {
-        Ok(doc) => {
-            info!("pdf loaded successfully");
-            Ok(doc)
-        }
-        Err(e) => {
-            error!("failed to load PDF: {:?}", e);
-            Err(e)
-        }
+/// note that the pdf was only "encrypted" to restrict permissions
+fn print_empty_password_notice(doc: &lopdf::Document) {
+    let denied = pdf_crypt::denied_permissions(doc);
+    println!(
+        "{} encrypted with an empty user password, decrypted automatically",
+        "「empty password」".cyan().bold()
+    );
+    if !denied.is_empty() {
+        println!("  {} {}", "Restricts:".green(), denied.join(", ").yellow());
     }
+    println!();
+}
+
+fn print_partial_notice(processed: usize, total: usize) {
+    println!(
+        "{} only the first {} of {} objects were processed, results are partial",
+        "「partial」".yellow().bold(),
+        processed.to_string().yellow(),
+        total.to_string().yellow()
+    );
+    println!();
 }
 
 /// prints PDF object counts and does a simple security check
@@ -92,36 +187,152 @@ fn load_pdf_from_bytes(
 /// digging into before you invest the time
 ///
 /// probably not super reliable but it's a decent start?
-pub fn analyze_pdf(file_path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+pub fn analyze_pdf(
+    file_path: &PathBuf,
+    options: &AnalyzeOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
     info!("starting PDF analysis");
-    let (doc, _) = repair_and_load_pdf(file_path)?;
-
-    let mut stats = PdfStats::default();
-    stats.object_count = doc.objects.len();
-    stats.page_count = doc.get_pages().len();
+    let text_output = options.format == OutputFormat::Text;
 
-    debug!(
-        "analyzing {} objects across {} pages",
-        stats.object_count, stats.page_count
-    );
+    let (mut doc, pre_parse_results) = repair_and_load_pdf(file_path, options.password.as_deref())?;
+    if text_output {
+        print_pre_parse_warnings(&pre_parse_results);
+    }
+    let empty_password = check_decryption(&doc, options.password.as_deref());
+    if empty_password && text_output {
+        print_empty_password_notice(&doc);
+    }
 
-    for (_object_id, object) in doc.objects.iter() {
-        count_object_types(object, &mut stats);
+    let object_total = doc.objects.len();
+    let sampled_from = enforce_object_limit(&mut doc, options.object_limit)?;
+    if let (Some(total), true) = (sampled_from, text_output) {
+        print_partial_notice(doc.objects.len(), total);
     }
 
+    let mut stats = gather_pdf_stats(&doc);
+    stats.object_count = object_total;
+    stats.header_offset = pre_parse_results.prepended_bytes.unwrap_or(0);
+
     info!("running post-parse security checks");
-    pdf_post_parse_sec_checks::post_parse_sec_checks(&doc);
+    let mut features = detect_suspicious_features(&doc);
+    check_signature_coverage(&mut features.signatures, pre_parse_results.file_size);
+    let info_fields = collect_info_fields(&doc);
+    let largest = largest_streams(&doc, &features.referenced_ids, options.top);
+    let dates = collect_metadata_dates(&doc);
+    let pages = if options.show_pages {
+        collect_page_summaries(&doc)
+    } else {
+        Vec::new()
+    };
 
-    print_pdf_stats(stats);
+    if !text_output && (options.show_tree || options.show_objstm_map) {
+        warn!("--tree and --objstm-map only apply to --format text");
+    }
+
+    if options.format == OutputFormat::Csv {
+        print!("{}", object_table_csv(&doc, &features.referenced_ids));
+        check_fail_on(options.fail_on, &pre_parse_results, &features)?;
+        return Ok(());
+    }
 
+    if options.format == OutputFormat::Json {
+        let report = AnalysisReport {
+            file: file_path.display().to_string(),
+            partial: sampled_from.is_some(),
+            pre_parse: &pre_parse_results,
+            stats: &stats,
+            suspicious_features: &features,
+            info: &info_fields,
+            largest_streams: &largest,
+            pages: &pages,
+            metadata_dates: &dates,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        check_fail_on(options.fail_on, &pre_parse_results, &features)?;
+        return Ok(());
+    }
+
+    print_post_parse_warnings(&features);
+    print_pdf_stats(&stats);
+    print_largest_streams(&largest);
+    print_info_fields(&info_fields);
+    print_metadata_dates(&dates);
+    print_page_summaries(&pages);
+
+    if options.show_tree {
+        print_catalog_tree(&doc);
+    }
+
+    if options.show_objstm_map {
+        print_objstm_map(&collect_objstm_map(&doc));
+    }
+
+    check_fail_on(options.fail_on, &pre_parse_results, &features)?;
     Ok(())
 }
 
+/// turn findings into an error when --fail-on asked for it
+///
+/// the report has already been printed by the time this runs,
+/// so the error only needs to say why the exit code is 1
+fn check_fail_on(
+    fail_on: Option<FailOn>,
+    pre_parse: &PreParseResults,
+    features: &SuspiciousFeatures,
+) -> Result<(), String> {
+    let Some(level) = fail_on else {
+        return Ok(());
+    };
+
+    let mut findings = Vec::new();
+    if features.has_javascript {
+        findings.push("javascript");
+    }
+    if features.has_auto_action {
+        findings.push("auto actions");
+    }
+    if features.has_open_action {
+        findings.push("open actions");
+    }
+    if !features.launch_actions.is_empty() {
+        findings.push("launch actions");
+    }
+    if !features.large_unreferenced_streams.is_empty() {
+        findings.push("hidden streams");
+    }
+    if level == FailOn::Warnings {
+        if pre_parse.prepended_bytes.is_some() {
+            findings.push("prepended data");
+        }
+        if pre_parse.appended_bytes.is_some() {
+            findings.push("appended data");
+        }
+    }
+
+    if findings.is_empty() {
+        return Ok(());
+    }
+    debug!("failing analysis on {:?}", findings);
+    Err(format!("suspicious findings: {}", findings.join(", ")))
+}
+
 /// extracts objects from pdf
 ///
 /// currently handles text fields, binary data, and images,
 /// though implementation is likely incomplete.
-pub fn extract_pdf(input_file: &PathBuf, output_dir: &PathBuf) {
+///
+/// if a diff base is given, streams whose content also appears
+/// in that reference pdf are skipped, as are streams smaller
+/// than min_size bytes. with hash every written stream gets a
+/// sha256 on its console line and a row in manifest.csv
+///
+/// extraction stops once max_total_bytes have been written,
+/// whatever made it to disk before that is left alone
+pub fn extract_pdf(
+    input_file: &PathBuf,
+    output_dir: &PathBuf,
+    options: &ExtractOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
     info!(
         "Starting PDF extraction: {} -> {}",
         input_file.display(),
@@ -129,41 +340,276 @@ pub fn extract_pdf(input_file: &PathBuf, output_dir: &PathBuf) {
     );
     print_extraction_header(input_file, output_dir);
 
-    let pdf_bytes = match fs::read(input_file) {
-        Ok(bytes) => {
-            debug!("Read {} bytes from input file", bytes.len());
-            bytes
-        }
-        Err(e) => {
-            error!("Failed to read input file: {}", e);
-            return;
-        }
-    };
+    let (mut doc, pre_parse_results) =
+        repair_and_load_pdf(input_file, options.password.as_deref())?;
+    print_pre_parse_warnings(&pre_parse_results);
+    if check_decryption(&doc, options.password.as_deref()) {
+        print_empty_password_notice(&doc);
+    }
 
-    let pre_parse_results = pdf_pre_parse_sec_checks::pre_parse_sec_checks(&pdf_bytes);
+    if let Some(total) = enforce_object_limit(&mut doc, options.object_limit)? {
+        print_partial_notice(doc.objects.len(), total);
+    }
 
-    if fs::create_dir_all(output_dir).is_err() {
+    fs::create_dir_all(output_dir).map_err(|e| {
         error!(
-            "Could not create output directory: {}",
-            output_dir.display()
+            "Could not create output directory {}: {}",
+            output_dir.display(),
+            e
         );
-        return;
-    }
+        e
+    })?;
 
-    extract_padding(output_dir, &pre_parse_results);
+    let mut budget = ByteBudget::new(options.max_total_bytes);
+    let print_aborted = |written: u64| {
+        println!(
+            "{} stopped after {} bytes, files already written are left in {}",
+            "「aborted」".red().bold(),
+            written.to_string().yellow(),
+            output_dir.display().to_string().cyan()
+        );
+    };
+    extract_padding(output_dir, &pre_parse_results, &mut budget)
+        .inspect_err(|_| print_aborted(budget.written))?;
 
-    let doc = match load_pdf_from_bytes(pdf_bytes, &pre_parse_results) {
-        Ok(doc) => doc,
-        Err(e) => {
-            error!("Could not load PDF: {:?}", e);
-            return;
+    let base_hashes = match &options.diff_base {
+        Some(base_path) => {
+            let base_doc = lopdf::Document::load(base_path).map_err(|e| {
+                error!("Could not load diff base PDF: {:?}", e);
+                e
+            })?;
+            debug!("loaded diff base {}", base_path.display());
+            Some(hash_stream_contents(&base_doc))
         }
+        None => None,
     };
 
-    let counts = extract_pdf_streams(&doc, output_dir);
+    let stream_options = StreamOptions {
+        base_hashes: base_hashes.as_ref(),
+        min_size: options.min_size,
+        hash: options.hash,
+        dedup: options.dedup,
+        show_progress: !options.quiet
+            && std::io::stdout().is_terminal()
+            && std::io::stderr().is_terminal(),
+        only: &options.only,
+    };
+    let counts = extract_pdf_streams(&doc, output_dir, &stream_options, &mut budget)
+        .inspect_err(|_| print_aborted(budget.written))?;
     info!(
-        "Extraction complete: {} images, {} text files, {} binary files",
-        counts.images, counts.text, counts.binary
+        "Extraction complete: {} images, {} inline images, {} text files, {} binary files",
+        counts.images, counts.inline_images, counts.text, counts.binary
     );
     print_extraction_summary(&counts, &pre_parse_results);
+
+    Ok(())
+}
+
+/// print a single object, and for streams write the decoded content
+///
+/// content goes to output if given, otherwise straight to stdout
+/// after the object header
+pub fn dump_object(
+    input_file: &PathBuf,
+    object_id: (u32, u16),
+    output: Option<&PathBuf>,
+    password: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (doc, _) = repair_and_load_pdf(input_file, password)?;
+    check_decryption(&doc, password);
+
+    let object = doc.get_object(object_id).map_err(|e| {
+        error!("Object {} {} not found: {:?}", object_id.0, object_id.1, e);
+        format!("object {} {} not found", object_id.0, object_id.1)
+    })?;
+    print_object_dump(&doc, object_id, object);
+
+    let Ok(stream) = object.as_stream() else {
+        if output.is_some() {
+            warn!("object is not a stream, nothing written");
+        }
+        return Ok(());
+    };
+
+    let content = stream.decompressed_content().unwrap_or_else(|e| {
+        warn!("could not decode stream, writing raw content: {:?}", e);
+        stream.content.clone()
+    });
+
+    match output {
+        Some(path) => {
+            fs::write(path, &content)?;
+            println!(
+                "{} {} bytes to {}",
+                "「wrote」".green().bold(),
+                content.len().to_string().yellow(),
+                path.display().to_string().cyan()
+            );
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&content)?;
+            stdout.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// search every decoded stream and dictionary string for a regex
+pub fn grep_pdf(
+    input_file: &PathBuf,
+    pattern: &str,
+    password: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pattern = regex::bytes::Regex::new(pattern).map_err(|e| {
+        error!("Invalid pattern: {}", e);
+        e
+    })?;
+
+    let (doc, _) = repair_and_load_pdf(input_file, password)?;
+    check_decryption(&doc, password);
+
+    print_search_matches(&search_document(&doc, &pattern));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_loader::load_pdf_bytes;
+    use lopdf::{EncryptionState, EncryptionVersion, Object, Permissions, dictionary};
+
+    const TITLE: &[u8] = b"only readable once decrypted";
+
+    /// a one page-tree pdf with an /Info title, saved the way lopdf writes it
+    fn pdf_bytes(user_password: Option<&str>) -> Vec<u8> {
+        let mut doc = lopdf::Document::with_version("1.7");
+        let pages_id = doc.add_object(dictionary! {
+            "Type" => "Pages",
+            "Kids" => Vec::<Object>::new(),
+            "Count" => 0,
+        });
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        let info_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal(TITLE),
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc.trailer.set("Info", info_id);
+        let id = Object::string_literal(&b"0123456789abcdef"[..]);
+        doc.trailer.set("ID", vec![id.clone(), id]);
+
+        if let Some(user_password) = user_password {
+            let state = EncryptionState::try_from(EncryptionVersion::V2 {
+                document: &doc,
+                owner_password: "owner",
+                user_password,
+                key_length: 128,
+                permissions: Permissions::all() - Permissions::COPYABLE,
+            })
+            .unwrap();
+            doc.encrypt(&state).unwrap();
+        }
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn title(doc: &lopdf::Document) -> Vec<u8> {
+        let info = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        doc.get_dictionary(info)
+            .unwrap()
+            .get(b"Title")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn password_decrypts_while_loading() {
+        let bytes = pdf_bytes(Some("user"));
+        assert!(!bytes.windows(TITLE.len()).any(|w| w == TITLE));
+
+        let (doc, _) = load_pdf_bytes(bytes, Some("user")).unwrap();
+        assert_eq!(title(&doc), TITLE);
+        assert!(!pdf_crypt::is_encrypted(&doc));
+        assert!(!check_decryption(&doc, Some("user")));
+        assert_eq!(
+            pdf_crypt::denied_permissions(&doc),
+            ["copy text and images"]
+        );
+    }
+
+    #[test]
+    fn empty_password_is_only_decrypted_once() {
+        let (doc, _) = load_pdf_bytes(pdf_bytes(Some("")), None).unwrap();
+        assert!(doc.encryption_state.is_some());
+        assert!(check_decryption(&doc, None));
+        // rc4 is symmetric, a second pass would scramble the title again
+        assert_eq!(title(&doc), TITLE);
+        assert_eq!(
+            pdf_crypt::denied_permissions(&doc),
+            ["copy text and images"]
+        );
+    }
+
+    #[test]
+    fn wrong_password_fails_the_load() {
+        let bytes = pdf_bytes(Some("user"));
+        assert!(matches!(
+            load_pdf_bytes(bytes, Some("wrong")),
+            Err(lopdf::Error::InvalidPassword)
+        ));
+    }
+
+    #[test]
+    fn no_password_leaves_it_encrypted() {
+        let (doc, _) = load_pdf_bytes(pdf_bytes(Some("user")), None).unwrap();
+        assert!(pdf_crypt::is_encrypted(&doc));
+        assert!(doc.encryption_state.is_none());
+        assert!(!check_decryption(&doc, None));
+    }
+
+    /// the pdf with its trailing whitespace swapped for ending
+    fn ending_with(ending: &[u8]) -> Vec<u8> {
+        let mut bytes = pdf_bytes(None);
+        while bytes.last().is_some_and(u8::is_ascii_whitespace) {
+            bytes.pop();
+        }
+        assert!(bytes.ends_with(b"%%EOF"));
+        bytes.extend_from_slice(ending);
+        bytes
+    }
+
+    fn fail_on_warnings(bytes: Vec<u8>) -> Result<(), String> {
+        let (doc, pre_parse) = load_pdf_bytes(bytes, None).unwrap();
+        let features = detect_suspicious_features(&doc);
+        check_fail_on(Some(FailOn::Warnings), &pre_parse, &features)
+    }
+
+    #[test]
+    fn end_of_line_after_eof_passes_fail_on_warnings() {
+        for ending in [&b"\n"[..], b"\r\n", b"\r", b""] {
+            assert_eq!(fail_on_warnings(ending_with(ending)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn data_after_eof_fails_fail_on_warnings() {
+        let error = fail_on_warnings(ending_with(b"\nhidden payload")).unwrap_err();
+        assert!(error.contains("appended data"));
+    }
+
+    #[test]
+    fn unencrypted_pdf_loads_with_a_password_too() {
+        let (doc, _) = load_pdf_bytes(pdf_bytes(None), Some("user")).unwrap();
+        assert_eq!(title(&doc), TITLE);
+        assert!(!check_decryption(&doc, Some("user")));
+    }
 }
@@ -1,22 +1,59 @@
 use colored::Colorize;
-use log::{debug, info};
+use log::debug;
 use lopdf::Object;
+use serde::Serialize;
 use std::collections::HashSet;
 
-#[derive(Debug, Default)]
+use crate::analysis_helpers::filter_chain;
+use crate::dump_helpers::declared_length;
+use crate::embedded_file_helpers::collect_embedded_files;
+use crate::extraction_helpers::{image_magic, image_size_mismatch, is_executable};
+use crate::metadata_helpers::decode_text_string;
+use crate::rich_media_helpers::rich_media_subtype;
+use crate::signature_helpers::{SignatureInfo, collect_signatures};
+use crate::xfa_helpers::acroform;
+
+/// decoded to raw size ratio past which a stream looks like a bomb
+const MAX_DECODE_RATIO: f64 = 1000.0;
+
+/// legitimate streams rarely chain more filters than this
+const MAX_FILTER_CHAIN: usize = 3;
+
+#[derive(Debug, Default, Serialize)]
 pub struct SuspiciousFeatures {
     pub has_javascript: bool,
     pub has_auto_action: bool,
     pub has_open_action: bool,
+    pub has_acroform: bool,
+    pub has_xfa: bool,
     pub unreferenced_objects_count: usize,
     pub large_unreferenced_streams: Vec<(u32, usize)>,
+    pub image_size_mismatches: Vec<(u32, usize, usize)>,
+    pub length_mismatches: Vec<(u32, i64, usize)>,
+    pub decode_bombs: Vec<(u32, usize, Option<f64>)>,
+    /// (object, what the dict declares, what the decoded bytes are)
+    pub magic_mismatches: Vec<(u32, String, String)>,
+    pub rich_media_annotations: Vec<(u32, String)>,
+    pub has_rich_media: bool,
+    pub has_3d: bool,
+    pub has_encryption: bool,
+    pub encryption: Option<EncryptionInfo>,
+    pub embedded_files: Vec<(String, usize)>,
+    pub uri_actions: Vec<String>,
+    pub launch_actions: Vec<String>,
+    pub signatures: Vec<SignatureInfo>,
+    /// everything reachable from the trailer's /Root and /Info
+    #[serde(skip)]
+    pub referenced_ids: HashSet<(u32, u16)>,
 }
 
-/// run post-parsing security checks
-pub fn post_parse_sec_checks(doc: &lopdf::Document) {
-    info!("running post-parse security checks");
-    let results = detect_suspicious_features(doc);
-    print_post_parse_warnings(results);
+/// what the trailer's /Encrypt dictionary says about the encryption
+#[derive(Debug, Serialize)]
+pub struct EncryptionInfo {
+    pub filter: String,
+    pub version: Option<i64>,
+    pub revision: Option<i64>,
+    pub method: String,
 }
 
 /// pdf security checks
@@ -26,6 +63,22 @@ pub fn detect_suspicious_features(doc: &lopdf::Document) -> SuspiciousFeatures {
     debug!("detecting suspicious features in PDF");
     let mut features = SuspiciousFeatures::default();
 
+    if let Some(encryption) = encryption_info(doc) {
+        features.has_encryption = true;
+        features.encryption = Some(encryption);
+    }
+
+    if let Some(form) = acroform(doc) {
+        features.has_acroform = true;
+        features.has_xfa = form.has(b"XFA");
+    }
+
+    features.signatures = collect_signatures(doc);
+    features.embedded_files = collect_embedded_files(doc)
+        .into_iter()
+        .map(|file| (file.name, file.data.len()))
+        .collect();
+
     let mut referenced_ids = HashSet::new();
 
     if let Ok(catalog_id) = doc.trailer.get(b"Root") {
@@ -62,11 +115,63 @@ pub fn detect_suspicious_features(doc: &lopdf::Document) -> SuspiciousFeatures {
             }
         }
 
+        if let Object::Stream(stream) = object {
+            let chain_length = filter_chain(&stream.dict).len();
+            let ratio = stream
+                .decompressed_content()
+                .ok()
+                .filter(|_| !stream.content.is_empty())
+                .map(|content| content.len() as f64 / stream.content.len() as f64);
+            if chain_length > MAX_FILTER_CHAIN || ratio.is_some_and(|r| r > MAX_DECODE_RATIO) {
+                features
+                    .decode_bombs
+                    .push((object_id.0, chain_length, ratio));
+            }
+
+            if let Some((declared, found)) = magic_mismatch(stream) {
+                features
+                    .magic_mismatches
+                    .push((object_id.0, declared, found));
+            }
+
+            if let Some(declared) = declared_length(doc, stream)
+                && declared != stream.content.len() as i64
+            {
+                features
+                    .length_mismatches
+                    .push((object_id.0, declared, stream.content.len()));
+            }
+
+            if let Ok(Object::Name(subtype)) = stream.dict.get(b"Subtype")
+                && subtype == b"Image"
+                && let Some((declared, actual)) = image_size_mismatch(stream)
+            {
+                features
+                    .image_size_mismatches
+                    .push((object_id.0, declared, actual));
+            }
+        }
+
         if let Object::Dictionary(dict) = object {
             if let Ok(Object::Name(name)) = dict.get(b"S") {
-                if name == b"JavaScript" {
-                    features.has_javascript = true;
+                match name.as_slice() {
+                    b"JavaScript" => features.has_javascript = true,
+                    b"URI" => {
+                        if let Ok(Object::String(uri, _)) = dict.get(b"URI") {
+                            features.uri_actions.push(decode_text_string(uri));
+                        }
+                    }
+                    b"Launch" => features.launch_actions.push(launch_target(doc, dict)),
+                    _ => {}
+                }
+            }
+
+            if let Some(subtype) = rich_media_subtype(dict) {
+                match subtype.as_str() {
+                    "RichMedia" => features.has_rich_media = true,
+                    _ => features.has_3d = true,
                 }
+                features.rich_media_annotations.push((object_id.0, subtype));
             }
 
             if dict.has(b"AA") {
@@ -79,47 +184,198 @@ pub fn detect_suspicious_features(doc: &lopdf::Document) -> SuspiciousFeatures {
         }
     }
 
+    features.referenced_ids = referenced_ids;
     features
 }
 
-fn collect_references(
-    object: &Object,
-    referenced: &mut HashSet<(u32, u16)>,
-    doc: &lopdf::Document,
-) {
-    match object {
-        Object::Reference(id) => {
-            if referenced.insert(*id) {
-                if let Ok(obj) = doc.get_object(*id) {
-                    collect_references(obj, referenced, doc);
-                }
-            }
+/// compare a stream's decoded magic bytes with its filters and subtype
+///
+/// executables and archives never fit, and the only image format a
+/// pdf stream carries whole is a jpeg behind DCTDecode. embedded
+/// files are skipped, they get their own warning
+fn magic_mismatch(stream: &lopdf::Stream) -> Option<(String, String)> {
+    if matches!(stream.dict.get(b"Type"), Ok(Object::Name(t)) if t == b"EmbeddedFile") {
+        return None;
+    }
+    let filters = filter_chain(&stream.dict);
+    let content = if filters.is_empty() {
+        stream.content.clone()
+    } else {
+        stream.decompressed_content().ok()?
+    };
+
+    let found = match (is_executable(&content), image_magic(&content)) {
+        (Some(kind), _) => kind.to_string(),
+        (None, Some("jpg")) if filters.iter().any(|f| f == "DCTDecode") => return None,
+        (None, Some(format)) => format!("a {} image", format),
+        (None, None) => return None,
+    };
+
+    let declared = format!(
+        "{} {}",
+        if filters.is_empty() {
+            "unfiltered".to_string()
+        } else {
+            filters.join("/")
+        },
+        match stream.dict.get(b"Subtype") {
+            Ok(Object::Name(subtype)) => String::from_utf8_lossy(subtype).to_string(),
+            _ => "stream".to_string(),
         }
-        Object::Array(arr) => {
-            for item in arr {
-                collect_references(item, referenced, doc);
+    );
+    debug!("stream claims {} but contains {}", declared, found);
+    Some((declared, found))
+}
+
+/// what a /Launch action would run
+///
+/// /F can be a plain string or a file spec, and the windows
+/// specific /Win dict carries the program and its parameters
+fn launch_target(doc: &lopdf::Document, action: &lopdf::Dictionary) -> String {
+    let file_string = |object: &Object| -> Option<String> {
+        let object = match object {
+            Object::Reference(id) => doc.get_object(*id).ok()?,
+            other => other,
+        };
+        match object {
+            Object::String(bytes, _) => Some(decode_text_string(bytes)),
+            Object::Dictionary(spec) => [&b"UF"[..], &b"F"[..], &b"Unix"[..], &b"DOS"[..]]
+                .iter()
+                .find_map(|key| match spec.get(key) {
+                    Ok(Object::String(bytes, _)) => Some(decode_text_string(bytes)),
+                    _ => None,
+                }),
+            _ => None,
+        }
+    };
+
+    if let Ok(Object::Dictionary(win)) = action.get(b"Win")
+        && let Some(program) = win.get(b"F").ok().and_then(file_string)
+    {
+        return match win.get(b"P") {
+            Ok(Object::String(params, _)) => {
+                format!("{} {}", program, decode_text_string(params))
             }
+            _ => program,
+        };
+    }
+
+    action
+        .get(b"F")
+        .ok()
+        .and_then(file_string)
+        .unwrap_or_else(|| "(unknown target)".to_string())
+}
+
+/// read /Encrypt from the trailer, if there is one
+///
+/// once lopdf has decrypted a pdf the trailer entry is gone, the
+/// dictionary is rebuilt from the state lopdf kept instead
+fn encryption_info(doc: &lopdf::Document) -> Option<EncryptionInfo> {
+    let decrypted;
+    let encrypt = match doc.trailer.get(b"Encrypt") {
+        Ok(Object::Reference(id)) => doc.get_dictionary(*id).ok()?,
+        Ok(Object::Dictionary(dict)) => dict,
+        Ok(_) => return None,
+        Err(_) => {
+            decrypted = doc.encryption_state.as_ref()?.encode().ok()?;
+            &decrypted
         }
-        Object::Dictionary(dict) => {
-            for (_, value) in dict.iter() {
-                collect_references(value, referenced, doc);
+    };
+
+    let name = |dict: &lopdf::Dictionary, key: &[u8]| {
+        dict.get(key)
+            .and_then(|v| v.as_name())
+            .map(|n| String::from_utf8_lossy(n).to_string())
+            .ok()
+    };
+
+    let version = encrypt.get(b"V").and_then(|v| v.as_i64()).ok();
+    let revision = encrypt.get(b"R").and_then(|r| r.as_i64()).ok();
+
+    // V4+ picks a crypt filter from /CF by name, older versions are always RC4
+    let method = match version {
+        Some(4..) => {
+            let stream_filter = name(encrypt, b"StmF").unwrap_or_else(|| "Identity".to_string());
+            let crypt_method = encrypt
+                .get(b"CF")
+                .and_then(|cf| cf.as_dict())
+                .and_then(|cf| cf.get(stream_filter.as_bytes()))
+                .and_then(|f| f.as_dict())
+                .ok()
+                .and_then(|f| name(f, b"CFM"));
+            match crypt_method.as_deref() {
+                Some("V2") => "RC4".to_string(),
+                Some("AESV2") => "AES-128".to_string(),
+                Some("AESV3") => "AES-256".to_string(),
+                _ if stream_filter == "Identity" => "none".to_string(),
+                Some("None") => "none".to_string(),
+                Some(other) => other.to_string(),
+                None => "unknown".to_string(),
             }
         }
-        Object::Stream(stream) => {
-            for (_, value) in stream.dict.iter() {
-                collect_references(value, referenced, doc);
+        _ => "RC4".to_string(),
+    };
+
+    debug!(
+        "pdf is encrypted: V={:?} R={:?} method {}",
+        version, revision, method
+    );
+
+    Some(EncryptionInfo {
+        filter: name(encrypt, b"Filter").unwrap_or_else(|| "unknown".to_string()),
+        version,
+        revision,
+        method,
+    })
+}
+
+/// mark everything reachable from an object as referenced
+///
+/// uses an explicit stack instead of recursion so deeply nested
+/// inline arrays and dicts can't overflow the real one
+fn collect_references<'a>(
+    object: &'a Object,
+    referenced: &mut HashSet<(u32, u16)>,
+    doc: &'a lopdf::Document,
+) {
+    let mut stack = vec![object];
+
+    while let Some(object) = stack.pop() {
+        match object {
+            Object::Reference(id) => {
+                if referenced.insert(*id)
+                    && let Ok(obj) = doc.get_object(*id)
+                {
+                    stack.push(obj);
+                }
             }
+            Object::Array(arr) => stack.extend(arr.iter()),
+            Object::Dictionary(dict) => stack.extend(dict.iter().map(|(_, value)| value)),
+            Object::Stream(stream) => stack.extend(stream.dict.iter().map(|(_, value)| value)),
+            _ => {}
         }
-        _ => {}
     }
 }
 
 /// prints parsing results
 ///
 /// again, doesn't need to be here but i don't like to see it so
-fn print_post_parse_warnings(results: SuspiciousFeatures) {
+pub fn print_post_parse_warnings(results: &SuspiciousFeatures) {
     let mut warnings = Vec::new();
 
+    if let Some(encryption) = &results.encryption {
+        let number = |n: Option<i64>| n.map_or("?".to_string(), |n| n.to_string());
+        warnings.push(format!(
+            "{} {} V{} R{} using {}, stats may be unreliable",
+            "「encrypted」\t".red().bold(),
+            encryption.filter.yellow(),
+            number(encryption.version).yellow(),
+            number(encryption.revision).yellow(),
+            encryption.method.yellow()
+        ));
+    }
+
     if results.has_javascript {
         warnings.push(format!(
             "{}",
@@ -145,6 +401,20 @@ fn print_post_parse_warnings(results: SuspiciousFeatures) {
         ));
     }
 
+    if results.has_xfa {
+        warnings.push(format!(
+            "{}",
+            "「xfa form」\t pdf carries an XFA form, which can hold scripts and large xml"
+                .red()
+                .bold()
+        ));
+    } else if results.has_acroform {
+        warnings.push(format!(
+            "{} pdf has an interactive form",
+            "「acroform」\t".yellow().bold()
+        ));
+    }
+
     if results.unreferenced_objects_count > 0 {
         warnings.push(format!(
             "{} {} unreferenced objects found",
@@ -172,6 +442,175 @@ fn print_post_parse_warnings(results: SuspiciousFeatures) {
         }
     }
 
+    if !results.uri_actions.is_empty() || !results.launch_actions.is_empty() {
+        warnings.push(format!(
+            "{} {} links and {} program launches",
+            "「external actions」\t".red().bold(),
+            results.uri_actions.len().to_string().yellow(),
+            results.launch_actions.len().to_string().yellow()
+        ));
+        for uri in &results.uri_actions {
+            warnings.push(format!("  {} {}", "URI:".cyan(), uri.yellow()));
+        }
+        for target in &results.launch_actions {
+            warnings.push(format!("  {} {}", "Launch:".red(), target.yellow()));
+        }
+    }
+
+    if !results.embedded_files.is_empty() {
+        warnings.push(format!(
+            "{} {} file attachments embedded in the pdf",
+            "「embedded files」\t".red().bold(),
+            results.embedded_files.len().to_string().yellow()
+        ));
+        for (name, size) in &results.embedded_files {
+            warnings.push(format!(
+                "  {}: {} bytes",
+                name.cyan(),
+                size.to_string().yellow()
+            ));
+        }
+    }
+
+    if results.has_rich_media {
+        warnings.push(format!(
+            "{}",
+            "「richmedia」\t pdf embeds RichMedia content, historically used to ship Flash exploits"
+                .red()
+                .bold()
+        ));
+    }
+
+    if results.has_3d {
+        warnings.push(format!(
+            "{}",
+            "「3d」\t pdf embeds U3D/PRC 3D models, a long running exploit target"
+                .red()
+                .bold()
+        ));
+    }
+
+    if !results.rich_media_annotations.is_empty() {
+        warnings.push(format!(
+            "{} {} 3D/RichMedia annotations embed external content",
+            "「rich media」\t".red().bold(),
+            results.rich_media_annotations.len().to_string().yellow()
+        ));
+        for (obj_id, subtype) in &results.rich_media_annotations {
+            warnings.push(format!(
+                "  Object {}: /{}",
+                obj_id.to_string().cyan(),
+                subtype.yellow()
+            ));
+        }
+    }
+
+    if !results.image_size_mismatches.is_empty() {
+        warnings.push(format!(
+            "{} {} images don't match their declared dimensions",
+            "「image size mismatch」\t".yellow().bold(),
+            results.image_size_mismatches.len().to_string().yellow()
+        ));
+        for (obj_id, declared, actual) in &results.image_size_mismatches {
+            warnings.push(format!(
+                "  Object {}: declared {} bytes, actual {} bytes",
+                obj_id.to_string().cyan(),
+                declared.to_string().yellow(),
+                actual.to_string().yellow()
+            ));
+        }
+    }
+
+    if !results.signatures.is_empty() {
+        warnings.push(format!(
+            "{} {} digital signatures (not cryptographically verified)",
+            "「signatures」\t".cyan().bold(),
+            results.signatures.len().to_string().yellow()
+        ));
+        for signature in &results.signatures {
+            let unknown = || "?".to_string();
+            warnings.push(format!(
+                "  Object {}: {} {} at {} ({})",
+                signature.object_id.to_string().cyan(),
+                signature.name.clone().unwrap_or_else(unknown).yellow(),
+                signature.sub_filter.clone().unwrap_or_else(unknown),
+                signature.signing_time.clone().unwrap_or_else(unknown),
+                signature.reason.clone().unwrap_or_else(unknown)
+            ));
+            if signature.invalid_range {
+                warnings.push(format!(
+                    "    {} /ByteRange {:?} doesn't describe this file",
+                    "「bad byte range」".red().bold(),
+                    signature.byte_range
+                ));
+            }
+            if signature.unexpected_gaps > 0 {
+                warnings.push(format!(
+                    "    {} {} unsigned holes besides /Contents",
+                    "「signature gap」".red().bold(),
+                    signature.unexpected_gaps.to_string().yellow()
+                ));
+            }
+            if signature.unsigned_trailing_bytes > 0 {
+                warnings.push(format!(
+                    "    {} {} bytes of incremental content added after signature",
+                    "「unsigned content」".red().bold(),
+                    signature.unsigned_trailing_bytes.to_string().yellow()
+                ));
+            }
+        }
+    }
+
+    if !results.decode_bombs.is_empty() {
+        warnings.push(format!(
+            "{} {} streams have deep filter chains or huge decompression ratios",
+            "「decode bomb」\t".red().bold(),
+            results.decode_bombs.len().to_string().yellow()
+        ));
+        for (obj_id, chain_length, ratio) in &results.decode_bombs {
+            warnings.push(format!(
+                "  Object {}: {} filters, ratio {}",
+                obj_id.to_string().cyan(),
+                chain_length.to_string().yellow(),
+                ratio
+                    .map_or("?".to_string(), |r| format!("{:.0}x", r))
+                    .yellow()
+            ));
+        }
+    }
+
+    if !results.magic_mismatches.is_empty() {
+        warnings.push(format!(
+            "{} {} streams contain something other than what they declare",
+            "「magic mismatch」\t".red().bold(),
+            results.magic_mismatches.len().to_string().yellow()
+        ));
+        for (obj_id, declared, found) in &results.magic_mismatches {
+            warnings.push(format!(
+                "  Object {} claims {} but contains {}",
+                obj_id.to_string().cyan(),
+                declared.yellow(),
+                found.red()
+            ));
+        }
+    }
+
+    if !results.length_mismatches.is_empty() {
+        warnings.push(format!(
+            "{} {} streams have a /Length that doesn't match their data",
+            "「length mismatch」\t".yellow().bold(),
+            results.length_mismatches.len().to_string().yellow()
+        ));
+        for (obj_id, declared, actual) in &results.length_mismatches {
+            warnings.push(format!(
+                "  Object {}: /Length {}, actual {} bytes",
+                obj_id.to_string().cyan(),
+                declared.to_string().yellow(),
+                actual.to_string().yellow()
+            ));
+        }
+    }
+
     if !warnings.is_empty() {
         println!("{}", "「post-parse warnings」".red().bold());
         for warning in warnings {
@@ -186,3 +625,59 @@ fn print_post_parse_warnings(results: SuspiciousFeatures) {
         println!();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::Dictionary;
+
+    const DEPTH: usize = 100_000;
+
+    /// alternating arrays and dicts, `DEPTH` levels down to a reference
+    fn deeply_nested(bottom: Object) -> Object {
+        let mut object = bottom;
+        for level in 0..DEPTH {
+            object = if level % 2 == 0 {
+                Object::Array(vec![object])
+            } else {
+                let mut dict = Dictionary::new();
+                dict.set("Kid", object);
+                Object::Dictionary(dict)
+            };
+        }
+        object
+    }
+
+    /// dropping the nesting normally recurses, so take it apart by hand
+    fn unnest(mut object: Object) {
+        loop {
+            object = match object {
+                Object::Array(mut arr) => match arr.pop() {
+                    Some(inner) => inner,
+                    None => break,
+                },
+                Object::Dictionary(mut dict) => match dict.remove(b"Kid") {
+                    Some(inner) => inner,
+                    None => break,
+                },
+                _ => break,
+            };
+        }
+    }
+
+    #[test]
+    fn collect_references_survives_deep_nesting() {
+        let mut doc = lopdf::Document::new();
+        doc.objects
+            .insert((1, 0), deeply_nested(Object::Reference((2, 0))));
+        doc.objects.insert((2, 0), Object::Integer(42));
+
+        let mut referenced = HashSet::new();
+        collect_references(&Object::Reference((1, 0)), &mut referenced, &doc);
+
+        assert!(referenced.contains(&(1, 0)));
+        assert!(referenced.contains(&(2, 0)));
+
+        unnest(doc.objects.remove(&(1, 0)).unwrap());
+    }
+}
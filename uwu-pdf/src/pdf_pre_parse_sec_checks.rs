@@ -1,39 +1,110 @@
 use colored::Colorize;
 use log::debug;
+use serde::Serialize;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Default)]
+use crate::xref_helpers::scan_object_headers;
+
+/// more #xx escapes than this in names is worth a warning by itself
+const NAME_ESCAPE_THRESHOLD: usize = 32;
+
+/// obfuscated names shown in the warning
+const NAME_EXAMPLES: usize = 5;
+
+/// redefined objects listed before the rest are summarised
+const DUPLICATE_EXAMPLES: usize = 10;
+
+#[derive(Debug, Default, Serialize)]
 pub struct PreParseResults {
+    pub file_size: usize,
     pub prepended_bytes: Option<usize>,
+    #[serde(skip)]
     pub prepended_data: Option<Vec<u8>>,
     pub appended_bytes: Option<usize>,
+    #[serde(skip)]
     pub appended_data: Option<Vec<u8>>,
+    pub xref_rebuilt: bool,
+    pub eof_markers: usize,
+    pub truncated: bool,
+    pub name_escapes: usize,
+    /// names that hex escape plain letters or digits, as "raw -> decoded"
+    pub obfuscated_names: Vec<String>,
+    /// object numbers with more than one `N G obj` in the raw bytes,
+    /// with the generation of each definition in file order
+    pub duplicate_objects: Vec<(u32, Vec<u16>)>,
 }
 
 /// wrapper to run pre-parse sec checks
+///
+/// nothing is printed here, use print_pre_parse_warnings for that
 pub fn pre_parse_sec_checks(bytes: &[u8]) -> PreParseResults {
     debug!("running pre-parse security checks on {} bytes", bytes.len());
 
     let prepend_result = check_prepended_data_bytes(bytes);
     let append_result = check_appended_data_bytes(bytes);
 
-    let results = PreParseResults {
+    let eof_markers = count_eof_markers(bytes);
+    let has_header = bytes.windows(5).any(|window| window == b"%PDF-");
+    let (name_escapes, obfuscated_names) = scan_name_escapes(bytes);
+    let duplicate_objects = find_duplicate_objects(bytes);
+
+    PreParseResults {
+        file_size: bytes.len(),
         prepended_bytes: prepend_result.as_ref().map(|(size, _)| *size),
         prepended_data: prepend_result.map(|(_, data)| data),
         appended_bytes: append_result.as_ref().map(|(size, _)| *size),
         appended_data: append_result.map(|(_, data)| data),
-    };
+        xref_rebuilt: false,
+        eof_markers,
+        truncated: has_header && eof_markers == 0,
+        name_escapes,
+        obfuscated_names,
+        duplicate_objects,
+    }
+}
 
-    print_pre_parse_warnings(&results);
+/// object numbers defined more than once
+///
+/// lopdf keeps only the definition the xref points at, so a viewer
+/// and a scanner can each end up looking at a different one.
+/// incremental updates redefine objects legitimately too, but those
+/// keep the same generation
+fn find_duplicate_objects(bytes: &[u8]) -> Vec<(u32, Vec<u16>)> {
+    let mut definitions: BTreeMap<u32, Vec<u16>> = BTreeMap::new();
+    for (_, number, generation) in scan_object_headers(bytes) {
+        definitions.entry(number).or_default().push(generation);
+    }
 
-    results
+    let duplicates: Vec<(u32, Vec<u16>)> = definitions
+        .into_iter()
+        .filter(|(_, generations)| generations.len() > 1)
+        .collect();
+    debug!("{} objects are defined more than once", duplicates.len());
+    duplicates
 }
 
-/// check for data appended after EOF header
+/// every incremental update adds its own %%EOF
+fn count_eof_markers(bytes: &[u8]) -> usize {
+    bytes
+        .windows(5)
+        .filter(|window| *window == b"%%EOF")
+        .count()
+}
+
+/// check for data appended after the final EOF marker
+///
+/// earlier markers are just incremental updates, only what comes
+/// after the last one is outside the document. the end of line most
+/// writers put after %%EOF (and any other trailing whitespace) is
+/// not counted
 fn check_appended_data_bytes(bytes: &[u8]) -> Option<(usize, Vec<u8>)> {
-    if let Some(eof_pos) = bytes.windows(5).position(|window| window == b"%%EOF") {
+    if let Some(eof_pos) = bytes.windows(5).rposition(|window| window == b"%%EOF") {
         let total_len = bytes.len();
         let truncated_len = eof_pos + 5;
-        if truncated_len < total_len {
+        let only_whitespace = bytes[truncated_len..]
+            .iter()
+            .all(|b| b.is_ascii_whitespace());
+        if !only_whitespace {
             let extra_bytes = total_len - truncated_len;
             let appended_data = bytes[truncated_len..].to_vec();
             return Some((extra_bytes, appended_data));
@@ -42,6 +113,101 @@ fn check_appended_data_bytes(bytes: &[u8]) -> Option<(usize, Vec<u8>)> {
     None
 }
 
+/// count #xx escapes in name tokens, skipping strings and stream data
+///
+/// escaping a space or non-ascii byte is legitimate, escaping a plain
+/// letter like /J#61vaScript only exists to dodge keyword scanners
+fn scan_name_escapes(bytes: &[u8]) -> (usize, Vec<String>) {
+    let is_delimiter = |b: u8| b.is_ascii_whitespace() || b"()<>[]{}/%".contains(&b);
+    let hex_value = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+
+    let mut escapes = 0;
+    let mut obfuscated = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'(' => {
+                // literal strings nest and can escape parens
+                let mut depth = 0;
+                while pos < bytes.len() {
+                    match bytes[pos] {
+                        b'\\' => pos += 1,
+                        b'(' => depth += 1,
+                        b')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    pos += 1;
+                }
+            }
+            b's' if bytes[pos..].starts_with(b"stream")
+                && !bytes[..pos].ends_with(b"end")
+                && matches!(bytes.get(pos + 6), Some(b'\r') | Some(b'\n')) =>
+            {
+                match bytes[pos..].windows(9).position(|w| w == b"endstream") {
+                    Some(end) => pos += end + 8,
+                    None => break,
+                }
+            }
+            b'/' => {
+                let start = pos;
+                pos += 1;
+                while pos < bytes.len() && !is_delimiter(bytes[pos]) {
+                    pos += 1;
+                }
+
+                let token = &bytes[start..pos];
+                let mut decoded = Vec::with_capacity(token.len());
+                let mut needless_escape = false;
+                let mut i = 0;
+                while i < token.len() {
+                    let escaped = match (token[i], token.get(i + 1), token.get(i + 2)) {
+                        (b'#', Some(&high), Some(&low)) => hex_value(high)
+                            .zip(hex_value(low))
+                            .map(|(h, l)| (h << 4) | l),
+                        _ => None,
+                    };
+                    match escaped {
+                        Some(byte) => {
+                            escapes += 1;
+                            needless_escape |= byte.is_ascii_alphanumeric();
+                            decoded.push(byte);
+                            i += 3;
+                        }
+                        None => {
+                            decoded.push(token[i]);
+                            i += 1;
+                        }
+                    }
+                }
+
+                if needless_escape {
+                    obfuscated.push(format!(
+                        "{} -> {}",
+                        String::from_utf8_lossy(token),
+                        String::from_utf8_lossy(&decoded)
+                    ));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+
+    debug!(
+        "found {} name escapes, {} obfuscated names",
+        escapes,
+        obfuscated.len()
+    );
+    (escapes, obfuscated)
+}
+
 /// check for data prepended before PDF header
 fn check_prepended_data_bytes(bytes: &[u8]) -> Option<(usize, Vec<u8>)> {
     if let Some(pdf_pos) = bytes.windows(5).position(|window| window == b"%PDF-") {
@@ -54,7 +220,7 @@ fn check_prepended_data_bytes(bytes: &[u8]) -> Option<(usize, Vec<u8>)> {
 }
 
 /// print pre-parse warnings, if any
-fn print_pre_parse_warnings(results: &PreParseResults) {
+pub fn print_pre_parse_warnings(results: &PreParseResults) {
     let mut warnings = Vec::new();
 
     if let Some(prepend_bytes) = results.prepended_bytes {
@@ -73,6 +239,64 @@ fn print_pre_parse_warnings(results: &PreParseResults) {
         ));
     }
 
+    if results.truncated {
+        warnings.push(format!(
+            "{} pdf header found but no %%EOF, the file looks cut off",
+            "「truncated」\t".yellow().bold()
+        ));
+    }
+
+    if !results.obfuscated_names.is_empty() || results.name_escapes > NAME_ESCAPE_THRESHOLD {
+        warnings.push(format!(
+            "{} {} #xx escapes in names, {} names escape plain letters",
+            "「name obfuscation」\t".red().bold(),
+            results.name_escapes.to_string().yellow(),
+            results.obfuscated_names.len().to_string().yellow()
+        ));
+        for example in results.obfuscated_names.iter().take(NAME_EXAMPLES) {
+            warnings.push(format!("  {}", example.yellow()));
+        }
+    }
+
+    if !results.duplicate_objects.is_empty() {
+        warnings.push(format!(
+            "{} {} object numbers are defined more than once",
+            "「duplicate object definitions」\t".red().bold(),
+            results.duplicate_objects.len().to_string().yellow()
+        ));
+        for (number, generations) in results.duplicate_objects.iter().take(DUPLICATE_EXAMPLES) {
+            let mismatched = generations.iter().any(|g| *g != generations[0]);
+            warnings.push(format!(
+                "  object {}: {} definitions, generations {}{}",
+                number.to_string().yellow(),
+                generations.len(),
+                generations
+                    .iter()
+                    .map(|g| g.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                if mismatched {
+                    " (mismatched)".red().to_string()
+                } else {
+                    String::new()
+                }
+            ));
+        }
+        if results.duplicate_objects.len() > DUPLICATE_EXAMPLES {
+            warnings.push(format!(
+                "  and {} more",
+                results.duplicate_objects.len() - DUPLICATE_EXAMPLES
+            ));
+        }
+    }
+
+    if results.xref_rebuilt {
+        warnings.push(format!(
+            "{} xref was broken and had to be rebuilt by scanning for objects",
+            "「xref rebuilt」\t".yellow().bold()
+        ));
+    }
+
     if !warnings.is_empty() {
         println!("{}", "「pre-parse warnings」".yellow().bold());
         for warning in warnings {
@@ -84,5 +308,13 @@ fn print_pre_parse_warnings(results: &PreParseResults) {
             "「pre-parse warnings」 no prepending or appending detected".green()
         )
     }
+
+    if results.eof_markers > 1 {
+        println!(
+            "{} {} incremental update(s) after the original document",
+            "「incremental updates」".cyan().bold(),
+            (results.eof_markers - 1).to_string().yellow()
+        );
+    }
     println!();
 }
@@ -0,0 +1,185 @@
+use colored::Colorize;
+use log::{debug, info, warn};
+use lopdf::Object;
+use std::fs;
+use std::path::Path;
+
+use crate::extraction_helpers::ByteBudget;
+
+/// annotation subtypes that embed 3D models or (historically) flash
+pub const RICH_MEDIA_SUBTYPES: &[&[u8]] = &[b"3D", b"RichMedia"];
+
+/// subtype name of an annotation if it's one of the rich media kinds
+pub fn rich_media_subtype(dict: &lopdf::Dictionary) -> Option<String> {
+    let subtype = dict.get(b"Subtype").ok()?.as_name().ok()?;
+    if RICH_MEDIA_SUBTYPES.contains(&subtype) && dict.has(b"Rect") {
+        Some(String::from_utf8_lossy(subtype).to_string())
+    } else {
+        None
+    }
+}
+
+/// write 3D streams and RichMedia assets to output_dir/rich_media
+///
+/// 3D streams carry their format in /Subtype (U3D or PRC), RichMedia
+/// assets live in a name tree of file specs under /RichMediaContent
+pub fn extract_rich_media(
+    doc: &lopdf::Document,
+    output_dir: &Path,
+    budget: &mut ByteBudget,
+) -> Result<usize, String> {
+    let rich_media_dir = output_dir.join("rich_media");
+    let mut count = 0;
+
+    for (object_id, object) in doc.objects.iter() {
+        match object {
+            Object::Stream(stream) => {
+                let dict = &stream.dict;
+                let is_3d_type = matches!(dict.get(b"Type"), Ok(Object::Name(t)) if t == b"3D");
+                let extension = match dict.get(b"Subtype").and_then(|s| s.as_name()) {
+                    Ok(b"U3D") => "u3d",
+                    Ok(b"PRC") => "prc",
+                    _ if is_3d_type => "3d",
+                    _ => continue,
+                };
+
+                let content = stream
+                    .decompressed_content()
+                    .unwrap_or_else(|_| stream.content.clone());
+                let filename = format!("3d_{}_{}.{}", object_id.0, object_id.1, extension);
+                if write_rich_media(&rich_media_dir, &filename, &content, budget)? {
+                    count += 1;
+                }
+            }
+            Object::Dictionary(dict) => {
+                if !matches!(dict.get(b"Subtype"), Ok(Object::Name(s)) if s == b"RichMedia") {
+                    continue;
+                }
+
+                for (asset_name, asset_data) in rich_media_assets(doc, dict) {
+                    let filename = format!(
+                        "richmedia_{}_{}_{}",
+                        object_id.0,
+                        object_id.1,
+                        asset_filename(&asset_name, &asset_data)
+                    );
+                    if write_rich_media(&rich_media_dir, &filename, &asset_data, budget)? {
+                        count += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    info!("extracted {} rich media streams", count);
+    Ok(count)
+}
+
+/// pull (name, data) pairs out of a RichMedia annotation's asset tree
+fn rich_media_assets(doc: &lopdf::Document, annot: &lopdf::Dictionary) -> Vec<(String, Vec<u8>)> {
+    let mut assets = Vec::new();
+
+    let Some(content) = resolve_dict(doc, annot.get(b"RichMediaContent").ok()) else {
+        return assets;
+    };
+    let Some(asset_tree) = resolve_dict(doc, content.get(b"Assets").ok()) else {
+        return assets;
+    };
+    let Ok(Object::Array(names)) = asset_tree.get(b"Names") else {
+        debug!("RichMedia asset tree has no flat /Names array");
+        return assets;
+    };
+
+    for pair in names.chunks_exact(2) {
+        let name = match &pair[0] {
+            Object::String(bytes, _) => String::from_utf8_lossy(bytes).to_string(),
+            _ => continue,
+        };
+        let Some(filespec) = resolve_dict(doc, Some(&pair[1])) else {
+            continue;
+        };
+        let Some(embedded) = resolve_dict(doc, filespec.get(b"EF").ok()) else {
+            continue;
+        };
+        let Ok(stream_ref) = embedded.get(b"F").and_then(|f| f.as_reference()) else {
+            continue;
+        };
+        if let Ok(Object::Stream(stream)) = doc.get_object(stream_ref) {
+            let data = stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone());
+            assets.push((name, data));
+        }
+    }
+
+    assets
+}
+
+fn resolve_dict<'a>(
+    doc: &'a lopdf::Document,
+    object: Option<&'a Object>,
+) -> Option<&'a lopdf::Dictionary> {
+    match object? {
+        Object::Dictionary(dict) => Some(dict),
+        Object::Reference(id) => doc.get_dictionary(*id).ok(),
+        _ => None,
+    }
+}
+
+/// keep the asset's own name if it looks sane, otherwise sniff an extension
+fn asset_filename(asset_name: &str, data: &[u8]) -> String {
+    let sanitized: String = asset_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if sanitized.contains('.') {
+        return sanitized;
+    }
+
+    let extension = if data.len() >= 3 && matches!(&data[0..3], b"FWS" | b"CWS" | b"ZWS") {
+        "swf"
+    } else if data.len() >= 3 && &data[0..3] == b"PRC" {
+        "prc"
+    } else if data.len() >= 4 && data[0..4] == [0x55, 0x33, 0x44, 0x00] {
+        "u3d"
+    } else {
+        "bin"
+    };
+
+    format!("{}.{}", sanitized, extension)
+}
+
+fn write_rich_media(
+    rich_media_dir: &Path,
+    filename: &str,
+    data: &[u8],
+    budget: &mut ByteBudget,
+) -> Result<bool, String> {
+    if let Err(e) = fs::create_dir_all(rich_media_dir) {
+        warn!("failed to create rich media directory: {}", e);
+        return Ok(false);
+    }
+
+    budget.reserve(filename, data.len())?;
+    let output_path = rich_media_dir.join(filename);
+    if let Err(e) = fs::write(&output_path, data) {
+        warn!("failed to write rich media {}: {}", filename, e);
+        Ok(false)
+    } else {
+        println!(
+            "  {} {} ({} bytes)",
+            "「rich media」".green().bold(),
+            filename.cyan(),
+            data.len().to_string().yellow()
+        );
+        Ok(true)
+    }
+}
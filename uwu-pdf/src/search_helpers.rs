@@ -0,0 +1,156 @@
+use colored::Colorize;
+use log::{debug, trace};
+use lopdf::Object;
+use regex::bytes::Regex;
+
+/// matches reported per object before the rest are skipped
+const MAX_MATCHES_PER_OBJECT: usize = 20;
+
+/// bytes of context kept on each side of a match
+const CONTEXT_BYTES: usize = 60;
+
+/// every hit inside one object
+pub struct ObjectMatches {
+    pub object_id: (u32, u16),
+    /// (where in the object, surrounding text)
+    pub matches: Vec<(String, String)>,
+    pub truncated: bool,
+}
+
+/// run a pattern over every decoded stream and dictionary string
+///
+/// streams that can't be decoded are searched raw
+pub fn search_document(doc: &lopdf::Document, pattern: &Regex) -> Vec<ObjectMatches> {
+    let mut results = Vec::new();
+
+    for (object_id, object) in doc.objects.iter() {
+        let mut found = ObjectMatches {
+            object_id: *object_id,
+            matches: Vec::new(),
+            truncated: false,
+        };
+
+        if let Object::Stream(stream) = object {
+            let content = stream.decompressed_content().unwrap_or_else(|e| {
+                debug!("searching raw content of {:?}: {:?}", object_id, e);
+                stream.content.clone()
+            });
+            search_bytes(&content, "stream", pattern, &mut found);
+        }
+
+        search_strings(object, "", pattern, &mut found);
+
+        if !found.matches.is_empty() {
+            trace!("{} matches in {:?}", found.matches.len(), object_id);
+            results.push(found);
+        }
+    }
+
+    results
+}
+
+fn search_bytes(data: &[u8], location: &str, pattern: &Regex, found: &mut ObjectMatches) {
+    for hit in pattern.find_iter(data) {
+        if found.matches.len() >= MAX_MATCHES_PER_OBJECT {
+            found.truncated = true;
+            return;
+        }
+        found
+            .matches
+            .push((location.to_string(), context(data, hit.start(), hit.end())));
+    }
+}
+
+/// search string values in dictionaries and arrays, labelled by key path
+fn search_strings(object: &Object, path: &str, pattern: &Regex, found: &mut ObjectMatches) {
+    match object {
+        Object::String(bytes, _) => {
+            let location = if path.is_empty() { "string" } else { path };
+            search_bytes(bytes, location, pattern, found);
+        }
+        Object::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                search_strings(item, &format!("{}[{}]", path, index), pattern, found);
+            }
+        }
+        Object::Dictionary(dict) => {
+            for (key, value) in dict.iter() {
+                let path = format!("{}/{}", path, String::from_utf8_lossy(key));
+                search_strings(value, &path, pattern, found);
+            }
+        }
+        Object::Stream(stream) => {
+            for (key, value) in stream.dict.iter() {
+                let path = format!("{}/{}", path, String::from_utf8_lossy(key));
+                search_strings(value, &path, pattern, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// the line around a match, clipped and with control bytes blanked out
+fn context(data: &[u8], start: usize, end: usize) -> String {
+    let is_line_break = |b: &u8| *b == b'\n' || *b == b'\r';
+
+    let line_start = data[..start]
+        .iter()
+        .rposition(is_line_break)
+        .map_or(0, |pos| pos + 1)
+        .max(start.saturating_sub(CONTEXT_BYTES));
+    let line_end = data[end..]
+        .iter()
+        .position(is_line_break)
+        .map_or(data.len(), |pos| end + pos)
+        .min(end + CONTEXT_BYTES);
+
+    let printable = |bytes: &[u8]| -> String {
+        String::from_utf8_lossy(bytes)
+            .chars()
+            .map(|c| if c.is_control() { '.' } else { c })
+            .collect()
+    };
+
+    format!(
+        "{}{}{}",
+        printable(&data[line_start..start]),
+        printable(&data[start..end]).red().bold(),
+        printable(&data[end..line_end])
+    )
+}
+
+pub fn print_search_matches(results: &[ObjectMatches]) {
+    if results.is_empty() {
+        println!("{}", "「grep」 no matches".yellow());
+        return;
+    }
+
+    let total: usize = results.iter().map(|r| r.matches.len()).sum();
+    println!(
+        "{} {} matches in {} objects",
+        "「grep」".cyan().bold(),
+        total.to_string().yellow(),
+        results.len().to_string().yellow()
+    );
+
+    for result in results {
+        println!(
+            "  {}",
+            format!("Object {} {}", result.object_id.0, result.object_id.1).cyan()
+        );
+        for (location, context) in &result.matches {
+            println!("    {}: {}", location.green(), context);
+        }
+        if result.truncated {
+            println!(
+                "    {}",
+                format!(
+                    "more matches skipped after the first {}",
+                    MAX_MATCHES_PER_OBJECT
+                )
+                .dimmed()
+            );
+        }
+    }
+    println!();
+}
@@ -0,0 +1,198 @@
+use log::{debug, trace};
+use lopdf::Object;
+use serde::Serialize;
+
+use crate::metadata_helpers::decode_text_string;
+
+/// a signature dictionary and what its /ByteRange covers
+///
+/// nothing here checks the signature itself, only which bytes
+/// of the file it claims to protect
+#[derive(Debug, Default, Serialize)]
+pub struct SignatureInfo {
+    pub object_id: u32,
+    pub name: Option<String>,
+    pub reason: Option<String>,
+    pub location: Option<String>,
+    pub signing_time: Option<String>,
+    pub sub_filter: Option<String>,
+    pub byte_range: Vec<i64>,
+    /// hex length of /Contents including the angle brackets
+    pub contents_len: usize,
+    /// file bytes after the end of the signed range
+    pub unsigned_trailing_bytes: usize,
+    /// gaps in the byte range other than the /Contents hole
+    pub unexpected_gaps: usize,
+    /// byte range is malformed or points outside the file
+    pub invalid_range: bool,
+}
+
+/// find every signature dictionary in the document
+///
+/// coverage fields stay empty until check_signature_coverage runs,
+/// the document alone doesn't know how big the file was
+pub fn collect_signatures(doc: &lopdf::Document) -> Vec<SignatureInfo> {
+    let mut signatures = Vec::new();
+
+    for (object_id, object) in doc.objects.iter() {
+        let Object::Dictionary(dict) = object else {
+            continue;
+        };
+        let is_sig_type = matches!(
+            dict.get(b"Type"),
+            Ok(Object::Name(t)) if t == b"Sig" || t == b"DocTimeStamp"
+        );
+        let has_signed_range = dict.has(b"ByteRange") && dict.has(b"Contents");
+        if !is_sig_type && !has_signed_range {
+            continue;
+        }
+
+        let text = |key: &[u8]| match dict.get(key) {
+            Ok(Object::String(bytes, _)) => Some(decode_text_string(bytes)),
+            Ok(Object::Name(name)) => Some(String::from_utf8_lossy(name).to_string()),
+            _ => None,
+        };
+
+        let byte_range = match dict.get(b"ByteRange") {
+            Ok(Object::Array(items)) => items.iter().filter_map(|i| i.as_i64().ok()).collect(),
+            Ok(Object::Reference(id)) => match doc.get_object(*id) {
+                Ok(Object::Array(items)) => items.iter().filter_map(|i| i.as_i64().ok()).collect(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        let contents_len = match dict.get(b"Contents") {
+            Ok(Object::String(bytes, _)) => bytes.len() * 2 + 2,
+            _ => 0,
+        };
+
+        trace!(
+            "signature in {:?} with byte range {:?}",
+            object_id, byte_range
+        );
+
+        signatures.push(SignatureInfo {
+            object_id: object_id.0,
+            name: text(b"Name"),
+            reason: text(b"Reason"),
+            location: text(b"Location"),
+            signing_time: text(b"M"),
+            sub_filter: text(b"SubFilter"),
+            byte_range,
+            contents_len,
+            ..Default::default()
+        });
+    }
+
+    signatures
+}
+
+/// compare each signature's byte range against the real file size
+///
+/// a normal signature covers [0, hole) and [hole end, eof) where
+/// the hole is exactly the /Contents string. anything after the
+/// covered range was added after signing
+pub fn check_signature_coverage(signatures: &mut [SignatureInfo], file_size: usize) {
+    for signature in signatures.iter_mut() {
+        let range = &signature.byte_range;
+        if range.len() < 2 || range.len() % 2 == 1 || range.iter().any(|value| *value < 0) {
+            signature.invalid_range = true;
+            continue;
+        }
+
+        let mut spans: Vec<(usize, usize)> = range
+            .chunks_exact(2)
+            .map(|pair| {
+                let start = pair[0] as usize;
+                (start, start.saturating_add(pair[1] as usize))
+            })
+            .collect();
+        spans.sort();
+
+        let covered_end = spans.iter().map(|(_, end)| *end).max().unwrap_or(0);
+        if spans[0].0 != 0 || covered_end > file_size {
+            signature.invalid_range = true;
+        }
+        signature.unsigned_trailing_bytes = file_size.saturating_sub(covered_end);
+
+        // the one expected gap is the /Contents hex string itself
+        signature.unexpected_gaps = spans
+            .windows(2)
+            .filter(|pair| pair[1].0 > pair[0].1)
+            .enumerate()
+            .filter(|(index, pair)| *index > 0 || pair[1].0 - pair[0].1 != signature.contents_len)
+            .count();
+
+        debug!(
+            "signature {}: covers to {} of {} bytes, {} unexpected gaps",
+            signature.object_id, covered_end, file_size, signature.unexpected_gaps
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{Dictionary, StringFormat};
+
+    /// bytes before the /Contents hole and after it, like a signed file
+    const BEFORE_HOLE: usize = 1200;
+    const AFTER_HOLE: usize = 800;
+    const SIGNATURE_BYTES: usize = 64;
+
+    /// a signature whose range skips exactly its /Contents string
+    fn signed_doc() -> lopdf::Document {
+        let contents_len = SIGNATURE_BYTES * 2 + 2;
+        let hole_end = BEFORE_HOLE + contents_len;
+
+        let mut sig = Dictionary::new();
+        sig.set("Type", Object::Name(b"Sig".to_vec()));
+        sig.set("SubFilter", Object::Name(b"adbe.pkcs7.detached".to_vec()));
+        sig.set(
+            "ByteRange",
+            vec![
+                Object::Integer(0),
+                Object::Integer(BEFORE_HOLE as i64),
+                Object::Integer(hole_end as i64),
+                Object::Integer(AFTER_HOLE as i64),
+            ],
+        );
+        sig.set(
+            "Contents",
+            Object::String(vec![0; SIGNATURE_BYTES], StringFormat::Hexadecimal),
+        );
+
+        let mut doc = lopdf::Document::new();
+        doc.objects.insert((5, 0), Object::Dictionary(sig));
+        doc
+    }
+
+    fn signed_file_size() -> usize {
+        BEFORE_HOLE + SIGNATURE_BYTES * 2 + 2 + AFTER_HOLE
+    }
+
+    #[test]
+    fn byte_range_covering_the_whole_file() {
+        let mut signatures = collect_signatures(&signed_doc());
+        assert_eq!(signatures.len(), 1);
+
+        check_signature_coverage(&mut signatures, signed_file_size());
+        let signature = &signatures[0];
+        assert!(!signature.invalid_range);
+        assert_eq!(signature.unsigned_trailing_bytes, 0);
+        assert_eq!(signature.unexpected_gaps, 0);
+    }
+
+    #[test]
+    fn byte_range_missing_an_incremental_update() {
+        let mut signatures = collect_signatures(&signed_doc());
+        assert_eq!(signatures.len(), 1);
+
+        // an update appended after signing isn't covered
+        check_signature_coverage(&mut signatures, signed_file_size() + 350);
+        let signature = &signatures[0];
+        assert!(!signature.invalid_range);
+        assert_eq!(signature.unsigned_trailing_bytes, 350);
+        assert_eq!(signature.unexpected_gaps, 0);
+    }
+}
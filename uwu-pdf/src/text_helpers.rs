@@ -0,0 +1,163 @@
+use colored::Colorize;
+use log::{debug, info, trace, warn};
+use lopdf::Object;
+use lopdf::content::Content;
+use std::fs;
+use std::path::Path;
+
+use crate::extraction_helpers::ByteBudget;
+use crate::metadata_helpers::decode_text_string;
+
+/// TJ adjustments past this (thousandths of an em) are treated as a word gap
+const TJ_SPACE_THRESHOLD: f32 = 200.0;
+
+/// pull readable text out of every page and write text_extracted/page_N.txt
+///
+/// this is approximate, strings come out in content stream order and
+/// fonts with custom encodings or no ToUnicode will look like garbage
+pub fn extract_page_text(
+    doc: &lopdf::Document,
+    output_dir: &Path,
+    budget: &mut ByteBudget,
+) -> Result<usize, String> {
+    let text_dir = output_dir.join("text_extracted");
+    let mut count = 0;
+
+    for (page_num, page_id) in doc.get_pages() {
+        let content = match doc.get_page_content(page_id) {
+            Ok(content) => content,
+            Err(e) => {
+                debug!("could not read content for page {}: {:?}", page_num, e);
+                continue;
+            }
+        };
+
+        let text = page_text(&content);
+        if text.trim().is_empty() {
+            trace!("no text on page {}", page_num);
+            continue;
+        }
+
+        if let Err(e) = fs::create_dir_all(&text_dir) {
+            warn!("failed to create text directory: {}", e);
+            return Ok(count);
+        }
+
+        let filename = format!("page_{}.txt", page_num);
+        budget.reserve(&filename, text.len())?;
+        if let Err(e) = fs::write(text_dir.join(&filename), &text) {
+            warn!("failed to write page text {}: {}", filename, e);
+        } else {
+            println!(
+                "  {} {} ({} chars)",
+                "「page text」".green().bold(),
+                filename.cyan(),
+                text.chars().count().to_string().yellow()
+            );
+            count += 1;
+        }
+    }
+
+    info!("extracted text from {} pages", count);
+    Ok(count)
+}
+
+/// walk the text operators in a content stream and rebuild the text
+pub fn page_text(content: &[u8]) -> String {
+    let operations = match Content::decode(content) {
+        Ok(decoded) => decoded.operations,
+        Err(e) => {
+            debug!("failed to tokenize content stream: {:?}", e);
+            return String::new();
+        }
+    };
+
+    let mut text = String::new();
+    let mut last_y: Option<f32> = None;
+
+    for op in operations {
+        let operands = &op.operands;
+        match op.operator.as_str() {
+            "Tj" => push_string(&mut text, operands.first()),
+            "TJ" => {
+                let Some(Object::Array(items)) = operands.first() else {
+                    continue;
+                };
+                for item in items {
+                    match item {
+                        Object::String(..) => push_string(&mut text, Some(item)),
+                        other => {
+                            // negative adjustments move the next glyph right
+                            if number(other).is_some_and(|n| -n > TJ_SPACE_THRESHOLD) {
+                                push_space(&mut text);
+                            }
+                        }
+                    }
+                }
+            }
+            "'" => {
+                push_newline(&mut text);
+                push_string(&mut text, operands.first());
+            }
+            "\"" => {
+                push_newline(&mut text);
+                push_string(&mut text, operands.get(2));
+            }
+            "Td" | "TD" => {
+                let tx = operands.first().and_then(number).unwrap_or(0.0);
+                let ty = operands.get(1).and_then(number).unwrap_or(0.0);
+                if ty != 0.0 {
+                    push_newline(&mut text);
+                } else if tx != 0.0 {
+                    push_space(&mut text);
+                }
+            }
+            "T*" => push_newline(&mut text),
+            "Tm" => {
+                let y = operands.get(5).and_then(number);
+                if y.is_some() && last_y.is_some() && y != last_y {
+                    push_newline(&mut text);
+                } else {
+                    push_space(&mut text);
+                }
+                last_y = y;
+            }
+            "BT" => push_newline(&mut text),
+            _ => {}
+        }
+    }
+
+    let mut text = text.trim().to_string();
+    text.push('\n');
+    text
+}
+
+fn number(object: &Object) -> Option<f32> {
+    match object {
+        Object::Integer(value) => Some(*value as f32),
+        Object::Real(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn push_string(text: &mut String, operand: Option<&Object>) {
+    if let Some(Object::String(bytes, _)) = operand {
+        let decoded = decode_text_string(bytes);
+        text.extend(decoded.chars().filter(|c| !c.is_control()));
+    }
+}
+
+fn push_space(text: &mut String) {
+    if !text.is_empty() && !text.ends_with([' ', '\n']) {
+        text.push(' ');
+    }
+}
+
+fn push_newline(text: &mut String) {
+    if text.ends_with(' ') {
+        text.pop();
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
@@ -0,0 +1,107 @@
+use colored::Colorize;
+use log::{debug, info, warn};
+use lopdf::Object;
+use std::fs;
+use std::path::Path;
+
+use crate::extraction_helpers::ByteBudget;
+
+/// the catalog's /AcroForm dictionary, if there is one
+pub fn acroform(doc: &lopdf::Document) -> Option<&lopdf::Dictionary> {
+    match doc.catalog().ok()?.get(b"AcroForm").ok()? {
+        Object::Dictionary(dict) => Some(dict),
+        Object::Reference(id) => doc.get_dictionary(*id).ok(),
+        _ => None,
+    }
+}
+
+/// (packet name, xml) for every XFA packet in the AcroForm
+///
+/// /XFA is either one stream holding the whole xdp document or an
+/// array alternating packet names and streams
+pub fn xfa_packets(doc: &lopdf::Document) -> Vec<(String, Vec<u8>)> {
+    let Some(xfa) = acroform(doc).and_then(|form| form.get(b"XFA").ok()) else {
+        return Vec::new();
+    };
+
+    let stream_data = |object: &Object| -> Option<Vec<u8>> {
+        let stream = match object {
+            Object::Reference(id) => doc.get_object(*id).ok()?.as_stream().ok()?,
+            Object::Stream(stream) => stream,
+            _ => return None,
+        };
+        Some(
+            stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone()),
+        )
+    };
+
+    let xfa = match xfa {
+        Object::Reference(id) => doc.get_object(*id).unwrap_or(xfa),
+        other => other,
+    };
+
+    match xfa {
+        Object::Array(parts) => parts
+            .chunks_exact(2)
+            .filter_map(|pair| {
+                let name = match &pair[0] {
+                    Object::String(bytes, _) => String::from_utf8_lossy(bytes).to_string(),
+                    _ => "packet".to_string(),
+                };
+                Some((name, stream_data(&pair[1])?))
+            })
+            .collect(),
+        Object::Stream(_) => stream_data(xfa)
+            .map(|data| vec![("xdp".to_string(), data)])
+            .unwrap_or_default(),
+        _ => {
+            debug!("/XFA is neither an array nor a stream");
+            Vec::new()
+        }
+    }
+}
+
+/// write every XFA packet to output_dir/xfa
+pub fn extract_xfa(
+    doc: &lopdf::Document,
+    output_dir: &Path,
+    budget: &mut ByteBudget,
+) -> Result<usize, String> {
+    let packets = xfa_packets(doc);
+    if packets.is_empty() {
+        return Ok(0);
+    }
+
+    let xfa_dir = output_dir.join("xfa");
+    if let Err(e) = fs::create_dir_all(&xfa_dir) {
+        warn!("failed to create xfa directory: {}", e);
+        return Ok(0);
+    }
+
+    let mut count = 0;
+    for (index, (name, data)) in packets.iter().enumerate() {
+        let sanitized: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let filename = format!("{:02}_{}.xml", index, sanitized);
+
+        budget.reserve(&filename, data.len())?;
+        if let Err(e) = fs::write(xfa_dir.join(&filename), data) {
+            warn!("failed to write xfa packet {}: {}", filename, e);
+        } else {
+            println!(
+                "  {} {} ({} bytes)",
+                "「xfa」".green().bold(),
+                filename.cyan(),
+                data.len().to_string().yellow()
+            );
+            count += 1;
+        }
+    }
+
+    info!("extracted {} xfa packets", count);
+    Ok(count)
+}
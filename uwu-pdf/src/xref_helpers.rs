@@ -0,0 +1,159 @@
+use log::{debug, trace};
+use std::collections::BTreeMap;
+
+/// rebuild a pdf's xref by scanning for `N G obj` headers
+///
+/// the original bytes are kept and a fresh xref table and trailer
+/// are appended, so lopdf's own parser still does the real work.
+/// later definitions of an object win, same as incremental updates
+pub fn rebuild_xref(bytes: &[u8]) -> Option<Vec<u8>> {
+    let offsets = scan_object_offsets(bytes);
+    if offsets.is_empty() {
+        debug!("no objects found while rebuilding xref");
+        return None;
+    }
+    debug!("found {} objects while rebuilding xref", offsets.len());
+
+    let root = find_trailer_reference(bytes, b"/Root").or_else(|| find_catalog(bytes, &offsets))?;
+    let info = find_trailer_reference(bytes, b"/Info");
+    trace!("rebuilt trailer root {:?} info {:?}", root, info);
+
+    let size = offsets.keys().next_back()? + 1;
+    let mut rebuilt = bytes.to_vec();
+    if !rebuilt.ends_with(b"\n") {
+        rebuilt.push(b'\n');
+    }
+
+    let xref_offset = rebuilt.len();
+    rebuilt.extend_from_slice(format!("xref\n0 {}\n", size).as_bytes());
+    for number in 0..size {
+        let entry = match offsets.get(&number) {
+            Some((offset, generation)) => format!("{:010} {:05} n\r\n", offset, generation),
+            None => "0000000000 65535 f\r\n".to_string(),
+        };
+        rebuilt.extend_from_slice(entry.as_bytes());
+    }
+
+    let mut trailer = format!("trailer\n<< /Size {} /Root {} {} R", size, root.0, root.1);
+    if let Some((number, generation)) = info {
+        trailer.push_str(&format!(" /Info {} {} R", number, generation));
+    }
+    trailer.push_str(&format!(" >>\nstartxref\n{}\n%%EOF\n", xref_offset));
+    rebuilt.extend_from_slice(trailer.as_bytes());
+
+    Some(rebuilt)
+}
+
+/// map of object number to (byte offset, generation)
+fn scan_object_offsets(bytes: &[u8]) -> BTreeMap<u32, (usize, u16)> {
+    scan_object_headers(bytes)
+        .into_iter()
+        .map(|(start, number, generation)| (number, (start, generation)))
+        .collect()
+}
+
+/// every `N G obj` header in file order, as (offset, number, generation)
+///
+/// unlike the parsed document this keeps objects that were defined
+/// more than once
+pub fn scan_object_headers(bytes: &[u8]) -> Vec<(usize, u32, u16)> {
+    let mut headers = Vec::new();
+
+    for pos in 0..bytes.len().saturating_sub(3) {
+        if &bytes[pos..pos + 3] != b"obj" {
+            continue;
+        }
+        let after = bytes.get(pos + 3).copied().unwrap_or(b' ');
+        if !(after.is_ascii_whitespace() || after == b'<' || after == b'[' || after == b'/') {
+            continue;
+        }
+        if let Some((start, number, generation)) = object_header_before(bytes, pos) {
+            trace!("object {} {} at {}", number, generation, start);
+            headers.push((start, number, generation));
+        }
+    }
+
+    headers
+}
+
+/// walk back from `obj` over `N G ` and return where N starts
+fn object_header_before(bytes: &[u8], obj_pos: usize) -> Option<(usize, u32, u16)> {
+    let mut pos = obj_pos;
+
+    let skip_whitespace = |pos: &mut usize| -> bool {
+        let start = *pos;
+        while *pos > 0 && bytes[*pos - 1].is_ascii_whitespace() {
+            *pos -= 1;
+        }
+        *pos < start
+    };
+    let take_digits = |pos: &mut usize| -> Option<u64> {
+        let end = *pos;
+        while *pos > 0 && bytes[*pos - 1].is_ascii_digit() {
+            *pos -= 1;
+        }
+        if *pos == end || end - *pos > 10 {
+            return None;
+        }
+        std::str::from_utf8(&bytes[*pos..end]).ok()?.parse().ok()
+    };
+
+    if !skip_whitespace(&mut pos) {
+        return None;
+    }
+    let generation = take_digits(&mut pos)?;
+    if !skip_whitespace(&mut pos) {
+        return None;
+    }
+    let number = take_digits(&mut pos)?;
+
+    if pos > 0 && !bytes[pos - 1].is_ascii_whitespace() {
+        return None;
+    }
+
+    Some((
+        pos,
+        u32::try_from(number).ok()?,
+        u16::try_from(generation).ok()?,
+    ))
+}
+
+/// find `/Key N G R` in the last trailer, or anywhere if there isn't one
+///
+/// xref streams keep /Root in the stream dict so the file-wide
+/// search still catches those
+fn find_trailer_reference(bytes: &[u8], key: &[u8]) -> Option<(u32, u16)> {
+    let search_from = rfind(bytes, b"trailer").unwrap_or(0);
+    let key_pos = search_from + rfind(&bytes[search_from..], key)?;
+    parse_reference(&bytes[key_pos + key.len()..])
+}
+
+/// fall back to whichever object mentions /Catalog
+fn find_catalog(bytes: &[u8], offsets: &BTreeMap<u32, (usize, u16)>) -> Option<(u32, u16)> {
+    let catalog_pos = rfind(bytes, b"/Catalog")?;
+    offsets
+        .iter()
+        .filter(|(_, (offset, _))| *offset < catalog_pos)
+        .max_by_key(|(_, (offset, _))| *offset)
+        .map(|(number, (_, generation))| (*number, *generation))
+}
+
+/// parse ` N G R` at the start of a slice
+fn parse_reference(bytes: &[u8]) -> Option<(u32, u16)> {
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(32)]);
+    let mut parts = text.split_ascii_whitespace();
+    let number = parts.next()?.parse().ok()?;
+    let generation = parts.next()?.parse().ok()?;
+    let marker = parts.next()?;
+    if marker.starts_with('R') {
+        Some((number, generation))
+    } else {
+        None
+    }
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
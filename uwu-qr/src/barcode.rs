@@ -0,0 +1,279 @@
+use crate::qr_decoder::QrDecoder;
+use image::GrayImage;
+use log::debug;
+use rxing::common::HybridBinarizer;
+use rxing::qrcode::QRCodeReader;
+use rxing::{
+    BarcodeFormat, BinaryBitmap, DecodeHintType, DecodeHintValue, Luma8LuminanceSource,
+    RXingResult, RXingResultMetadataType, RXingResultMetadataValue, Reader,
+};
+use std::collections::{HashMap, HashSet};
+
+/// which 2d codes to look for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Symbology {
+    Qr,
+    DataMatrix,
+    Aztec,
+    All,
+}
+
+impl Symbology {
+    pub fn name(self) -> &'static str {
+        match self {
+            Symbology::Qr => "QR",
+            Symbology::DataMatrix => "Data Matrix",
+            Symbology::Aztec => "Aztec",
+            Symbology::All => "all",
+        }
+    }
+
+    /// what each decoded code is headed with
+    pub fn heading(self) -> &'static str {
+        match self {
+            Symbology::Qr => "qr code",
+            Symbology::DataMatrix => "data matrix",
+            Symbology::Aztec => "aztec",
+            Symbology::All => "code",
+        }
+    }
+}
+
+/// a code one of the decoders found, whether or not it decoded
+pub struct DecodedCode {
+    /// corners in image pixels, clockwise from the top left
+    pub corners: [(i32, i32); 4],
+    /// what it held, or why it couldn't be read
+    pub result: Result<CodeContent, String>,
+}
+
+/// the payload of a decoded code and what's known about the symbol
+pub struct CodeContent {
+    pub bytes: Vec<u8>,
+    /// qr only
+    pub version: Option<usize>,
+    pub ecc_level: Option<&'static str>,
+    /// set when the code is one part of a structured append sequence
+    pub part: Option<SequencePart>,
+}
+
+/// where a structured append part goes in its message
+#[derive(Debug, Clone, Copy)]
+pub struct SequencePart {
+    /// 0 based
+    pub position: u8,
+    pub total: u8,
+    /// xor of every byte of the whole message, the same in all its parts
+    pub parity: u8,
+}
+
+/// what a decoder had to do to a frame before it found anything
+pub enum Adjustment {
+    Threshold(u8),
+    Preprocess {
+        pass: &'static str,
+        orientation: Option<&'static str>,
+    },
+}
+
+/// everything one decoder found in one frame
+#[derive(Default)]
+pub struct FrameScan {
+    pub codes: Vec<DecodedCode>,
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// a decoder for one symbology
+pub trait BarcodeDecoder {
+    fn symbology(&self) -> Symbology;
+    fn decode(&self, gray_img: &GrayImage) -> FrameScan;
+}
+
+/// data matrix and aztec both go through rxing, which finds the symbol
+/// in the image as well as decoding it
+struct RxingDecoder {
+    symbology: Symbology,
+    format: BarcodeFormat,
+}
+
+impl BarcodeDecoder for RxingDecoder {
+    fn symbology(&self) -> Symbology {
+        self.symbology
+    }
+
+    fn decode(&self, gray_img: &GrayImage) -> FrameScan {
+        let codes = read_with_rxing(gray_img, self.format)
+            .into_iter()
+            .map(|(corners, content)| DecodedCode {
+                corners,
+                result: Ok(content),
+            })
+            .collect();
+        FrameScan {
+            codes,
+            adjustments: Vec::new(),
+        }
+    }
+}
+
+/// the decoders for the requested symbologies, qr when none were asked for
+pub fn decoders(symbologies: &[Symbology], reprobe: bool) -> Vec<Box<dyn BarcodeDecoder>> {
+    let wants =
+        |symbology| symbologies.contains(&symbology) || symbologies.contains(&Symbology::All);
+
+    let mut decoders: Vec<Box<dyn BarcodeDecoder>> = Vec::new();
+    if symbologies.is_empty() || wants(Symbology::Qr) {
+        decoders.push(Box::new(QrDecoder { reprobe }));
+    }
+    if wants(Symbology::DataMatrix) {
+        decoders.push(Box::new(RxingDecoder {
+            symbology: Symbology::DataMatrix,
+            format: BarcodeFormat::DATA_MATRIX,
+        }));
+    }
+    if wants(Symbology::Aztec) {
+        decoders.push(Box::new(RxingDecoder {
+            symbology: Symbology::Aztec,
+            format: BarcodeFormat::AZTEC,
+        }));
+    }
+    decoders
+}
+
+/// every code of one format rxing can find in the image
+pub fn read_with_rxing(
+    gray_img: &GrayImage,
+    format: BarcodeFormat,
+) -> Vec<([(i32, i32); 4], CodeContent)> {
+    let mut hints = HashMap::from([(
+        DecodeHintType::POSSIBLE_FORMATS,
+        DecodeHintValue::PossibleFormats(HashSet::from([format])),
+    )]);
+    let results = rxing::helpers::detect_multiple_in_luma_with_hints(
+        gray_img.as_raw().clone(),
+        gray_img.width(),
+        gray_img.height(),
+        &mut hints,
+    );
+
+    match results {
+        Ok(results) => results
+            .iter()
+            .map(|result| {
+                let points: Vec<(i32, i32)> = result
+                    .getPoints()
+                    .iter()
+                    .map(|p| (p.x as i32, p.y as i32))
+                    .collect();
+                let content = CodeContent {
+                    bytes: payload_bytes(result),
+                    version: None,
+                    ecc_level: None,
+                    part: None,
+                };
+                (bounding_corners(&points), content)
+            })
+            .collect(),
+        Err(e) => {
+            debug!("no {:?} code found: {:?}", format, e);
+            Vec::new()
+        }
+    }
+}
+
+/// read a structured append qr part from an image holding just that code
+///
+/// the multi-code reader only keeps a part's position and count, the
+/// plain qr reader keeps the whole sequence byte and the parity as well
+pub fn read_qr_part(gray_img: &GrayImage) -> Option<CodeContent> {
+    let source = Luma8LuminanceSource::new(
+        gray_img.as_raw().clone(),
+        gray_img.width(),
+        gray_img.height(),
+    );
+    let mut bitmap = BinaryBitmap::new(HybridBinarizer::new(source));
+    let hints = HashMap::from([(DecodeHintType::TRY_HARDER, DecodeHintValue::TryHarder(true))]);
+    let result = QRCodeReader::new()
+        .decode_with_hints(&mut bitmap, &hints)
+        .map_err(|e| debug!("rxing couldn't read the part: {:?}", e))
+        .ok()?;
+
+    let metadata = result.getRXingResultMetadata();
+    let Some(RXingResultMetadataValue::StructuredAppendSequence(sequence)) =
+        metadata.get(&RXingResultMetadataType::STRUCTURED_APPEND_SEQUENCE)
+    else {
+        return None;
+    };
+    let Some(RXingResultMetadataValue::StructuredAppendParity(parity)) =
+        metadata.get(&RXingResultMetadataType::STRUCTURED_APPEND_PARITY)
+    else {
+        return None;
+    };
+
+    Some(CodeContent {
+        bytes: payload_bytes(&result),
+        version: None,
+        ecc_level: None,
+        // the high nibble is the position, the low one the count less one
+        part: Some(SequencePart {
+            position: (sequence >> 4 & 0xf) as u8,
+            total: (sequence & 0xf) as u8 + 1,
+            parity: *parity as u8,
+        }),
+    })
+}
+
+/// the payload as it was encoded
+///
+/// rxing decodes to text, so binary payloads come back as latin-1. the
+/// byte segments hold what byte mode actually carried, but leave out
+/// numeric and alphanumeric runs, so they're only used when they
+/// account for the whole text. aztec has no segments at all, its text
+/// is latin-1 unless an eci says otherwise, so that maps back to the
+/// bytes when they're utf-8 or have control codes no text would
+fn payload_bytes(result: &RXingResult) -> Vec<u8> {
+    let text = result.getText();
+    let segments = match result
+        .getRXingResultMetadata()
+        .get(&RXingResultMetadataType::BYTE_SEGMENTS)
+    {
+        Some(RXingResultMetadataValue::ByteSegments(segments)) => segments.concat(),
+        _ => Vec::new(),
+    };
+
+    let whole_text =
+        std::str::from_utf8(&segments) == Ok(text) || text.chars().count() == segments.len();
+    if !segments.is_empty() && whole_text {
+        return segments;
+    }
+
+    let latin1: Option<Vec<u8>> = text.chars().map(|c| u8::try_from(c).ok()).collect();
+    match latin1 {
+        Some(bytes)
+            if segments.is_empty()
+                && (std::str::from_utf8(&bytes).is_ok() || bytes.iter().any(is_binary)) =>
+        {
+            bytes
+        }
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+/// c0 and c1 control codes, other than the whitespace text has
+fn is_binary(byte: &u8) -> bool {
+    matches!(byte, 0x00..=0x08 | 0x0b | 0x0c | 0x0e..=0x1f | 0x7f..=0x9f)
+}
+
+/// the decoders report a varying number of finder points, so box them
+fn bounding_corners(points: &[(i32, i32)]) -> [(i32, i32); 4] {
+    let min_x = points.iter().map(|p| p.0).min().unwrap_or(0);
+    let max_x = points.iter().map(|p| p.0).max().unwrap_or(0);
+    let min_y = points.iter().map(|p| p.1).min().unwrap_or(0);
+    let max_y = points.iter().map(|p| p.1).max().unwrap_or(0);
+    [
+        (min_x, min_y),
+        (max_x, min_y),
+        (max_x, max_y),
+        (min_x, max_y),
+    ]
+}
@@ -0,0 +1,206 @@
+use crate::image_io;
+use crate::qr_decoder::{self, DecodeOptions, DecodeOutcome, OutputFormat, QrJson};
+use colored::Colorize;
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// image types picked up from a directory, by extension
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "bmp", "gif", "tif", "tiff"];
+
+/// longest payload shown in the summary before it gets cut off
+const SUMMARY_CONTENT_LEN: usize = 60;
+
+/// one file in the --format json document, its codes or why it was skipped
+#[derive(Serialize)]
+struct FileJson<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codes: Option<&'a [QrJson]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// decode every image directly inside a directory
+///
+/// files that can't be read get a warning and the rest carry on,
+/// then a summary lists what each file held. --output and --debug-image
+/// go to a directory named after each file, and --format json prints
+/// one document keyed by path. the exit status covers the whole
+/// directory, 0 if any file had a code that decoded
+pub fn decode_directory(
+    dir: &Path,
+    options: &DecodeOptions,
+    normalize_orientation: bool,
+    all_frames: bool,
+    found_only: bool,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| {
+                        IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+                    })
+        })
+        .collect();
+    files.sort();
+    debug!("found {} image(s) in {}", files.len(), dir.display());
+
+    let mut results: Vec<(String, Result<DecodeOutcome, String>)> = Vec::new();
+    for file in &files {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+        let file_options = per_file_options(options, stem);
+
+        if options.decorated() {
+            println!("{} {}", "「file」".magenta().bold(), name.yellow());
+            println!();
+        }
+
+        let frames = if all_frames {
+            image_io::read_all_frames(file, normalize_orientation)
+        } else {
+            image_io::read_image_from_file(file, normalize_orientation).map(|img| vec![img])
+        };
+        let result = frames
+            .and_then(|frames| {
+                create_output_dirs(&file_options)?;
+                qr_decoder::decode_qr_codes(&frames, &file_options)
+            })
+            .map_err(|e| e.to_string());
+
+        if let Err(e) = &result {
+            warn!("skipping {}: {}", name, e);
+        }
+        results.push((name, result));
+    }
+
+    if options.decorated() {
+        print_batch_summary(&results, found_only);
+    }
+
+    if options.format == OutputFormat::Json {
+        let document: BTreeMap<String, FileJson> = files
+            .iter()
+            .zip(&results)
+            .map(|(file, (_, result))| {
+                let entry = match result {
+                    Ok(outcome) => FileJson {
+                        codes: Some(&outcome.json),
+                        error: None,
+                    },
+                    Err(e) => FileJson {
+                        codes: None,
+                        error: Some(e),
+                    },
+                };
+                (file.display().to_string(), entry)
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&document)?);
+    }
+
+    let outcomes = results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().ok());
+    let (detected, decoded) = outcomes.fold((0, 0), |(detected, decoded), outcome| {
+        (
+            detected + outcome.detected,
+            decoded + outcome.payloads.len(),
+        )
+    });
+    Ok(qr_decoder::exit_code(detected, decoded))
+}
+
+/// point --output and --debug-image at a directory for the file
+///
+/// paths given on the command line keep their file name, so
+/// `-o out/codes.txt` becomes `out/<stem>/codes.txt` and files don't
+/// overwrite each other's payloads and debug images
+fn per_file_options(options: &DecodeOptions, stem: &str) -> DecodeOptions {
+    let relocate = |path: &Option<PathBuf>| {
+        path.as_ref().and_then(|path| {
+            let parent = path.parent().unwrap_or(Path::new(""));
+            Some(parent.join(stem).join(path.file_name()?))
+        })
+    };
+
+    DecodeOptions {
+        output: relocate(&options.output),
+        debug_image: relocate(&options.debug_image),
+        ..options.clone()
+    }
+}
+
+fn create_output_dirs(options: &DecodeOptions) -> Result<(), Box<dyn std::error::Error>> {
+    for path in [&options.output, &options.debug_image]
+        .into_iter()
+        .flatten()
+    {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_batch_summary(results: &[(String, Result<DecodeOutcome, String>)], found_only: bool) {
+    println!("{}", "「batch summary」".magenta().bold());
+
+    for (name, result) in results {
+        match result {
+            Ok(outcome) if outcome.payloads.is_empty() => {
+                if !found_only {
+                    println!("  {}: {}", name, "no codes".dimmed());
+                }
+            }
+            Ok(DecodeOutcome { payloads, .. }) => {
+                println!(
+                    "  {}: {}",
+                    name,
+                    format!("{} code(s)", payloads.len()).green()
+                );
+                for payload in payloads {
+                    println!("    {}", summarize(payload));
+                }
+            }
+            Err(e) => println!("  {}: {}", name, format!("unreadable, {}", e).red()),
+        }
+    }
+
+    let with_codes = results
+        .iter()
+        .filter(|(_, result)| {
+            result
+                .as_ref()
+                .is_ok_and(|outcome| !outcome.payloads.is_empty())
+        })
+        .count();
+    println!(
+        "{} {} file(s), {} with codes",
+        "「batch done」".green().bold(),
+        results.len().to_string().yellow(),
+        with_codes.to_string().yellow()
+    );
+}
+
+/// one line of a payload, cut short if it's long
+fn summarize(payload: &[u8]) -> String {
+    let text = String::from_utf8_lossy(payload);
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() > SUMMARY_CONTENT_LEN || line.len() < text.len() {
+        let short: String = line.chars().take(SUMMARY_CONTENT_LEN).collect();
+        format!("{}…", short)
+    } else {
+        line.to_string()
+    }
+}
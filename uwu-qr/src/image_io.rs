@@ -1,9 +1,35 @@
-use image::DynamicImage;
-use log::{debug, error, info};
-use std::path::PathBuf;
+use crate::qr_decoder::DetectedBox;
+use image::codecs::gif::GifDecoder;
+use image::metadata::Orientation;
+use image::{
+    AnimationDecoder, DynamicImage, GrayImage, ImageDecoder, ImageFormat, ImageReader, RgbImage,
+    Rgba, RgbaImage,
+};
+use log::{debug, error, info, warn};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use tiff::ColorType;
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult};
+use tiff::tags::Tag;
 
-pub fn read_image_from_file(path: &PathBuf) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+/// outline colors for --debug-image
+const DECODED_COLOR: Rgba<u8> = Rgba([0, 220, 0, 255]);
+const FAILED_COLOR: Rgba<u8> = Rgba([230, 0, 0, 255]);
+
+/// outline thickness in pixels, either side of the edge
+const OUTLINE_RADIUS: i32 = 1;
+
+pub fn read_image_from_file(
+    path: &PathBuf,
+    normalize_orientation: bool,
+) -> Result<DynamicImage, Box<dyn std::error::Error>> {
     debug!("loading image from file: {}", path.display());
+
+    if normalize_orientation {
+        return read_oriented_image(path);
+    }
+
     let img = image::open(path).map_err(|e| {
         error!("failed to open image: {}", e);
         e
@@ -11,3 +37,219 @@ pub fn read_image_from_file(path: &PathBuf) -> Result<DynamicImage, Box<dyn std:
     info!("loaded image ({}x{})", img.width(), img.height());
     Ok(img)
 }
+
+/// read a whole image piped in on stdin, e.g. `grim - | uwu-qr`
+pub fn read_image_from_stdin() -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let mut buffer = Vec::new();
+    io::stdin().lock().read_to_end(&mut buffer)?;
+    debug!("read {} bytes from stdin", buffer.len());
+
+    if buffer.is_empty() {
+        return Err("no image data on stdin".into());
+    }
+
+    let img = image::load_from_memory(&buffer).map_err(|e| {
+        error!("failed to load image from stdin: {}", e);
+        e
+    })?;
+    info!("loaded image from stdin ({}x{})", img.width(), img.height());
+    Ok(img)
+}
+
+/// load an image and apply its EXIF orientation
+///
+/// phone photos are usually stored sideways with a tag saying so,
+/// only jpeg and tiff carry that tag so everything else loads as is
+fn read_oriented_image(path: &PathBuf) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+
+    if !matches!(reader.format(), Some(ImageFormat::Jpeg | ImageFormat::Tiff)) {
+        debug!(
+            "no EXIF orientation for {:?}, loading as is",
+            reader.format()
+        );
+        let img = reader.decode().map_err(|e| {
+            error!("failed to open image: {}", e);
+            e
+        })?;
+        info!("loaded image ({}x{})", img.width(), img.height());
+        return Ok(img);
+    }
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|e| {
+        error!("failed to open image: {}", e);
+        e
+    })?;
+
+    debug!("applying EXIF orientation {:?}", orientation);
+    img.apply_orientation(orientation);
+
+    info!("loaded image ({}x{})", img.width(), img.height());
+    Ok(img)
+}
+
+/// load every frame of an animated gif or page of a multi-page tiff
+///
+/// anything else comes back as a single frame, the same as
+/// read_image_from_file would give
+pub fn read_all_frames(
+    path: &PathBuf,
+    normalize_orientation: bool,
+) -> Result<Vec<DynamicImage>, Box<dyn std::error::Error>> {
+    let format = ImageReader::open(path)?.with_guessed_format()?.format();
+
+    let frames = match format {
+        Some(ImageFormat::Gif) => read_gif_frames(path)?,
+        Some(ImageFormat::Tiff) => read_tiff_pages(path, normalize_orientation)?,
+        _ => vec![read_image_from_file(path, normalize_orientation)?],
+    };
+
+    info!("loaded {} frame(s)", frames.len());
+    Ok(frames)
+}
+
+fn read_gif_frames(path: &PathBuf) -> Result<Vec<DynamicImage>, Box<dyn std::error::Error>> {
+    let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+    let frames = decoder.into_frames().collect_frames()?;
+    debug!("gif has {} frame(s)", frames.len());
+
+    Ok(frames
+        .into_iter()
+        .map(|frame| DynamicImage::ImageRgba8(frame.into_buffer()))
+        .collect())
+}
+
+/// the image crate only reads the first tiff page, so go to the tiff
+/// crate for the rest. pages in formats other than 8 bit gray/rgb/rgba
+/// are skipped
+///
+/// each page has its own orientation tag, applied per page when
+/// normalize_orientation is set
+fn read_tiff_pages(
+    path: &PathBuf,
+    normalize_orientation: bool,
+) -> Result<Vec<DynamicImage>, Box<dyn std::error::Error>> {
+    let mut decoder = TiffDecoder::new(BufReader::new(File::open(path)?))?;
+    let mut pages = Vec::new();
+
+    for page_number in 1.. {
+        let orientation = if normalize_orientation {
+            decoder
+                .find_tag_unsigned::<u8>(Tag::Orientation)?
+                .and_then(Orientation::from_exif)
+        } else {
+            None
+        };
+        let (width, height) = decoder.dimensions()?;
+        let color_type = decoder.colortype()?;
+        let page = match (decoder.read_image()?, color_type) {
+            (DecodingResult::U8(data), ColorType::Gray(8)) => {
+                GrayImage::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+            }
+            (DecodingResult::U8(data), ColorType::RGB(8)) => {
+                RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+            }
+            (DecodingResult::U8(data), ColorType::RGBA(8)) => {
+                RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+            }
+            _ => None,
+        };
+
+        match page {
+            Some(mut page) => {
+                if let Some(orientation) = orientation {
+                    debug!(
+                        "applying orientation {:?} to tiff page {}",
+                        orientation, page_number
+                    );
+                    page.apply_orientation(orientation);
+                }
+                pages.push(page);
+            }
+            None => warn!(
+                "skipping tiff page {} with unsupported color type {:?}",
+                page_number, color_type
+            ),
+        }
+
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image()?;
+    }
+
+    debug!("tiff has {} usable page(s)", pages.len());
+    Ok(pages)
+}
+
+/// draw each detected grid's outline over its frame and save the result
+///
+/// decoded grids are green and failed ones red. one frame is written to
+/// `path` as is, several get numbered names next to it. returns the
+/// paths written
+pub fn write_debug_images(
+    frames: &[DynamicImage],
+    boxes: &[DetectedBox],
+    path: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut written = Vec::new();
+
+    for (frame_index, frame) in frames.iter().enumerate() {
+        let mut canvas = frame.to_rgba8();
+        for detected in boxes.iter().filter(|b| b.frame == frame_index) {
+            let color = if detected.decoded {
+                DECODED_COLOR
+            } else {
+                FAILED_COLOR
+            };
+            for i in 0..4 {
+                let from = detected.corners[i];
+                let to = detected.corners[(i + 1) % 4];
+                draw_line(&mut canvas, from, to, color);
+            }
+        }
+
+        let frame_path = if frames.len() == 1 {
+            path.to_path_buf()
+        } else {
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| "debug".to_string());
+            let extension = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_string())
+                .unwrap_or_else(|| "png".to_string());
+            path.with_file_name(format!("{}-{}.{}", stem, frame_index + 1, extension))
+        };
+
+        canvas.save(&frame_path)?;
+        debug!("wrote debug image {}", frame_path.display());
+        written.push(frame_path);
+    }
+
+    Ok(written)
+}
+
+/// a thick line by stepping along the longer axis, clipped to the image
+fn draw_line(canvas: &mut RgbaImage, (x0, y0): (i32, i32), (x1, y1): (i32, i32), color: Rgba<u8>) {
+    let steps = (x1 - x0).abs().max((y1 - y0).abs()).max(1);
+    for step in 0..=steps {
+        let x = x0 + (x1 - x0) * step / steps;
+        let y = y0 + (y1 - y0) * step / steps;
+        for dy in -OUTLINE_RADIUS..=OUTLINE_RADIUS {
+            for dx in -OUTLINE_RADIUS..=OUTLINE_RADIUS {
+                let (px, py) = (x + dx, y + dy);
+                if px >= 0
+                    && py >= 0
+                    && (px as u32) < canvas.width()
+                    && (py as u32) < canvas.height()
+                {
+                    canvas.put_pixel(px as u32, py as u32, color);
+                }
+            }
+        }
+    }
+}
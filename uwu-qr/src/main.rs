@@ -1,21 +1,75 @@
+mod barcode;
+mod batch;
 mod clipboard;
 mod image_io;
+mod payload;
 mod qr_decoder;
 
 use clap::Parser;
 use log::info;
+use std::io::IsTerminal;
 use std::path::PathBuf;
+use std::process::ExitCode;
 
 #[derive(Parser)]
 #[command(name = "uwu-qr")]
 #[command(about = "🌸 「simple and cute qr code reader」 🌸")]
 struct Cli {
-    // either a path to a file or not, if not we'll get from clipboard
+    // a file, a directory of images, - for stdin, or nothing to read the
+    // clipboard (or stdin, when something is piped in)
     #[arg(short = 'i', long, value_name = "FILE")]
     input: Option<PathBuf>,
+
+    // with a directory input, leave files without codes out of the summary
+    #[arg(long)]
+    found_only: bool,
+
+    // rotate/flip file input according to its EXIF orientation tag
+    #[arg(long)]
+    normalize_orientation: bool,
+
+    // report the outcome of every detected grid, failures included
+    #[arg(long)]
+    report_all_decodings: bool,
+
+    // retry at several fixed binarization thresholds if nothing decodes
+    #[arg(long)]
+    reprobe: bool,
+
+    // print only the decoded content, for piping into other tools
+    #[arg(long)]
+    raw: bool,
+
+    // write each code's content to this file, numbered if there are several
+    #[arg(short = 'o', long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    // what goes between payloads in --raw mode
+    #[arg(long, value_enum, default_value = "newline")]
+    separator: qr_decoder::Separator,
+
+    // scan every frame of an animated gif or page of a multi-page tiff
+    #[arg(long)]
+    all_frames: bool,
+
+    // pretty-print known payloads: wifi, vcard, mailto, tel, sms, geo, urls
+    #[arg(long)]
+    parse: bool,
+
+    // draw where codes were found over the input and save it here
+    #[arg(long, value_name = "FILE")]
+    debug_image: Option<PathBuf>,
+
+    // which codes to look for: qr, data-matrix, aztec or all
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "qr")]
+    symbology: Vec<barcode::Symbology>,
+
+    // text for reading, json for scripts
+    #[arg(long, value_enum, default_value = "text")]
+    format: qr_decoder::OutputFormat,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
     pretty_env_logger::formatted_builder()
         .filter_level(log::LevelFilter::Warn)
         .parse_default_env()
@@ -23,18 +77,63 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let cli = Cli::parse();
 
-    let img = match cli.input {
+    let options = qr_decoder::DecodeOptions {
+        report_all: cli.report_all_decodings,
+        reprobe: cli.reprobe,
+        raw: cli.raw,
+        output: cli.output,
+        separator: cli.separator,
+        format: cli.format,
+        parse: cli.parse,
+        debug_image: cli.debug_image,
+        symbologies: cli.symbology,
+    };
+
+    if let Some(dir) = cli.input.as_ref().filter(|path| path.is_dir()) {
+        info!("reading QR codes from every image in: {}", dir.display());
+        return batch::decode_directory(
+            dir,
+            &options,
+            cli.normalize_orientation,
+            cli.all_frames,
+            cli.found_only,
+        );
+    }
+
+    let from_stdin = match &cli.input {
+        Some(path) => path.as_os_str() == "-",
+        None => !std::io::stdin().is_terminal(),
+    };
+
+    let frames = match cli.input {
+        _ if from_stdin => {
+            info!("reading QR code from stdin");
+            vec![image_io::read_image_from_stdin()?]
+        }
+        Some(path) if cli.all_frames => {
+            info!("reading QR codes from every frame of: {}", path.display());
+            image_io::read_all_frames(&path, cli.normalize_orientation)?
+        }
         Some(path) => {
             info!("reading QR code from file: {}", path.display());
-            image_io::read_image_from_file(&path)?
+            vec![image_io::read_image_from_file(
+                &path,
+                cli.normalize_orientation,
+            )?]
         }
         None => {
             info!("reading QR code from clipboard");
-            clipboard::read_image_from_clipboard()?
+            vec![clipboard::read_image_from_clipboard()?]
         }
     };
 
-    qr_decoder::decode_qr_codes(&img)?;
+    let outcome = qr_decoder::decode_qr_codes(&frames, &options)?;
+    if options.format == qr_decoder::OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&outcome.json)?);
+    }
 
-    Ok(())
+    Ok(qr_decoder::exit_code(
+        outcome.detected,
+        outcome.payloads.len(),
+    ))
 }
@@ -0,0 +1,248 @@
+/// a well-known qr payload broken into labelled fields
+#[derive(Debug)]
+pub struct ParsedPayload {
+    pub kind: &'static str,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+/// recognize the common payload schemes, None for anything else
+///
+/// covers WIFI:, vcards, mailto:, tel:, SMSTO:, geo: and http(s) urls
+pub fn parse_payload(content: &str) -> Option<ParsedPayload> {
+    let content = content.trim();
+    let lower = content.to_ascii_lowercase();
+
+    if lower.starts_with("wifi:") {
+        parse_wifi(&content[5..])
+    } else if lower.starts_with("begin:vcard") {
+        parse_vcard(content)
+    } else if lower.starts_with("mailto:") {
+        parse_mailto(&content[7..])
+    } else if lower.starts_with("tel:") {
+        Some(ParsedPayload {
+            kind: "Phone",
+            fields: vec![("Number", content[4..].to_string())],
+        })
+    } else if lower.starts_with("smsto:") {
+        parse_sms(&content[6..])
+    } else if lower.starts_with("geo:") {
+        parse_geo(&content[4..])
+    } else if lower.starts_with("http://") || lower.starts_with("https://") {
+        parse_url(content)
+    } else {
+        None
+    }
+}
+
+/// `T:WPA;S:name;P:secret;H:true;;`, with `\` escaping `;,:"\`
+fn parse_wifi(body: &str) -> Option<ParsedPayload> {
+    let mut fields = Vec::new();
+
+    for part in split_unescaped(body, ';') {
+        let Some((key, value)) = part.split_once(':') else {
+            continue;
+        };
+        let label = match key.to_ascii_uppercase().as_str() {
+            "S" => "SSID",
+            "P" => "Password",
+            "T" => "Auth",
+            "H" => "Hidden",
+            _ => continue,
+        };
+        fields.push((label, unescape(value)));
+    }
+
+    if fields.is_empty() {
+        return None;
+    }
+    Some(ParsedPayload {
+        kind: "WiFi",
+        fields,
+    })
+}
+
+fn parse_vcard(content: &str) -> Option<ParsedPayload> {
+    // folded lines continue with a leading space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.trim_end().to_string()),
+        }
+    }
+
+    let mut fields = Vec::new();
+    for line in &lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        // parameters like TEL;TYPE=CELL don't change what the field is
+        let name = key.split(';').next().unwrap_or(key).to_ascii_uppercase();
+        let label = match name.as_str() {
+            "FN" => "Name",
+            "N" if !fields.iter().any(|(label, _)| *label == "Name") => "Name",
+            "ORG" => "Organization",
+            "TITLE" => "Title",
+            "TEL" => "Phone",
+            "EMAIL" => "Email",
+            "ADR" => "Address",
+            "URL" => "URL",
+            "NOTE" => "Note",
+            _ => continue,
+        };
+
+        let value = match name.as_str() {
+            // structured fields separate their parts with ;
+            "N" | "ADR" => value
+                .split(';')
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => value.to_string(),
+        };
+        fields.push((label, value));
+    }
+
+    Some(ParsedPayload {
+        kind: "Contact",
+        fields,
+    })
+}
+
+/// `to@example.com?subject=hi&body=...`
+fn parse_mailto(body: &str) -> Option<ParsedPayload> {
+    let (to, query) = body.split_once('?').unwrap_or((body, ""));
+    let mut fields = vec![("To", percent_decode(to))];
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let label = match key.to_ascii_lowercase().as_str() {
+            "subject" => "Subject",
+            "body" => "Body",
+            "cc" => "Cc",
+            "bcc" => "Bcc",
+            _ => continue,
+        };
+        fields.push((label, percent_decode(value)));
+    }
+
+    Some(ParsedPayload {
+        kind: "Email",
+        fields,
+    })
+}
+
+/// `number:message`
+fn parse_sms(body: &str) -> Option<ParsedPayload> {
+    let (number, message) = body.split_once(':').unwrap_or((body, ""));
+    let mut fields = vec![("Number", number.to_string())];
+    if !message.is_empty() {
+        fields.push(("Message", message.to_string()));
+    }
+
+    Some(ParsedPayload {
+        kind: "SMS",
+        fields,
+    })
+}
+
+/// `lat,lon[,alt][?q=query]`
+fn parse_geo(body: &str) -> Option<ParsedPayload> {
+    let (coords, query) = body.split_once('?').unwrap_or((body, ""));
+    let mut parts = coords.split(',');
+    let latitude: f64 = parts.next()?.trim().parse().ok()?;
+    let longitude: f64 = parts.next()?.trim().parse().ok()?;
+
+    let mut fields = vec![
+        ("Latitude", latitude.to_string()),
+        ("Longitude", longitude.to_string()),
+    ];
+    if let Some(altitude) = parts.next() {
+        fields.push(("Altitude", altitude.trim().to_string()));
+    }
+    if let Some(place) = query.strip_prefix("q=") {
+        fields.push(("Query", percent_decode(place)));
+    }
+
+    Some(ParsedPayload {
+        kind: "Location",
+        fields,
+    })
+}
+
+fn parse_url(content: &str) -> Option<ParsedPayload> {
+    let after_scheme = &content[content.find("://")? + 3..];
+    let host = after_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or(after_scheme);
+
+    Some(ParsedPayload {
+        kind: "URL",
+        fields: vec![("URL", content.to_string()), ("Host", host.to_string())],
+    })
+}
+
+/// split on `separator` unless it's escaped with a backslash
+fn split_unescaped(text: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+
+    for c in text.chars() {
+        if escaped {
+            current.push('\\');
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            out.extend(chars.next());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// %xx escapes back to bytes, anything malformed is left as is
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).to_string()
+}
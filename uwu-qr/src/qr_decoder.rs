@@ -1,54 +1,932 @@
+use crate::barcode::{
+    self, Adjustment, BarcodeDecoder, CodeContent, DecodedCode, FrameScan, SequencePart, Symbology,
+};
+use crate::image_io;
+use crate::payload::parse_payload;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use colored::Colorize;
-use image::DynamicImage;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage};
 use log::{debug, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-pub fn decode_qr_codes(img: &DynamicImage) -> Result<(), Box<dyn std::error::Error>> {
-    debug!("converting image to grayscale for QR detection");
-    let gray_img = img.to_luma8();
+/// fixed thresholds tried by --reprobe when adaptive binarization fails
+const REPROBE_THRESHOLDS: [u8; 4] = [80, 110, 140, 170];
 
-    let mut prepared_img = rqrr::PreparedImage::prepare(gray_img);
+/// contrast normalization ignores this share of pixels at each end,
+/// so a few specks of pure black or white don't pin the range
+const CONTRAST_CLIP: f64 = 0.01;
 
-    let grids = prepared_img.detect_grids();
+/// exit status when nothing that looks like a code was found
+const EXIT_NO_CODES: u8 = 2;
 
-    if grids.is_empty() {
+/// exit status when codes were found but none of them decoded
+const EXIT_UNDECODABLE: u8 = 3;
+
+/// bytes per row of the binary payload dump
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// room left around a grid when it's cut out for rxing, as a share of
+/// its size, rxing wants some quiet zone to find the finder patterns
+const CROP_MARGIN: f64 = 0.25;
+
+/// extra passes tried in order when the raw image has no grids
+///
+/// rotations and inversion come last, rqrr misses codes that are
+/// turned sideways or printed light on dark
+#[derive(Debug, Clone, Copy)]
+enum Preprocess {
+    Otsu,
+    Upscale,
+    Contrast,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Invert,
+}
+
+const PREPROCESS_PASSES: [Preprocess; 7] = [
+    Preprocess::Otsu,
+    Preprocess::Upscale,
+    Preprocess::Contrast,
+    Preprocess::Rotate90,
+    Preprocess::Rotate180,
+    Preprocess::Rotate270,
+    Preprocess::Invert,
+];
+
+impl Preprocess {
+    /// map a point found in the processed image back onto the source,
+    /// `source_size` being the source's width and height
+    fn to_source(self, (x, y): (i32, i32), (width, height): (u32, u32)) -> (i32, i32) {
+        let (width, height) = (width as i32, height as i32);
+        match self {
+            Preprocess::Upscale => (x / 2, y / 2),
+            Preprocess::Rotate90 => (y, height - 1 - x),
+            Preprocess::Rotate180 => (width - 1 - x, height - 1 - y),
+            Preprocess::Rotate270 => (width - 1 - y, x),
+            Preprocess::Otsu | Preprocess::Contrast | Preprocess::Invert => (x, y),
+        }
+    }
+
+    /// how the source was oriented, for the transforms that tell us
+    fn orientation(self) -> Option<&'static str> {
+        match self {
+            Preprocess::Rotate90 => Some("rotated 90° counterclockwise"),
+            Preprocess::Rotate180 => Some("upside down"),
+            Preprocess::Rotate270 => Some("rotated 90° clockwise"),
+            Preprocess::Invert => Some("inverted, light on dark"),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Preprocess::Otsu => "otsu binarization",
+            Preprocess::Upscale => "2x upscale",
+            Preprocess::Contrast => "contrast normalization",
+            Preprocess::Rotate90 => "rotating 90° clockwise",
+            Preprocess::Rotate180 => "rotating 180°",
+            Preprocess::Rotate270 => "rotating 270° clockwise",
+            Preprocess::Invert => "inverting luminance",
+        }
+    }
+
+    fn apply(self, gray_img: &GrayImage) -> GrayImage {
+        match self {
+            Preprocess::Otsu => binarize(gray_img, otsu_threshold(gray_img)),
+            Preprocess::Upscale => imageops::resize(
+                gray_img,
+                gray_img.width() * 2,
+                gray_img.height() * 2,
+                FilterType::Triangle,
+            ),
+            Preprocess::Contrast => normalize_contrast(gray_img),
+            Preprocess::Rotate90 => imageops::rotate90(gray_img),
+            Preprocess::Rotate180 => imageops::rotate180(gray_img),
+            Preprocess::Rotate270 => imageops::rotate270(gray_img),
+            Preprocess::Invert => {
+                let mut inverted = gray_img.clone();
+                imageops::invert(&mut inverted);
+                inverted
+            }
+        }
+    }
+}
+
+/// how decoded codes are printed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// one decoded code in --format json
+#[derive(Debug, Serialize)]
+pub struct QrJson {
+    index: usize,
+    /// which frame of a multi-frame image it came from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame: Option<usize>,
+    symbology: &'static str,
+    /// qr only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ecc_level: Option<&'static str>,
+    /// the payload when it's valid utf-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    /// the payload bytes otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_base64: Option<String>,
+    /// where this code goes when it's one part of a structured append message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    part: Option<PartJson>,
+    /// for a reassembled message, the indexes of the parts it was put together from
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reassembled_from: Vec<usize>,
+    /// the grid's four corners as [x, y]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bounds: Vec<[i32; 2]>,
+}
+
+#[derive(Debug, Serialize)]
+struct PartJson {
+    /// 1 based
+    position: u8,
+    total: u8,
+    parity: u8,
+}
+
+impl QrJson {
+    /// `content`, or `content_base64` when the bytes aren't utf-8
+    fn new(index: usize, symbology: &'static str, bytes: &[u8]) -> Self {
+        let text = String::from_utf8(bytes.to_vec()).ok();
+        QrJson {
+            index,
+            frame: None,
+            symbology,
+            version: None,
+            ecc_level: None,
+            content_base64: text.is_none().then(|| BASE64.encode(bytes)),
+            content: text,
+            part: None,
+            reassembled_from: Vec::new(),
+            bounds: Vec::new(),
+        }
+    }
+}
+
+/// what goes between payloads in --raw mode
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum)]
+pub enum Separator {
+    #[default]
+    Newline,
+    Nul,
+}
+
+impl Separator {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Separator::Newline => b"\n",
+            Separator::Nul => b"\0",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DecodeOptions {
+    // report every grid's outcome, including failures
+    pub report_all: bool,
+    // retry at fixed binarization thresholds if nothing decodes
+    pub reprobe: bool,
+    // print only the decoded payloads, nothing decorative
+    pub raw: bool,
+    // write each payload to this file, numbered if there are several
+    pub output: Option<PathBuf>,
+    // between payloads in raw mode
+    pub separator: Separator,
+    // text for people, json for scripts
+    pub format: OutputFormat,
+    // break known payloads like WIFI: or vcards into fields
+    pub parse: bool,
+    // draw the detected grids over the input and save it here
+    pub debug_image: Option<PathBuf>,
+    // which codes to look for, qr when empty
+    pub symbologies: Vec<Symbology>,
+}
+
+impl DecodeOptions {
+    /// whether the 「...」 console output should be printed at all
+    pub fn decorated(&self) -> bool {
+        !self.raw && self.format == OutputFormat::Text
+    }
+}
+
+/// what one input held
+pub struct DecodeOutcome {
+    pub detected: usize,
+    pub payloads: Vec<Vec<u8>>,
+    /// every code for --format json, printed by the caller so a batch
+    /// can put all its files in one document
+    pub json: Vec<QrJson>,
+}
+
+/// 0 if anything decoded, 2 if nothing was found, 3 if codes were
+/// found but none decoded, so scripts can tell them apart
+pub fn exit_code(detected: usize, decoded: usize) -> ExitCode {
+    match (detected, decoded) {
+        (_, 1..) => ExitCode::SUCCESS,
+        (0, _) => ExitCode::from(EXIT_NO_CODES),
+        _ => ExitCode::from(EXIT_UNDECODABLE),
+    }
+}
+
+/// everything decoded across the frames of one input
+#[derive(Default)]
+struct ScanResults {
+    grids_found: usize,
+    failures: Vec<String>,
+    payloads: Vec<Vec<u8>>,
+    json_results: Vec<QrJson>,
+    // structured append parts, kept out of payloads until they're reassembled
+    parts: Vec<FoundPart>,
+    // every grid's corners in source pixels, by frame, and whether it decoded
+    boxes: Vec<DetectedBox>,
+}
+
+/// one structured append part and the index it was reported under
+struct FoundPart {
+    index: usize,
+    part: SequencePart,
+    bytes: Vec<u8>,
+}
+
+/// where a grid was found, for --debug-image
+pub struct DetectedBox {
+    pub frame: usize,
+    pub corners: [(i32, i32); 4],
+    pub decoded: bool,
+}
+
+/// find and decode all qr codes in every frame of an image
+///
+/// with report_all every detected grid gets an explicit outcome,
+/// including the decode error and where it was found. returns how
+/// many codes were detected and the payloads that decoded
+pub fn decode_qr_codes(
+    frames: &[DynamicImage],
+    options: &DecodeOptions,
+) -> Result<DecodeOutcome, Box<dyn std::error::Error>> {
+    let report_all = options.report_all && options.decorated();
+    let decorated = options.decorated();
+
+    let decoders = barcode::decoders(&options.symbologies, options.reprobe);
+    let mut results = ScanResults::default();
+    for (frame_index, img) in frames.iter().enumerate() {
+        let frame = (frames.len() > 1).then_some(frame_index + 1);
+        debug!("converting image to grayscale for detection");
+        let gray_img = img.to_luma8();
+        for decoder in &decoders {
+            scan_with_decoder(decoder.as_ref(), &gray_img, frame, options, &mut results);
+        }
+    }
+    reassemble_sequences(&mut results, options);
+
+    if results.grids_found == 0 {
         warn!("no QR codes found in image");
-        return Ok(());
     }
 
-    println!(
-        "{} {}",
-        "「found」".green().bold(),
-        format!("{} qr code(s)", grids.len()).cyan()
-    );
-    println!();
+    if frames.len() > 1 && decorated {
+        println!(
+            "{} {} code(s) across {} frames",
+            "「frames」".cyan().bold(),
+            results.payloads.len().to_string().yellow(),
+            frames.len().to_string().yellow()
+        );
+    }
+
+    if report_all {
+        let failures = &results.failures;
+        let mut summary = format!(
+            "{} grid(s) detected, {} decoded",
+            results.grids_found,
+            results.grids_found - failures.len()
+        );
+        if !failures.is_empty() {
+            summary.push_str(&format!(
+                ", {} failed with {}",
+                failures.len(),
+                failures.join(", ")
+            ));
+        }
+        println!("{} {}", "「summary」".cyan().bold(), summary);
+    }
+
+    let payloads = &results.payloads;
+    if options.raw {
+        let mut stdout = io::stdout().lock();
+        for (i, payload) in payloads.iter().enumerate() {
+            if i > 0 {
+                stdout.write_all(options.separator.as_bytes())?;
+            }
+            stdout.write_all(payload)?;
+        }
+        if matches!(options.separator, Separator::Newline) && !payloads.is_empty() {
+            stdout.write_all(b"\n")?;
+        }
+        stdout.flush()?;
+    }
+
+    if let Some(output) = &options.output {
+        write_payloads(payloads, output, decorated)?;
+    }
+
+    if let Some(debug_path) = &options.debug_image {
+        for path in image_io::write_debug_images(frames, &results.boxes, debug_path)? {
+            if decorated {
+                println!(
+                    "{} {}",
+                    "「debug image」".green().bold(),
+                    path.display().to_string().cyan()
+                );
+            }
+        }
+    }
+
+    Ok(DecodeOutcome {
+        detected: results.grids_found,
+        payloads: results.payloads,
+        json: results.json_results,
+    })
+}
+
+/// the rqrr pipeline, with reprobing and the preprocessing passes
+pub struct QrDecoder {
+    // retry at fixed binarization thresholds if nothing decodes
+    pub reprobe: bool,
+}
+
+impl BarcodeDecoder for QrDecoder {
+    fn symbology(&self) -> Symbology {
+        Symbology::Qr
+    }
+
+    fn decode(&self, gray_img: &GrayImage) -> FrameScan {
+        let mut scan = FrameScan::default();
+        // the image the grids were found in, which is what bounds refer to
+        let mut scan_img = gray_img.clone();
+
+        let mut prepared_img = rqrr::PreparedImage::prepare(gray_img.clone());
+        let mut grids = prepared_img.detect_grids();
+
+        let mut reprobed_img;
+        if self.reprobe && !grids.iter().any(|grid| grid.decode().is_ok()) {
+            debug!("adaptive binarization found nothing decodable, reprobing");
+            if let Some(threshold) = find_working_threshold(gray_img) {
+                scan.adjustments.push(Adjustment::Threshold(threshold));
+                scan_img = binarize(gray_img, threshold);
+                reprobed_img = rqrr::PreparedImage::prepare(scan_img.clone());
+                grids = reprobed_img.detect_grids();
+            }
+        }
+
+        let mut preprocessed_img;
+        let mut transform = None;
+        if grids.is_empty() {
+            debug!("no grids in the raw image, trying preprocessing passes");
+            if let Some((pass, processed)) = find_working_preprocess(gray_img) {
+                scan.adjustments.push(Adjustment::Preprocess {
+                    pass: pass.name(),
+                    orientation: pass.orientation(),
+                });
+                scan_img = processed;
+                preprocessed_img = rqrr::PreparedImage::prepare(scan_img.clone());
+                grids = preprocessed_img.detect_grids();
+                transform = Some(pass);
+            }
+        }
+
+        let source_size = gray_img.dimensions();
+        for grid in &grids {
+            let corners = grid.bounds.map(|p| match transform {
+                Some(pass) => pass.to_source((p.x, p.y), source_size),
+                None => (p.x, p.y),
+            });
+
+            // decode_to keeps the raw bytes, decode would force them into a String
+            let mut content = Vec::new();
+            let result = match grid.decode_to(&mut content) {
+                Ok(meta) => Ok(CodeContent {
+                    bytes: content,
+                    version: Some(meta.version.0),
+                    ecc_level: Some(ecc_level_name(meta.ecc_level)),
+                    part: None,
+                }),
+                // rqrr stops at the structured append header, rxing reads it
+                Err(rqrr::DeQRError::UnknownDataType) => read_structured_append(&scan_img, grid)
+                    .ok_or_else(|| format!("{:?}", rqrr::DeQRError::UnknownDataType)),
+                Err(e) => Err(format!("{:?}", e)),
+            };
+            scan.codes.push(DecodedCode { corners, result });
+        }
+
+        scan
+    }
+}
+
+/// read a grid rqrr couldn't as a structured append part
+///
+/// the grid is cut out of the image it was found in and handed to
+/// rxing, which parses the sequence header rqrr rejects
+fn read_structured_append<G: rqrr::BitGrid>(
+    scan_img: &GrayImage,
+    grid: &rqrr::Grid<G>,
+) -> Option<CodeContent> {
+    let xs = grid.bounds.map(|p| p.x);
+    let ys = grid.bounds.map(|p| p.y);
+    let (min_x, max_x) = (*xs.iter().min()?, *xs.iter().max()?);
+    let (min_y, max_y) = (*ys.iter().min()?, *ys.iter().max()?);
+    let margin = ((max_x - min_x).max(max_y - min_y) as f64 * CROP_MARGIN) as i32;
+
+    let left = (min_x - margin).max(0) as u32;
+    let top = (min_y - margin).max(0) as u32;
+    let right = ((max_x + margin).max(0) as u32).min(scan_img.width());
+    let bottom = ((max_y + margin).max(0) as u32).min(scan_img.height());
+    if right <= left || bottom <= top {
+        return None;
+    }
 
-    for (i, grid) in grids.iter().enumerate() {
-        match grid.decode() {
-            Ok((meta, content)) => {
+    let crop = imageops::crop_imm(scan_img, left, top, right - left, bottom - top).to_image();
+    let mut content = barcode::read_qr_part(&crop)?;
+    debug!("read structured append part {:?}", content.part);
+
+    if let Ok((meta, _)) = grid.get_raw_data() {
+        content.version = Some(meta.version.0);
+        content.ecc_level = Some(ecc_level_name(meta.ecc_level));
+    }
+    Some(content)
+}
+
+/// run one decoder over a frame, adding what it finds to `results`
+///
+/// `frame` is only set for multi-frame input, so single images keep
+/// their output unchanged
+fn scan_with_decoder(
+    decoder: &dyn BarcodeDecoder,
+    gray_img: &GrayImage,
+    frame: Option<usize>,
+    options: &DecodeOptions,
+    results: &mut ScanResults,
+) {
+    let report_all = options.report_all && options.decorated();
+    let decorated = options.decorated();
+    let symbology = decoder.symbology();
+    let frame_label = frame
+        .map(|frame| format!(" (frame {})", frame))
+        .unwrap_or_default();
+
+    let scan = decoder.decode(gray_img);
+    if decorated {
+        for adjustment in &scan.adjustments {
+            print_adjustment(adjustment, &frame_label);
+        }
+    }
+
+    if scan.codes.is_empty() {
+        debug!("no {} codes in frame {:?}", symbology.name(), frame);
+        return;
+    }
+
+    if decorated {
+        println!(
+            "{} {}{}",
+            "「found」".green().bold(),
+            format!(
+                "{} {} code(s)",
+                scan.codes.len(),
+                symbology.name().to_lowercase()
+            )
+            .cyan(),
+            frame_label
+        );
+        println!();
+    }
+
+    for code in scan.codes {
+        let index = results.grids_found + 1;
+        results.grids_found += 1;
+
+        let corner_list: Vec<String> = code
+            .corners
+            .iter()
+            .map(|(x, y)| format!("({}, {})", x, y))
+            .collect();
+        let decoded = code.result.is_ok();
+
+        match code.result {
+            Ok(content) => {
                 debug!(
-                    "decoded QR code {}: {:?} version {:?}",
-                    i + 1,
-                    meta.ecc_level,
-                    meta.version
+                    "decoded {} code {}: {:?} version {:?}",
+                    symbology.name(),
+                    index,
+                    content.ecc_level,
+                    content.version
+                );
+
+                if decorated {
+                    println!(
+                        "{} {}{}",
+                        format!("「{}」", symbology.heading()).cyan().bold(),
+                        index.to_string().yellow(),
+                        frame_label
+                    );
+                    if let Some(version) = content.version {
+                        println!("  {}: {}", "Version".green(), version);
+                    }
+                    if let Some(ecc_level) = content.ecc_level {
+                        println!("  {}: {}", "Error Correction".green(), ecc_level);
+                    }
+                    if let Some(part) = content.part {
+                        println!(
+                            "  {}: {} of {}",
+                            "Part".green(),
+                            part.position + 1,
+                            part.total
+                        );
+                    }
+                    println!("  {}: {}", "Bounds".green(), corner_list.join(" "));
+                    print_content(&content.bytes, options.parse);
+                }
+
+                if options.format == OutputFormat::Json {
+                    results.json_results.push(QrJson {
+                        frame,
+                        version: content.version,
+                        ecc_level: content.ecc_level,
+                        part: content.part.map(|part| PartJson {
+                            position: part.position + 1,
+                            total: part.total,
+                            parity: part.parity,
+                        }),
+                        bounds: code.corners.iter().map(|&(x, y)| [x, y]).collect(),
+                        ..QrJson::new(index, symbology.name(), &content.bytes)
+                    });
+                }
+
+                match content.part {
+                    Some(part) => results.parts.push(FoundPart {
+                        index,
+                        part,
+                        bytes: content.bytes,
+                    }),
+                    None => results.payloads.push(content.bytes),
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "failed to decode {} code {}: {}",
+                    symbology.name(),
+                    index,
+                    e
                 );
 
+                if report_all {
+                    println!(
+                        "{} {}{}",
+                        "「decode failed」".red().bold(),
+                        index.to_string().yellow(),
+                        frame_label
+                    );
+                    println!("  {}: {}", "Error".green(), e);
+                    println!("  {}: {}", "Bounds".green(), corner_list.join(" "));
+                    println!();
+                }
+                results.failures.push(e);
+            }
+        }
+
+        results.boxes.push(DetectedBox {
+            frame: frame.unwrap_or(1) - 1,
+            corners: code.corners,
+            decoded,
+        });
+    }
+}
+
+/// say what a decoder had to do to the frame to find its codes
+fn print_adjustment(adjustment: &Adjustment, frame_label: &str) {
+    match adjustment {
+        Adjustment::Threshold(threshold) => println!(
+            "{} decoded after binarizing at threshold {}{}",
+            "「reprobe」".magenta().bold(),
+            threshold.to_string().yellow(),
+            frame_label
+        ),
+        Adjustment::Preprocess { pass, orientation } => {
+            println!(
+                "{} found grids after {}{}",
+                "「preprocess」".magenta().bold(),
+                pass.yellow(),
+                frame_label
+            );
+            if let Some(orientation) = orientation {
+                println!("  {}: {}", "Original".green(), orientation);
+            }
+        }
+    }
+    println!();
+}
+
+/// put structured append parts back together into their messages
+///
+/// parts are grouped by parity and count, so codes from two different
+/// messages in one image stay apart. a complete message becomes one
+/// payload, an incomplete one leaves its parts as separate payloads
+fn reassemble_sequences(results: &mut ScanResults, options: &DecodeOptions) {
+    let decorated = options.decorated();
+    // reassembled messages are numbered on from the codes
+    let mut next_index = results.grids_found;
+    let mut sequences: BTreeMap<(u8, u8), Vec<FoundPart>> = BTreeMap::new();
+    for found in std::mem::take(&mut results.parts) {
+        sequences
+            .entry((found.part.parity, found.part.total))
+            .or_default()
+            .push(found);
+    }
+
+    for ((parity, total), mut parts) in sequences {
+        parts.sort_by_key(|found| found.part.position);
+        // the same part can turn up in several frames
+        parts.dedup_by_key(|found| found.part.position);
+
+        let missing: Vec<String> = (0..total)
+            .filter(|position| !parts.iter().any(|found| found.part.position == *position))
+            .map(|position| (position + 1).to_string())
+            .collect();
+
+        if !missing.is_empty() {
+            if decorated {
                 println!(
-                    "{} {}",
-                    "「qr code」".cyan().bold(),
-                    (i + 1).to_string().yellow()
+                    "{} {} of {} part(s), incomplete, missing {}",
+                    "「structured append」".yellow().bold(),
+                    parts.len().to_string().yellow(),
+                    total.to_string().yellow(),
+                    missing.join(", ")
                 );
-                println!("  {}: {:?}", "Version".green(), meta.version);
-                println!("  {}: {:?}", "Error Correction".green(), meta.ecc_level);
-                println!("  {}:", "Content".green());
-                println!();
-                println!("{}", content);
                 println!();
             }
-            Err(e) => {
-                warn!("failed to decode QR code {}: {:?}", i + 1, e);
+            results
+                .payloads
+                .extend(parts.into_iter().map(|found| found.bytes));
+            continue;
+        }
+
+        let message: Vec<u8> = parts.iter().flat_map(|found| found.bytes.clone()).collect();
+        let parity_ok = message.iter().fold(0, |acc, byte| acc ^ byte) == parity;
+        if !parity_ok {
+            warn!(
+                "reassembled message doesn't match its parity {:#04x}",
+                parity
+            );
+        }
+
+        if decorated {
+            println!(
+                "{} {} of {} part(s), complete",
+                "「structured append」".green().bold(),
+                parts.len().to_string().yellow(),
+                total.to_string().yellow()
+            );
+            if !parity_ok {
+                println!("  {}: {}", "Parity".green(), "doesn't match".yellow());
             }
+            print_content(&message, options.parse);
+        }
+
+        next_index += 1;
+        if options.format == OutputFormat::Json {
+            results.json_results.push(QrJson {
+                reassembled_from: parts.iter().map(|found| found.index).collect(),
+                ..QrJson::new(next_index, Symbology::Qr.name(), &message)
+            });
+        }
+        results.payloads.push(message);
+    }
+}
+
+/// one payload goes to `output` as is, several get numbered copies of
+/// its name, `codes.txt` becoming `codes-1.txt`, `codes-2.txt` and so on
+fn write_payloads(
+    payloads: &[Vec<u8>],
+    output: &Path,
+    decorated: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let paths: Vec<PathBuf> = if payloads.len() == 1 {
+        vec![output.to_path_buf()]
+    } else {
+        let stem = output
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "qr".to_string());
+        let extension = output
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        (1..=payloads.len())
+            .map(|n| output.with_file_name(format!("{}-{}{}", stem, n, extension)))
+            .collect()
+    };
+
+    for (payload, path) in payloads.iter().zip(&paths) {
+        fs::write(path, payload)?;
+        debug!("wrote {} bytes to {}", payload.len(), path.display());
+        if decorated {
+            println!(
+                "{} {}",
+                "「saved」".green().bold(),
+                path.display().to_string().cyan()
+            );
         }
     }
 
     Ok(())
 }
+
+/// the payload as fields when --parse knows its scheme, raw otherwise
+///
+/// byte mode codes can carry anything, payloads that aren't utf-8
+/// get a hex dump rather than a screen of replacement characters
+fn print_content(content: &[u8], parse: bool) {
+    println!("  {}: {} bytes", "Length".green(), content.len());
+
+    let Ok(text) = std::str::from_utf8(content) else {
+        println!("  {}: {}", "Content".green(), "binary, not utf-8".yellow());
+        println!();
+        print_hex_dump(content);
+        println!();
+        return;
+    };
+
+    if let Some(parsed) = parse.then(|| parse_payload(text)).flatten() {
+        println!("  {}: {}", "Type".green(), parsed.kind.yellow());
+        for (label, value) in &parsed.fields {
+            println!("  {}: {}", label.green(), value);
+        }
+        println!();
+        return;
+    }
+
+    println!("  {}:", "Content".green());
+    println!();
+    println!("{}", text);
+    println!();
+}
+
+/// offset, hex and ascii columns, 16 bytes a row like xxd
+fn print_hex_dump(content: &[u8]) {
+    for (row, chunk) in content.chunks(HEX_DUMP_WIDTH).enumerate() {
+        let hex: Vec<String> = chunk
+            .chunks(2)
+            .map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect())
+            .collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!(
+            "{}: {:<width$}  {}",
+            format!("{:08x}", row * HEX_DUMP_WIDTH).dimmed(),
+            hex.join(" "),
+            ascii,
+            width = HEX_DUMP_WIDTH / 2 * 5 - 1
+        );
+    }
+}
+
+/// the ecc level letter, rqrr hands back the two format bits as is
+fn ecc_level_name(ecc_level: u16) -> &'static str {
+    match ecc_level {
+        0 => "M",
+        1 => "L",
+        2 => "H",
+        _ => "Q",
+    }
+}
+
+fn binarize(gray_img: &GrayImage, threshold: u8) -> GrayImage {
+    let mut binarized = gray_img.clone();
+    for pixel in binarized.pixels_mut() {
+        pixel.0[0] = if pixel.0[0] < threshold { 0 } else { 255 };
+    }
+    binarized
+}
+
+/// run each preprocessing pass until one turns up grids
+fn find_working_preprocess(gray_img: &GrayImage) -> Option<(Preprocess, GrayImage)> {
+    for pass in PREPROCESS_PASSES {
+        let processed = pass.apply(gray_img);
+        let mut prepared_img = rqrr::PreparedImage::prepare(processed.clone());
+        let grids = prepared_img.detect_grids();
+        debug!("{} found {} grid(s)", pass.name(), grids.len());
+
+        if !grids.is_empty() {
+            return Some((pass, processed));
+        }
+    }
+
+    debug!("no preprocessing pass found any grids");
+    None
+}
+
+/// threshold that best splits the histogram into two classes
+fn otsu_threshold(gray_img: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in gray_img.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+
+    let total = gray_img.width() as f64 * gray_img.height() as f64;
+    let total_sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &count)| value as f64 * count as f64)
+        .sum();
+
+    let mut best = (0, 0.0);
+    let mut background_weight = 0.0;
+    let mut background_sum = 0.0;
+    for (value, &count) in histogram.iter().enumerate() {
+        background_weight += count as f64;
+        background_sum += value as f64 * count as f64;
+        let foreground_weight = total - background_weight;
+        if background_weight == 0.0 || foreground_weight == 0.0 {
+            continue;
+        }
+
+        let background_mean = background_sum / background_weight;
+        let foreground_mean = (total_sum - background_sum) / foreground_weight;
+        let between =
+            background_weight * foreground_weight * (background_mean - foreground_mean).powi(2);
+        if between > best.1 {
+            best = (value, between);
+        }
+    }
+
+    debug!("otsu threshold {}", best.0);
+    // binarize puts values below the threshold in the dark class
+    (best.0 + 1).min(255) as u8
+}
+
+/// stretch the luminance range out to the full 0-255
+fn normalize_contrast(gray_img: &GrayImage) -> GrayImage {
+    let mut values: Vec<u8> = gray_img.pixels().map(|pixel| pixel.0[0]).collect();
+    values.sort_unstable();
+    if values.is_empty() {
+        return gray_img.clone();
+    }
+
+    let clip = (values.len() as f64 * CONTRAST_CLIP) as usize;
+    let low = values[clip] as f64;
+    let high = values[values.len() - 1 - clip] as f64;
+    if high <= low {
+        return gray_img.clone();
+    }
+
+    let mut normalized = gray_img.clone();
+    for pixel in normalized.pixels_mut() {
+        let stretched = (pixel.0[0] as f64 - low) / (high - low) * 255.0;
+        pixel.0[0] = stretched.clamp(0.0, 255.0) as u8;
+    }
+    normalized
+}
+
+/// binarize at each fixed threshold until something decodes
+fn find_working_threshold(gray_img: &GrayImage) -> Option<u8> {
+    for threshold in REPROBE_THRESHOLDS {
+        let mut prepared_img = rqrr::PreparedImage::prepare(binarize(gray_img, threshold));
+        let grids = prepared_img.detect_grids();
+        debug!("threshold {} found {} grid(s)", threshold, grids.len());
+
+        if grids.iter().any(|grid| grid.decode().is_ok()) {
+            return Some(threshold);
+        }
+    }
+
+    warn!("no threshold produced a decodable QR code");
+    None
+}